@@ -2,7 +2,7 @@ use crate::create_runtime;
 use buffdb::backend::BlobBackend;
 use buffdb::client::blob::BlobClient;
 use buffdb::interop::IntoTonicStatus;
-use buffdb::proto::blob::{DeleteRequest, GetRequest, StoreRequest, UpdateRequest};
+use buffdb::proto::blob::{Compression, DeleteRequest, GetRequest, StoreRequest, UpdateRequest};
 use buffdb::transitive::Transitive;
 use buffdb::{backend, transitive, Location};
 use criterion::measurement::Measurement;
@@ -55,6 +55,10 @@ where
     let requests: Vec<_> = iter::repeat_with(|| StoreRequest {
         bytes: generate_bytes(),
         metadata: generate_metadata(),
+        attributes: None,
+        compression: Compression::None.into(),
+        id: None,
+        idempotency_key: None,
     })
     .take(INSERT_COUNT)
     .collect();
@@ -70,7 +74,7 @@ where
     }
 
     ids.into_iter()
-        .zip(requests.into_iter())
+        .zip(requests)
         .map(|(id, request)| (id, request.bytes, request.metadata))
         .choose_multiple(&mut thread_rng(), RETURN_COUNT)
 }
@@ -86,6 +90,12 @@ where
             StoreStream: Send,
             UpdateStream: Send,
             DeleteStream: Send,
+            UploadStream: Send,
+            DownloadStream: Send,
+            ListStream: Send,
+            CopyStream: Send,
+            AppendStream: Send,
+            VerifyStream: Send,
         > + 'static,
 {
     let mut client = transitive::blob_client::<_, Backend>(Location::InMemory)
@@ -109,6 +119,10 @@ where
                 let requests: Vec<_> = iter::repeat_with(|| StoreRequest {
                     bytes: generate_bytes(),
                     metadata: generate_metadata(),
+                    attributes: None,
+                    compression: Compression::None.into(),
+                    id: None,
+                    idempotency_key: None,
                 })
                 .take(INSERT_QUERIES_PER_BATCH)
                 .collect();
@@ -130,7 +144,11 @@ where
         runtime.block_on(create_blob_client::<backend::Sqlite, GET_QUERIES_PER_BATCH>());
     let ids: Vec<_> = contents
         .into_iter()
-        .map(|(id, _, _)| GetRequest { id })
+        .map(|(id, _, _)| GetRequest {
+            id,
+            offset: None,
+            length: None,
+        })
         .collect();
 
     c.bench_function("sqlite_blob_get", |b| {
@@ -164,6 +182,9 @@ where
                         bytes: Some(generate_bytes()),
                         should_update_metadata: false,
                         metadata: None,
+                        attributes: None,
+                        remove_attributes: Vec::new(),
+                        compression: Compression::None.into(),
                     })
                     .collect();
                 (stream::iter(requests), client.clone())
@@ -196,6 +217,9 @@ where
                         bytes: None,
                         should_update_metadata: true,
                         metadata: generate_metadata(),
+                        attributes: None,
+                        remove_attributes: Vec::new(),
+                        compression: Compression::None.into(),
                     })
                     .collect();
                 (stream::iter(requests), client.clone())
@@ -228,6 +252,9 @@ where
                         bytes: Some(generate_bytes()),
                         should_update_metadata: true,
                         metadata: generate_metadata(),
+                        attributes: None,
+                        remove_attributes: Vec::new(),
+                        compression: Compression::None.into(),
                     })
                     .collect();
                 (stream::iter(requests), client.clone())
@@ -3,6 +3,8 @@ use buffdb::backend::{DatabaseBackend, KvBackend};
 use buffdb::client::kv::KvClient;
 use buffdb::client::query::QueryClient;
 use buffdb::interop::IntoTonicStatus;
+use buffdb::prost_types::value::Kind;
+use buffdb::prost_types::Value;
 use buffdb::proto::kv::{DeleteRequest, GetRequest, SetRequest};
 use buffdb::proto::query::{RawQuery, TargetStore};
 use buffdb::queryable::Queryable;
@@ -13,6 +15,7 @@ use criterion::{criterion_group, BatchSize, Criterion};
 use futures::stream;
 use rand::distributions::{Alphanumeric, DistString};
 use rand::prelude::*;
+use std::collections::HashMap;
 use std::iter;
 
 const INSERT_QUERIES_PER_BATCH: usize = 1_000;
@@ -33,6 +36,13 @@ fn generate_value() -> String {
     Alphanumeric.sample_string(&mut rng, len)
 }
 
+/// A query parameter holding a string.
+fn param(value: &str) -> Value {
+    Value {
+        kind: Some(Kind::StringValue(value.to_owned())),
+    }
+}
+
 async fn create_query_client<Backend, const RETURN_COUNT: usize>() -> (
     Transitive<QueryClient<tonic::transport::Channel>>,
     Vec<(String, String)>,
@@ -51,6 +61,8 @@ where
         .execute(stream::iter([RawQuery {
             query: "CREATE TABLE IF NOT EXISTS kv (key TEXT PRIMARY KEY, value TEXT)".to_owned(),
             target: TargetStore::Kv as _,
+            params: Vec::new(),
+            named_params: HashMap::new(),
         }]))
         .await
         .unwrap();
@@ -68,6 +80,12 @@ where
             GetStream: Send,
             SetStream: Send,
             DeleteStream: Send,
+            ScanStream: Send,
+            CompareAndSwapStream: Send,
+            TransactionStream: Send,
+            WatchStream: Send,
+            IncrementStream: Send,
+            HistoryStream: Send,
         > + 'static,
 {
     let mut client = transitive::kv_client::<_, Backend>(Location::InMemory)
@@ -100,8 +118,10 @@ where
         let value = generate_value();
         (
             RawQuery {
-                query: format!("INSERT INTO kv (key, value) VALUES ('{key}', '{value}')"),
+                query: "INSERT INTO kv (key, value) VALUES (?, ?)".to_owned(),
                 target: TargetStore::Kv as _,
+                params: vec![param(&key), param(&value)],
+                named_params: HashMap::new(),
             },
             (key, value),
         )
@@ -140,7 +160,8 @@ where
 
     let requests: Vec<_> = iter::repeat_with(|| SetRequest {
         key: generate_key(),
-        value: generate_value(),
+        value: generate_value().into_bytes(),
+        expiration: None,
     })
     .take(INSERT_COUNT)
     .collect();
@@ -149,7 +170,8 @@ where
 
     requests
         .into_iter()
-        .map(|req| (req.key, req.value))
+        // Values are generated from alphanumeric characters, so they are always valid UTF-8.
+        .map(|req| (req.key, String::from_utf8(req.value).unwrap()))
         .choose_multiple(&mut thread_rng(), RETURN_COUNT)
 }
 
@@ -185,10 +207,10 @@ where
                     let key = generate_key();
                     let value = generate_value();
                     RawQuery {
-                        // No risk of SQL injection here as the values are known to be alphanumeric.
-                        // Even if they weren't, it's a transitive in-memory database.
-                        query: format!("INSERT INTO kv (key, value) VALUES ('{key}', '{value}')"),
+                        query: "INSERT INTO kv (key, value) VALUES (?, ?)".to_owned(),
                         target: TargetStore::Kv as _,
+                        params: vec![param(&key), param(&value)],
+                        named_params: HashMap::new(),
                     }
                 })
                 .take(INSERT_QUERIES_PER_BATCH)
@@ -214,7 +236,8 @@ where
             || {
                 let requests: Vec<_> = iter::repeat_with(|| SetRequest {
                     key: generate_key(),
-                    value: generate_value(),
+                    value: generate_value().into_bytes(),
+                    expiration: None,
                 })
                 .take(INSERT_QUERIES_PER_BATCH)
                 .collect();
@@ -238,8 +261,10 @@ where
     let queries = kv_pairs
         .into_iter()
         .map(|(key, _)| RawQuery {
-            query: format!("SELECT (key, value) FROM kv WHERE key = '{key}'"),
+            query: "SELECT (key, value) FROM kv WHERE key = ?".to_owned(),
             target: TargetStore::Kv as _,
+            params: vec![param(&key)],
+            named_params: HashMap::new(),
         })
         .collect::<Vec<_>>();
 
@@ -263,7 +288,11 @@ where
 
     let keys = kv_pairs
         .into_iter()
-        .map(|(key, _)| GetRequest { key })
+        .map(|(key, _)| GetRequest {
+            key,
+            allow_missing: false,
+            version: None,
+        })
         .collect::<Vec<_>>();
 
     c.bench_function("sqlite_kv_get", |b| {
@@ -289,8 +318,10 @@ where
     let queries = kv_pairs
         .iter()
         .map(|(key, _)| RawQuery {
-            query: format!("DELETE FROM kv WHERE key = '{key}'"),
+            query: "DELETE FROM kv WHERE key = ?".to_owned(),
             target: TargetStore::Kv as _,
+            params: vec![param(key)],
+            named_params: HashMap::new(),
         })
         .collect::<Vec<_>>();
 
@@ -302,8 +333,10 @@ where
                 let mut insert_queries = vec![];
                 for (key, value) in &kv_pairs {
                     insert_queries.push(RawQuery {
-                        query: format!("INSERT INTO kv (key, value) VALUES ('{key}', '{value}')"),
+                        query: "INSERT INTO kv (key, value) VALUES (?, ?)".to_owned(),
                         target: TargetStore::Kv as _,
+                        params: vec![param(key), param(value)],
+                        named_params: HashMap::new(),
                     });
                 }
                 runtime
@@ -341,7 +374,11 @@ where
                 // iterations after the first.
                 let mut insert_queries = vec![];
                 for (key, value) in kv_pairs.iter().cloned() {
-                    insert_queries.push(SetRequest { key, value });
+                    insert_queries.push(SetRequest {
+                        key,
+                        value: value.into_bytes(),
+                        expiration: None,
+                    });
                 }
                 runtime.block_on(async { client.set(stream::iter(insert_queries)).await.unwrap() });
 
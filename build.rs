@@ -10,6 +10,9 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 "proto/kv.proto",
                 "proto/query.proto",
                 "proto/google/protobuf/any.proto",
+                "proto/google/protobuf/duration.proto",
+                "proto/google/protobuf/struct.proto",
+                "proto/google/protobuf/timestamp.proto",
                 "proto/google/protobuf/wrappers.proto",
             ],
             &["proto"],
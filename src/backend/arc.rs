@@ -32,6 +32,12 @@ where
     type GetStream = Backend::GetStream;
     type SetStream = Backend::SetStream;
     type DeleteStream = Backend::DeleteStream;
+    type ScanStream = Backend::ScanStream;
+    type CompareAndSwapStream = Backend::CompareAndSwapStream;
+    type TransactionStream = Backend::TransactionStream;
+    type WatchStream = Backend::WatchStream;
+    type IncrementStream = Backend::IncrementStream;
+    type HistoryStream = Backend::HistoryStream;
 
     fn initialize(&self, connection: &Self::Connection) -> Result<(), Self::Error> {
         self.as_ref().initialize(connection)
@@ -68,6 +74,69 @@ where
     ) -> crate::RpcResponse<bool> {
         self.as_ref().not_eq(request).await
     }
+
+    async fn scan(
+        &self,
+        request: StreamingRequest<kv::ScanRequest>,
+    ) -> crate::RpcResponse<Self::ScanStream> {
+        self.as_ref().scan(request).await
+    }
+
+    async fn compare_and_swap(
+        &self,
+        request: StreamingRequest<kv::CompareAndSwapRequest>,
+    ) -> crate::RpcResponse<Self::CompareAndSwapStream> {
+        self.as_ref().compare_and_swap(request).await
+    }
+
+    async fn transaction(
+        &self,
+        request: StreamingRequest<kv::TransactionRequest>,
+    ) -> crate::RpcResponse<Self::TransactionStream> {
+        self.as_ref().transaction(request).await
+    }
+
+    async fn watch(
+        &self,
+        request: tonic::Request<kv::WatchRequest>,
+    ) -> crate::RpcResponse<Self::WatchStream> {
+        self.as_ref().watch(request).await
+    }
+
+    async fn increment(
+        &self,
+        request: StreamingRequest<kv::IncrementRequest>,
+    ) -> crate::RpcResponse<Self::IncrementStream> {
+        self.as_ref().increment(request).await
+    }
+
+    async fn create_namespace(
+        &self,
+        request: tonic::Request<kv::CreateNamespaceRequest>,
+    ) -> crate::RpcResponse<kv::CreateNamespaceResponse> {
+        self.as_ref().create_namespace(request).await
+    }
+
+    async fn list_namespaces(
+        &self,
+        request: tonic::Request<kv::ListNamespacesRequest>,
+    ) -> crate::RpcResponse<kv::ListNamespacesResponse> {
+        self.as_ref().list_namespaces(request).await
+    }
+
+    async fn drop_namespace(
+        &self,
+        request: tonic::Request<kv::DropNamespaceRequest>,
+    ) -> crate::RpcResponse<kv::DropNamespaceResponse> {
+        self.as_ref().drop_namespace(request).await
+    }
+
+    async fn history(
+        &self,
+        request: StreamingRequest<kv::HistoryRequest>,
+    ) -> crate::RpcResponse<Self::HistoryStream> {
+        self.as_ref().history(request).await
+    }
 }
 
 #[async_trait]
@@ -79,11 +148,21 @@ where
     type StoreStream = Backend::StoreStream;
     type UpdateStream = Backend::UpdateStream;
     type DeleteStream = Backend::DeleteStream;
+    type UploadStream = Backend::UploadStream;
+    type DownloadStream = Backend::DownloadStream;
+    type ListStream = Backend::ListStream;
+    type CopyStream = Backend::CopyStream;
+    type AppendStream = Backend::AppendStream;
+    type VerifyStream = Backend::VerifyStream;
 
     fn initialize(&self, connection: &Self::Connection) -> Result<(), Self::Error> {
         self.as_ref().initialize(connection)
     }
 
+    fn set_external_threshold(&self, threshold: Option<u64>) {
+        self.as_ref().set_external_threshold(threshold);
+    }
+
     async fn get(
         &self,
         request: StreamingRequest<blob::GetRequest>,
@@ -125,4 +204,46 @@ where
     ) -> crate::RpcResponse<bool> {
         self.as_ref().not_eq_data(request).await
     }
+
+    async fn upload(
+        &self,
+        request: StreamingRequest<blob::UploadRequest>,
+    ) -> crate::RpcResponse<Self::UploadStream> {
+        self.as_ref().upload(request).await
+    }
+
+    async fn download(
+        &self,
+        request: StreamingRequest<blob::DownloadRequest>,
+    ) -> crate::RpcResponse<Self::DownloadStream> {
+        self.as_ref().download(request).await
+    }
+
+    async fn list(
+        &self,
+        request: tonic::Request<blob::ListRequest>,
+    ) -> crate::RpcResponse<Self::ListStream> {
+        self.as_ref().list(request).await
+    }
+
+    async fn copy(
+        &self,
+        request: StreamingRequest<blob::CopyRequest>,
+    ) -> crate::RpcResponse<Self::CopyStream> {
+        self.as_ref().copy(request).await
+    }
+
+    async fn append(
+        &self,
+        request: StreamingRequest<blob::AppendRequest>,
+    ) -> crate::RpcResponse<Self::AppendStream> {
+        self.as_ref().append(request).await
+    }
+
+    async fn verify(
+        &self,
+        request: tonic::Request<blob::VerifyRequest>,
+    ) -> crate::RpcResponse<Self::VerifyStream> {
+        self.as_ref().verify(request).await
+    }
 }
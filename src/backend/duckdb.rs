@@ -1,22 +1,1199 @@
+//! A backend utilizing DuckDB.
+//!
+//! Prepared statements are not `Send`, so they cannot be held across a yield point of a stream.
+//! Each query is therefore prepared and run to completion before the stream yields its results.
+
+use std::collections::HashMap;
+use std::io::Write as _;
+use std::path::PathBuf;
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, LazyLock, Mutex, PoisonError, Weak};
 
-use crate::backend::{helpers, BlobBackend, DatabaseBackend, KvBackend};
-use crate::conv::try_into_protobuf_any;
-use crate::duckdb_helper::{params2, params3};
+use crate::backend::helpers::{
+    self, AttributeChanges, BufferedTransaction, ExternalFiles, KeyRange, Namespace,
+    StoredAttribute, Sweeper, TransactionTarget, Watchers,
+};
+use crate::backend::{BlobBackend, DatabaseBackend, KvBackend};
+use crate::conv::{duckdb_type_name, try_from_protobuf_value, try_into_protobuf_any};
+use crate::duckdb_helper::{params2, params3, params4};
 use crate::interop::into_tonic_status;
+use crate::proto::blob::{upload_request, Compression};
+use crate::proto::kv::set_request::Expiration;
+use crate::proto::kv::transaction_request::Operation;
+use crate::proto::kv::transaction_response::Outcome;
 use crate::proto::{blob, kv, query};
-use crate::queryable::Queryable;
+use crate::queryable::{QueryParams, Queryable};
 use crate::tracing_shim::{trace_span, Instrument};
 use crate::{DynStream, Location, RpcResponse, StreamingRequest};
 use async_stream::stream;
-use duckdb::Connection;
-use tonic::{async_trait, Response, Status};
+use duckdb::{Connection, OptionalExt as _, ParamsFromIter};
+use tonic::{async_trait, Code, Response, Status};
+
+/// The databases on disk that are open in this process, by their path.
+///
+/// DuckDB does not support opening a database more than once within a process, as each instance
+/// caches the database independently. Every connection to a database is therefore cloned from the
+/// same instance, which is closed once no backend refers to it.
+static INSTANCES: LazyLock<Mutex<HashMap<PathBuf, Weak<Instance>>>> = LazyLock::new(Mutex::default);
+
+/// A database that is open in this process.
+#[derive(Debug)]
+struct Instance {
+    /// The connection from which every other connection to the database is cloned.
+    connection: Mutex<Connection>,
+    /// Held while the store is initialized and for the duration of each transaction performed by
+    /// [`in_transaction`].
+    writer: Mutex<()>,
+}
+
+impl Instance {
+    /// Wrap a newly opened connection to a database.
+    const fn new(connection: Connection) -> Self {
+        Self {
+            connection: Mutex::new(connection),
+            writer: Mutex::new(()),
+        }
+    }
+}
 
 /// A backend utilizing DuckDB.
 #[derive(Debug)]
 pub struct DuckDb {
     location: Location,
+    instance: Arc<Instance>,
     initialized: AtomicBool,
+    sweeper: Sweeper,
+    watchers: Watchers,
+    external: ExternalFiles,
+}
+
+/// The columns of a table storing a namespace.
+const KV_COLUMNS: &str = "key TEXT PRIMARY KEY, value BLOB, expires_at BIGINT, version BIGINT";
+/// The columns of a table storing the history of a namespace. A `NULL` value records a deletion.
+const HISTORY_COLUMNS: &str = "key TEXT NOT NULL, version BIGINT NOT NULL, value BLOB,
+    written_at BIGINT NOT NULL, PRIMARY KEY (key, version)";
+
+/// Determine if a table with the given name exists.
+fn table_exists(db: &Connection, name: &str) -> duckdb::Result<bool> {
+    db.query_row(
+        "SELECT COUNT(*) FROM information_schema.tables WHERE table_name = ?",
+        [name],
+        |row| row.get::<_, u64>(0),
+    )
+    .map(|count| count != 0)
+}
+
+/// The table storing the namespace, provided the namespace exists.
+fn table(db: &Connection, namespace: &Namespace) -> Result<String, Status> {
+    if let Some(name) = namespace.storage_name() {
+        if !table_exists(db, &name).map_err(into_tonic_status)? {
+            return Err(namespace.not_found());
+        }
+    }
+    Ok(namespace.table())
+}
+
+/// All namespaces other than the default, ordered by name.
+fn namespaces(db: &Connection) -> duckdb::Result<Vec<Namespace>> {
+    let mut statement = db.prepare(
+        "SELECT table_name FROM information_schema.tables WHERE starts_with(table_name, ?)
+        ORDER BY table_name",
+    )?;
+    let names = statement
+        .query_map([Namespace::PREFIX], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    // Tables created through raw queries may share the prefix without being valid namespaces.
+    Ok(names
+        .into_iter()
+        .filter_map(|name| {
+            let name = name.strip_prefix(Namespace::PREFIX)?;
+            Namespace::named(name.to_owned()).ok()
+        })
+        .collect())
+}
+
+/// Get the value associated with the key, provided it exists and has not expired.
+fn current_value(db: &Connection, table: &str, key: &str) -> duckdb::Result<Option<Vec<u8>>> {
+    db.query_row(
+        &format!(
+            "SELECT value FROM {table} WHERE key = ? AND (expires_at IS NULL OR expires_at > ?)"
+        ),
+        params2(key, helpers::now_millis()),
+        |row| row.get(0),
+    )
+    .optional()
+}
+
+/// Get the value associated with the key and the version of the write that produced it.
+///
+/// If a version is provided, the value as of that version is read from the history of the
+/// namespace, regardless of whether it has since expired. Otherwise, the current value is read,
+/// provided it exists and has not expired.
+fn versioned_value(
+    db: &Connection,
+    table: &str,
+    history: &str,
+    key: &str,
+    version: Option<u64>,
+) -> duckdb::Result<Option<(Vec<u8>, u64)>> {
+    match version {
+        Some(version) => db
+            .query_row(
+                &format!(
+                    "SELECT value, version FROM {history}
+                    WHERE key = ? AND version <= ? ORDER BY version DESC LIMIT 1"
+                ),
+                params2(key, i64::try_from(version).unwrap_or(i64::MAX)),
+                |row| Ok((row.get::<_, Option<Vec<u8>>>(0)?, row.get(1)?)),
+            )
+            .optional()
+            // The most recent write may have deleted the key.
+            .map(|write| write.and_then(|(value, version)| Some((value?, version)))),
+        None => db
+            .query_row(
+                &format!(
+                    "SELECT value, coalesce(version, 0) FROM {table}
+                    WHERE key = ? AND (expires_at IS NULL OR expires_at > ?)"
+                ),
+                params2(key, helpers::now_millis()),
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional(),
+    }
+}
+
+/// Record a write to the key in the history of the namespace, returning the version assigned to
+/// the write. A value of `None` records the deletion of the key.
+///
+/// This must be performed within the same transaction as the write itself.
+fn record_write(
+    db: &Connection,
+    history: &str,
+    key: &str,
+    value: Option<&[u8]>,
+) -> duckdb::Result<u64> {
+    let version = db.query_row("SELECT nextval('kv_version_seq')", [], |row| row.get(0))?;
+    let _rows_changed = db.execute(
+        &format!("INSERT INTO {history} (key, version, value, written_at) VALUES (?, ?, ?, ?)"),
+        params4(key, version, value, helpers::now_millis()),
+    )?;
+    Ok(version)
+}
+
+/// Set the value associated with the key, returning the version of the write.
+fn put(
+    db: &Connection,
+    table: &str,
+    history: &str,
+    key: &str,
+    value: &[u8],
+    expires_at: Option<i64>,
+) -> duckdb::Result<u64> {
+    let version = record_write(db, history, key, Some(value))?;
+    // DuckDB fails to read rows replaced by `INSERT OR REPLACE` later in the same transaction, so
+    // existing rows are updated in place instead.
+    let rows_changed = db.execute(
+        &format!("UPDATE {table} SET value = ?, expires_at = ?, version = ? WHERE key = ?"),
+        params4(value, expires_at, version, key),
+    )?;
+    if rows_changed == 0 {
+        let _rows_changed = db.execute(
+            &format!("INSERT INTO {table} (key, value, expires_at, version) VALUES (?, ?, ?, ?)"),
+            params4(key, value, expires_at, version),
+        )?;
+    }
+    Ok(version)
+}
+
+/// Delete the key, recording the deletion if the key existed.
+fn delete(db: &Connection, table: &str, history: &str, key: &str) -> duckdb::Result<()> {
+    let rows_changed = db.execute(&format!("DELETE FROM {table} WHERE key = ?"), [key])?;
+    if rows_changed != 0 {
+        let _version = record_write(db, history, key, None)?;
+    }
+    Ok(())
+}
+
+/// The number of times a transaction is attempted before a conflict is reported to the client.
+const TRANSACTION_ATTEMPTS: u32 = 5;
+
+/// Perform `f` within a transaction, committing it if `f` succeeds.
+///
+/// DuckDB detects conflicting writes rather than waiting for locks, so the transactions performed by
+/// this function on the same database are performed one at a time. Otherwise, concurrent
+/// transactions storing the same key would conflict when the second of them is committed, and
+/// DuckDB 1.0 can leave the index of the table inconsistent when a commit fails in this way. A
+/// transaction that still conflicts with another, such as one performed by a query, is rolled
+/// back and `f` is performed again.
+fn in_transaction<T>(
+    instance: &Instance,
+    db: &mut Connection,
+    mut f: impl FnMut(&Connection) -> Result<T, Status>,
+) -> Result<T, Status> {
+    let _writer = instance
+        .writer
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    let mut attempt = 1;
+    loop {
+        // The transaction is rolled back if it is dropped before being committed.
+        let result = db.transaction().map_err(into_tonic_status).and_then(|txn| {
+            let value = f(&txn)?;
+            txn.commit().map_err(into_tonic_status)?;
+            Ok(value)
+        });
+        match result {
+            Err(status) if status.code() == Code::Aborted && attempt < TRANSACTION_ATTEMPTS => {
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// A namespace accessed within a database transaction, to which a [`BufferedTransaction`] is
+/// applied when it is committed.
+struct TransactionTable<'a> {
+    /// The connection on which the database transaction is open.
+    db: &'a Connection,
+    /// The table holding the pairs in the namespace.
+    table: &'a str,
+    /// The table holding the history of the namespace.
+    history: &'a str,
+}
+
+impl TransactionTarget for TransactionTable<'_> {
+    fn current(&mut self, key: &str) -> Result<Option<(Vec<u8>, u64)>, Status> {
+        versioned_value(self.db, self.table, self.history, key, None).map_err(into_tonic_status)
+    }
+
+    fn put(&mut self, key: &str, value: &[u8], expires_at: Option<i64>) -> Result<u64, Status> {
+        put(self.db, self.table, self.history, key, value, expires_at).map_err(into_tonic_status)
+    }
+
+    fn delete(&mut self, key: &str) -> Result<(), Status> {
+        delete(self.db, self.table, self.history, key).map_err(into_tonic_status)
+    }
+}
+
+/// Add `delta` to the integer value associated with the key, returning the previous value and the
+/// new integer value.
+///
+/// This must be performed within a transaction, as the value is read before it is written.
+fn increment(
+    db: &Connection,
+    table: &str,
+    history: &str,
+    key: &str,
+    delta: i64,
+    expiration: Option<Expiration>,
+) -> Result<(Option<Vec<u8>>, i64), Status> {
+    let now = helpers::now_millis();
+    let current = db
+        .query_row(
+            &format!(
+                "SELECT value, expires_at FROM {table}
+                WHERE key = ? AND (expires_at IS NULL OR expires_at > ?)"
+            ),
+            params2(key, now),
+            |row| Ok((row.get::<_, Vec<u8>>(0)?, row.get::<_, Option<i64>>(1)?)),
+        )
+        .optional()
+        .map_err(into_tonic_status)?;
+    // Pairs that already exist keep their expiration.
+    let (old_value, expires_at) = match current {
+        Some((old_value, expires_at)) => (Some(old_value), expires_at),
+        None => (None, helpers::expires_at(expiration, now)?),
+    };
+    let value = helpers::increment(key, old_value.as_deref(), delta)?;
+    let _version = put(
+        db,
+        table,
+        history,
+        key,
+        value.to_string().as_bytes(),
+        expires_at,
+    )
+    .map_err(into_tonic_status)?;
+    Ok((old_value, value))
+}
+
+/// The version of the layout of the BLOB store that is written by this version of the crate.
+///
+/// Stores written by earlier versions, including those written before the version was recorded,
+/// are upgraded once, when first connected to. The version they were upgraded to is then recorded,
+/// so that the upgrade, which can remove data once it has been moved, is never repeated.
+const BLOB_SCHEMA_VERSION: i64 = 2;
+
+/// Upgrade a BLOB store written by an earlier version of the crate to the current layout.
+fn upgrade_blob_store(db: &Connection) -> duckdb::Result<()> {
+    db.execute_batch(
+        "ALTER TABLE blob ADD COLUMN IF NOT EXISTS digest BLOB;
+        ALTER TABLE blob_content ADD COLUMN IF NOT EXISTS size BIGINT;
+        ALTER TABLE blob_content ADD COLUMN IF NOT EXISTS compression INTEGER DEFAULT 0;
+        ALTER TABLE blob_content ADD COLUMN IF NOT EXISTS path TEXT;",
+    )?;
+    // Stores created before compression was supported hold all content uncompressed.
+    let _rows_updated = db.execute(
+        "UPDATE blob_content SET size = octet_length(data) WHERE size IS NULL",
+        [],
+    )?;
+    // Stores created before deduplication was supported hold the data of each BLOB in the
+    // `blob` table.
+    migrate_blob_data(db)?;
+    widen_blob_ids(db)
+}
+
+/// Store the IDs of BLOBs as unsigned 64-bit integers, so that any ID can be chosen.
+///
+/// Stores created before this was supported hold IDs as signed integers. DuckDB cannot change the
+/// type of a column that is part of a key, so each table referring to BLOBs by their IDs is
+/// recreated.
+fn widen_blob_ids(db: &Connection) -> duckdb::Result<()> {
+    let id_type = db.query_row(
+        "SELECT data_type FROM information_schema.columns
+        WHERE table_name = 'blob' AND column_name = 'id'",
+        [],
+        |row| row.get::<_, String>(0),
+    )?;
+    if id_type == "UBIGINT" {
+        return Ok(());
+    }
+
+    let txn = db.unchecked_transaction()?;
+    txn.execute_batch(
+        "CREATE TABLE blob_widened(
+            id UBIGINT PRIMARY KEY DEFAULT nextval('blob_id_seq'),
+            metadata TEXT,
+            digest BLOB
+        );
+        INSERT INTO blob_widened(id, metadata, digest) SELECT id, metadata, digest FROM blob;
+        DROP TABLE blob;
+        ALTER TABLE blob_widened RENAME TO blob;
+        CREATE TABLE blob_attribute_widened(
+            blob_id UBIGINT NOT NULL,
+            name TEXT NOT NULL,
+            value BLOB NOT NULL,
+            PRIMARY KEY (blob_id, name)
+        );
+        INSERT INTO blob_attribute_widened(blob_id, name, value)
+        SELECT blob_id, name, value FROM blob_attribute;
+        DROP TABLE blob_attribute;
+        ALTER TABLE blob_attribute_widened RENAME TO blob_attribute;
+        CREATE TABLE blob_idempotency_widened(
+            idempotency_key TEXT PRIMARY KEY,
+            blob_id UBIGINT NOT NULL
+        );
+        INSERT INTO blob_idempotency_widened(idempotency_key, blob_id)
+        SELECT idempotency_key, blob_id FROM blob_idempotency;
+        DROP TABLE blob_idempotency;
+        ALTER TABLE blob_idempotency_widened RENAME TO blob_idempotency;",
+    )?;
+    txn.commit()
+}
+
+/// Move the data of BLOBs stored before deduplication was supported into the content table.
+///
+/// Such stores hold the data of each BLOB in the `data` column of the `blob` table, which is
+/// dropped once every BLOB has been moved.
+fn migrate_blob_data(db: &Connection) -> duckdb::Result<()> {
+    let has_data = db.query_row(
+        "SELECT COUNT(*) FROM information_schema.columns
+        WHERE table_name = 'blob' AND column_name = 'data'",
+        [],
+        |row| row.get::<_, u64>(0),
+    )? != 0;
+    if !has_data {
+        return Ok(());
+    }
+
+    let txn = db.unchecked_transaction()?;
+    let ids = txn
+        .prepare("SELECT id FROM blob WHERE digest IS NULL")?
+        .query_map([], |row| row.get(0))?
+        .collect::<duckdb::Result<Vec<u64>>>()?;
+    // Each BLOB is read separately to avoid holding every BLOB in memory at once.
+    for id in ids {
+        let data = txn.query_row("SELECT data FROM blob WHERE id = ?", [id], |row| {
+            row.get::<_, Option<Vec<u8>>>(0)
+        })?;
+        // Migrated content is kept in the database, where it was already stored.
+        let data = data.unwrap_or_default();
+        let digest = helpers::digest(&data);
+        if !add_content_ref(&txn, &digest)? {
+            insert_content_row(
+                &txn,
+                &digest,
+                &data,
+                data.len() as u64,
+                Compression::None,
+                None,
+            )?;
+        }
+        let _rows_updated = txn.execute(
+            "UPDATE blob SET digest = ? WHERE id = ?",
+            params2(digest, id),
+        )?;
+    }
+    txn.execute_batch("ALTER TABLE blob DROP COLUMN data")?;
+    txn.commit()
+}
+
+/// Add a reference to the stored content with the given digest, returning whether there is any.
+fn add_content_ref(db: &Connection, digest: &[u8]) -> duckdb::Result<bool> {
+    Ok(db.execute(
+        "UPDATE blob_content SET refs = refs + 1 WHERE digest = ?",
+        [digest],
+    )? != 0)
+}
+
+/// Remove a reference to the stored content with the given digest, deleting the content once no
+/// BLOB refers to it.
+///
+/// Returns the name of the file storing the deleted content, if any, which is to be removed once
+/// the transaction is committed.
+fn remove_content_ref(db: &Connection, digest: &[u8]) -> duckdb::Result<Option<String>> {
+    let _rows_updated = db.execute(
+        "UPDATE blob_content SET refs = refs - 1 WHERE digest = ?",
+        [digest],
+    )?;
+    let Some(path) = db
+        .query_row(
+            "DELETE FROM blob_content WHERE digest = ? AND refs <= 0 RETURNING path",
+            [digest],
+            |row| row.get::<_, Option<String>>(0),
+        )
+        .optional()?
+    else {
+        return Ok(None);
+    };
+    let _rows_deleted = db.execute("DELETE FROM blob_content_chunk WHERE digest = ?", [digest])?;
+    Ok(path)
+}
+
+/// Store new content with a single reference to it. `bytes` is the content as it is stored, which
+/// is empty if the content is stored in the file named `path` or in chunks.
+fn insert_content_row(
+    db: &Connection,
+    digest: &[u8],
+    bytes: &[u8],
+    size: u64,
+    compression: Compression,
+    path: Option<&str>,
+) -> duckdb::Result<()> {
+    let _rows_inserted = db.execute(
+        "INSERT INTO blob_content(digest, data, size, compression, path, refs)
+        VALUES(?, ?, ?, ?, ?, 1)",
+        duckdb::params![digest, bytes, size, compression as i32, path],
+    )?;
+    Ok(())
+}
+
+/// Store new content consisting of `data`, compressed with the requested algorithm, with a single
+/// reference to it. The content is stored in a file if it is large enough.
+fn insert_content(
+    db: &Connection,
+    external: &ExternalFiles,
+    digest: &[u8],
+    data: &[u8],
+    compression: Compression,
+) -> Result<(), Status> {
+    let (compression, bytes) = helpers::compress(data, compression);
+    let path = external.write(digest, &bytes)?;
+    let chunked = path.is_none() && compression == Compression::None;
+    let stored = if path.is_some() || chunked {
+        &[]
+    } else {
+        &*bytes
+    };
+    insert_content_row(
+        db,
+        digest,
+        stored,
+        data.len() as u64,
+        compression,
+        path.as_deref(),
+    )
+    .map_err(into_tonic_status)?;
+    if chunked {
+        for (index, chunk) in bytes.chunks(helpers::CONTENT_CHUNK_SIZE).enumerate() {
+            insert_chunk(
+                db,
+                digest,
+                (index * helpers::CONTENT_CHUNK_SIZE) as u64,
+                chunk,
+            )
+            .map_err(into_tonic_status)?;
+        }
+    }
+    Ok(())
+}
+
+/// Store a chunk of uncompressed content, beginning at the given position within it.
+fn insert_chunk(db: &Connection, digest: &[u8], position: u64, bytes: &[u8]) -> duckdb::Result<()> {
+    let _rows_inserted = db.execute(
+        "INSERT INTO blob_content_chunk(digest, position, data) VALUES(?, ?, ?)",
+        params3(digest, position, bytes),
+    )?;
+    Ok(())
+}
+
+/// Add a reference to the content consisting of `data`, storing it only if it is not already
+/// stored. Returns the digest of the content.
+fn store_content(
+    db: &Connection,
+    external: &ExternalFiles,
+    data: &[u8],
+    compression: Compression,
+) -> Result<Vec<u8>, Status> {
+    let digest = helpers::digest(data);
+    if !add_content_ref(db, &digest).map_err(into_tonic_status)? {
+        insert_content(db, external, &digest, data, compression)?;
+    }
+    Ok(digest)
+}
+
+/// Add attributes to a BLOB, replacing any existing attributes with the same names.
+fn set_attributes(db: &Connection, id: u64, attributes: &[StoredAttribute]) -> duckdb::Result<()> {
+    for (name, value) in attributes {
+        // Existing attributes are updated in place, as DuckDB cannot always replace a row with the
+        // same primary key within a transaction.
+        let rows_updated = db.execute(
+            "UPDATE blob_attribute SET value = ? WHERE blob_id = ? AND name = ?",
+            params3(value, id, name),
+        )?;
+        if rows_updated == 0 {
+            let _rows_inserted = db.execute(
+                "INSERT INTO blob_attribute(blob_id, name, value) VALUES(?, ?, ?)",
+                params3(id, name, value),
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Read the attributes of a BLOB. Returns `None` if the BLOB has no attributes.
+fn read_attributes(db: &Connection, id: u64) -> Result<Option<prost_types::Struct>, Status> {
+    let attributes = db
+        .prepare("SELECT name, value FROM blob_attribute WHERE blob_id = ?")
+        .and_then(|mut statement| {
+            statement
+                .query_map([id], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect::<duckdb::Result<Vec<_>>>()
+        })
+        .map_err(into_tonic_status)?;
+    helpers::decode_attributes(attributes)
+}
+
+/// Allocate an ID for a new BLOB from the sequence, skipping any IDs already chosen by clients.
+///
+/// This must be performed by [`in_transaction`], which performs one transaction at a time, so that
+/// no other transaction can store a BLOB under the ID before it is used.
+fn next_blob_id(db: &Connection) -> duckdb::Result<u64> {
+    loop {
+        let id = db.query_row("SELECT nextval('blob_id_seq')", [], |row| row.get(0))?;
+        let is_used = db.query_row(
+            "SELECT EXISTS(SELECT 1 FROM blob WHERE id = ?)",
+            [id],
+            |row| row.get::<_, bool>(0),
+        )?;
+        if !is_used {
+            return Ok(id);
+        }
+    }
+}
+
+/// Store a new BLOB whose content is already referenced, returning its ID. An ID is allocated if
+/// none is given.
+fn insert_blob(
+    db: &Connection,
+    id: Option<u64>,
+    digest: &[u8],
+    metadata: Option<String>,
+    attributes: &[StoredAttribute],
+) -> duckdb::Result<u64> {
+    let id = match id {
+        Some(id) => id,
+        None => next_blob_id(db)?,
+    };
+    let _rows_inserted = db.execute(
+        "INSERT INTO blob(id, metadata, digest) VALUES(?, ?, ?)",
+        params3(id, metadata, digest),
+    )?;
+    set_attributes(db, id, attributes)?;
+    Ok(id)
+}
+
+/// The ID and digest of the BLOB stored with the idempotency key if there is one, or else of the
+/// BLOB with the given ID if it exists.
+fn existing_blob(
+    db: &Connection,
+    id: Option<u64>,
+    idempotency_key: Option<&str>,
+) -> duckdb::Result<Option<(u64, Vec<u8>)>> {
+    let by_key = match idempotency_key {
+        Some(key) => db
+            .query_row(
+                "SELECT blob.id, blob.digest
+                FROM blob_idempotency JOIN blob ON blob.id = blob_idempotency.blob_id
+                WHERE blob_idempotency.idempotency_key = ?",
+                [key],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?,
+        None => None,
+    };
+    match (by_key, id) {
+        (Some(blob), _) => Ok(Some(blob)),
+        (None, Some(id)) => db
+            .query_row("SELECT id, digest FROM blob WHERE id = ?", [id], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .optional(),
+        (None, None) => Ok(None),
+    }
+}
+
+/// Whether an error was caused by a row having the same primary key as an existing row.
+fn is_duplicate_key(err: &duckdb::Error) -> bool {
+    matches!(
+        err,
+        duckdb::Error::DuckDBFailure(_, Some(message))
+            if message.starts_with("Constraint Error: Duplicate key")
+    )
+}
+
+/// Store a new BLOB as requested, returning its ID and the digest of its data. If a BLOB with the
+/// requested ID or idempotency key already exists, nothing is stored and that BLOB is returned.
+///
+/// This must be performed by [`in_transaction`], as the content and BLOB are written separately,
+/// and a BLOB stored concurrently with the same ID or idempotency key is only found once the
+/// transaction is retried.
+fn store_blob(
+    db: &Connection,
+    external: &ExternalFiles,
+    request: &blob::StoreRequest,
+) -> Result<blob::StoreResponse, Status> {
+    let blob::StoreRequest {
+        bytes,
+        metadata,
+        attributes,
+        compression,
+        id,
+        idempotency_key,
+    } = request;
+    let id = *id;
+    let compression = Compression::try_from(*compression).map_err(into_tonic_status)?;
+    let existing = existing_blob(db, id, idempotency_key.as_deref()).map_err(into_tonic_status)?;
+    if let Some((id, digest)) = existing {
+        return Ok(blob::StoreResponse {
+            id,
+            digest,
+            already_exists: true,
+        });
+    }
+
+    let digest = store_content(db, external, bytes, compression)?;
+    let attributes = helpers::encode_attributes(attributes.clone());
+    let id = insert_blob(db, id, &digest, metadata.clone(), &attributes)
+        .and_then(|id| {
+            if let Some(key) = idempotency_key {
+                let _rows_inserted = db.execute(
+                    "INSERT INTO blob_idempotency(idempotency_key, blob_id) VALUES(?, ?)",
+                    params2(key, id),
+                )?;
+            }
+            Ok(id)
+        })
+        .map_err(|err| {
+            // Another transaction stored a BLOB with the same ID or idempotency key since it was
+            // checked for. Retrying the transaction returns that BLOB instead.
+            if is_duplicate_key(&err) {
+                Status::aborted(
+                    "a BLOB with the same ID or idempotency key was stored concurrently",
+                )
+            } else {
+                into_tonic_status(err)
+            }
+        })?;
+    Ok(blob::StoreResponse {
+        id,
+        digest,
+        already_exists: false,
+    })
+}
+
+/// Update the data, metadata, and attributes of a BLOB. The data or metadata is not changed if it
+/// is `None`. New data is compressed with the algorithm given alongside it.
+///
+/// This must be performed within a transaction, as the content and BLOB are written separately.
+/// Returns the name of the file storing the content that was released, if any, which is to be
+/// removed once the transaction is committed.
+fn update_blob(
+    db: &Connection,
+    external: &ExternalFiles,
+    id: u64,
+    data: Option<(&[u8], Compression)>,
+    metadata: Option<Option<String>>,
+    attributes: &AttributeChanges,
+) -> Result<Option<String>, Status> {
+    let old_digest = db
+        .query_row("SELECT digest FROM blob WHERE id = ?", [id], |row| {
+            row.get::<_, Vec<u8>>(0)
+        })
+        .optional()
+        .map_err(into_tonic_status)?;
+    // Updating a BLOB that does not exist has no effect.
+    let Some(old_digest) = old_digest else {
+        return Ok(None);
+    };
+    let released = if let Some((data, compression)) = data {
+        // The new content is referenced before the old content is released, so content shared by
+        // both is never deleted.
+        let digest = store_content(db, external, data, compression)?;
+        db.execute(
+            "UPDATE blob SET digest = ? WHERE id = ?",
+            params2(digest, id),
+        )
+        .and_then(|_| remove_content_ref(db, &old_digest))
+        .map_err(into_tonic_status)?
+    } else {
+        None
+    };
+    if let Some(metadata) = metadata {
+        let _rows_updated = db
+            .execute(
+                "UPDATE blob SET metadata = ? WHERE id = ?",
+                params2(metadata, id),
+            )
+            .map_err(into_tonic_status)?;
+    }
+    for name in &attributes.removed {
+        let _rows_deleted = db
+            .execute(
+                "DELETE FROM blob_attribute WHERE blob_id = ? AND name = ?",
+                params2(id, name),
+            )
+            .map_err(into_tonic_status)?;
+    }
+    set_attributes(db, id, &attributes.set).map_err(into_tonic_status)?;
+    Ok(released)
+}
+
+/// Delete a BLOB and its attributes, along with its content if no other BLOB refers to it.
+///
+/// This must be performed within a transaction, as the content and BLOB are written separately.
+/// Returns the name of the file storing the content that was deleted, if any, which is to be
+/// removed once the transaction is committed.
+fn delete_blob(db: &Connection, id: u64) -> duckdb::Result<Option<String>> {
+    let _rows_deleted = db.execute("DELETE FROM blob_attribute WHERE blob_id = ?", [id])?;
+    let _rows_deleted = db.execute("DELETE FROM blob_idempotency WHERE blob_id = ?", [id])?;
+    let digest = db
+        .query_row(
+            "DELETE FROM blob WHERE id = ? RETURNING digest",
+            [id],
+            |row| row.get::<_, Vec<u8>>(0),
+        )
+        .optional()?;
+    match digest {
+        Some(digest) => remove_content_ref(db, &digest),
+        None => Ok(None),
+    }
+}
+
+/// Duplicate a BLOB under a new ID, along with its attributes, returning the ID of the copy. The
+/// metadata of the original BLOB is copied if `metadata` is `None`.
+///
+/// This must be performed within a transaction, as the content and BLOB are written separately.
+fn copy_blob(db: &Connection, id: u64, metadata: Option<Option<String>>) -> Result<u64, Status> {
+    let (digest, original_metadata) = db
+        .query_row(
+            "SELECT digest, metadata FROM blob WHERE id = ?",
+            [id],
+            |row| Ok((row.get::<_, Vec<u8>>(0)?, row.get(1)?)),
+        )
+        .optional()
+        .map_err(into_tonic_status)?
+        .ok_or_else(|| helpers::blob_not_found(id))?;
+    // The content of an existing BLOB is always stored, so the reference is always added.
+    let _is_stored = add_content_ref(db, &digest).map_err(into_tonic_status)?;
+    let copy_id = insert_blob(
+        db,
+        None,
+        &digest,
+        metadata.unwrap_or(original_metadata),
+        &[],
+    )
+    .map_err(into_tonic_status)?;
+    let _rows_inserted = db
+        .execute(
+            "INSERT INTO blob_attribute(blob_id, name, value)
+        SELECT ?, name, value FROM blob_attribute WHERE blob_id = ?",
+            params2(copy_id, id),
+        )
+        .map_err(into_tonic_status)?;
+    Ok(copy_id)
+}
+
+/// How the content of a BLOB is stored.
+struct Content {
+    /// The digest of the content.
+    digest: Vec<u8>,
+    /// The size of the content once decompressed.
+    size: u64,
+    /// The algorithm the content is compressed with.
+    compression: i32,
+    /// The name of the file storing the content, if it is not stored in the database.
+    path: Option<String>,
+    /// Whether the content is stored in chunks rather than in the content table itself. Content
+    /// stored before it was split into chunks is held in full by the content table.
+    chunked: bool,
+}
+
+impl Content {
+    /// Whether the content is stored uncompressed, such that ranges of it can be read directly.
+    const fn is_uncompressed(&self) -> bool {
+        self.compression == Compression::None as i32
+    }
+}
+
+/// The content of a BLOB, along with the metadata of the BLOB.
+fn blob_content(db: &Connection, id: u64) -> Result<(Content, Option<String>), Status> {
+    db.query_row(
+        "SELECT digest, blob_content.size, blob_content.compression, blob_content.path,
+            octet_length(blob_content.data) = 0, blob.metadata
+        FROM blob JOIN blob_content USING (digest)
+        WHERE blob.id = ?",
+        [id],
+        |row| Ok((content_from_row(row)?, row.get(5)?)),
+    )
+    .optional()
+    .map_err(into_tonic_status)?
+    .ok_or_else(|| helpers::blob_not_found(id))
+}
+
+/// Read a content from the first five columns of a row: its digest, size, compression, path, and
+/// whether it is chunked.
+fn content_from_row(row: &duckdb::Row<'_>) -> duckdb::Result<Content> {
+    Ok(Content {
+        digest: row.get(0)?,
+        size: row.get(1)?,
+        compression: row.get(2)?,
+        path: row.get(3)?,
+        chunked: row.get(4)?,
+    })
+}
+
+/// The ID of a BLOB to verify, along with its content and the number of BLOBs referring to it,
+/// unless the content is missing.
+type BlobToVerify = (u64, Option<(Content, u64)>);
+
+/// Collect up to `limit` BLOBs to verify, in ascending order of their IDs, starting from
+/// `start_id`.
+fn blobs_to_verify(
+    db: &Connection,
+    start_id: u64,
+    limit: usize,
+) -> duckdb::Result<Vec<BlobToVerify>> {
+    let mut statement = db.prepare(
+        "SELECT blob_content.digest, blob_content.size, blob_content.compression,
+            blob_content.path, octet_length(blob_content.data) = 0, blob_content.refs, blob.id
+        FROM blob LEFT JOIN blob_content ON blob_content.digest = blob.digest
+        WHERE blob.id >= ? ORDER BY blob.id LIMIT ?",
+    )?;
+    let blobs = statement
+        .query_map(params2(start_id, limit as u64), |row| {
+            let content = match row.get::<_, Option<Vec<u8>>>(0)? {
+                Some(_) => Some((content_from_row(row)?, row.get(5)?)),
+                None => None,
+            };
+            Ok((row.get(6)?, content))
+        })?
+        .collect();
+    blobs
+}
+
+/// Verify a content against its digest, returning a description of the problem if it is corrupted.
+///
+/// Uncompressed content is read and verified one chunk at a time. Compressed content, and content
+/// stored before it was split into chunks, is read in full.
+fn content_corruption(
+    db: &Connection,
+    external: &ExternalFiles,
+    content: &Content,
+) -> Result<Option<String>, Status> {
+    if !content.is_uncompressed() || (content.path.is_none() && !content.chunked) {
+        return helpers::corruption(read_content(db, external, content));
+    }
+    let mut verifier = helpers::Verifier::default();
+    helpers::corruption(
+        content_pieces(db, external, content)
+            .try_for_each(|piece| {
+                verifier.update(&piece?.1);
+                Ok(())
+            })
+            .and_then(|()| verifier.finish(&content.digest)),
+    )
+}
+
+/// Store the chunks of a BLOB that have been staged by an upload, returning its ID. This must be
+/// performed within a transaction.
+///
+/// If the content is not already stored and is not to be compressed, no more than one chunk is held
+/// in memory at a time: the chunks are either written to a file in turn or kept as the chunks of
+/// the content in the database. Content to be compressed is assembled in full first.
+fn finalize_upload(
+    db: &Connection,
+    external: &ExternalFiles,
+    size: u64,
+    digest: &[u8],
+    compression: Compression,
+    metadata: Option<String>,
+    attributes: &[StoredAttribute],
+) -> Result<u64, Status> {
+    if add_content_ref(db, digest).map_err(into_tonic_status)? {
+        // The content is already stored.
+    } else if compression != Compression::None {
+        let data = db
+            .prepare("SELECT data FROM temp.blob_upload ORDER BY position")
+            .and_then(|mut statement| {
+                statement
+                    .query_map([], |row| row.get::<_, Vec<u8>>(0))?
+                    .collect::<duckdb::Result<Vec<_>>>()
+            })
+            .map_err(into_tonic_status)?
+            .concat();
+        insert_content(db, external, digest, &data, compression)?;
+    } else if let Some(path) = external.write_with(digest, size, |file| {
+        write_upload(db, |chunk| {
+            file.write_all(chunk).map_err(helpers::file_status)
+        })
+    })? {
+        insert_content_row(db, digest, &[], size, compression, Some(&path))
+            .map_err(into_tonic_status)?;
+    } else {
+        let _rows_inserted = insert_content_row(db, digest, &[], size, compression, None)
+            .and_then(|()| {
+                db.execute(
+                    "INSERT INTO blob_content_chunk(digest, position, data)
+                    SELECT ?, position, data FROM temp.blob_upload",
+                    [digest],
+                )
+            })
+            .map_err(into_tonic_status)?;
+    }
+    db.execute("DELETE FROM temp.blob_upload", [])
+        .and_then(|_| insert_blob(db, None, digest, metadata, attributes))
+        .map_err(into_tonic_status)
+}
+
+/// Pass each chunk staged by an upload to `write`, in order.
+fn write_upload(
+    db: &Connection,
+    mut write: impl FnMut(&[u8]) -> Result<(), Status>,
+) -> Result<(), Status> {
+    let mut statement = db
+        .prepare("SELECT data FROM temp.blob_upload ORDER BY position")
+        .map_err(into_tonic_status)?;
+    let mut chunks = statement.query([]).map_err(into_tonic_status)?;
+    while let Some(chunk) = chunks.next().map_err(into_tonic_status)? {
+        let bytes = chunk
+            .get_ref(0)
+            .and_then(|value| Ok(value.as_blob()?))
+            .map_err(into_tonic_status)?;
+        write(bytes)?;
+    }
+    Ok(())
+}
+
+/// Read a content in full, decompressing it if necessary and verifying it against its digest.
+fn read_content(
+    db: &Connection,
+    external: &ExternalFiles,
+    content: &Content,
+) -> Result<Vec<u8>, Status> {
+    let bytes = match &content.path {
+        Some(path) => external.read(path)?,
+        None if content.chunked => read_content_range(
+            db,
+            external,
+            content,
+            0,
+            usize::try_from(content.size)
+                .map_err(|_| Status::out_of_range("BLOB is too large to read"))?,
+        )?,
+        None => db
+            .query_row(
+                "SELECT data FROM blob_content WHERE digest = ?",
+                [&content.digest],
+                |row| row.get(0),
+            )
+            .map_err(into_tonic_status)?,
+    };
+    let data = helpers::decompress(bytes, content.compression, content.size)?;
+    helpers::verify(&data, &content.digest)?;
+    Ok(data)
+}
+
+/// Read `len` bytes of a content, beginning at `offset`. The content must not be compressed.
+///
+/// Only the chunks overlapping the range are read. DuckDB has no substring function for BLOBs, so
+/// content stored before it was split into chunks is read in full. The range read is not verified
+/// against the digest of the content.
+fn read_content_range(
+    db: &Connection,
+    external: &ExternalFiles,
+    content: &Content,
+    offset: u64,
+    len: usize,
+) -> Result<Vec<u8>, Status> {
+    if let Some(path) = &content.path {
+        return external.read_range(path, offset, len);
+    }
+    if !content.chunked {
+        let data = db
+            .query_row(
+                "SELECT data FROM blob_content WHERE digest = ?",
+                [&content.digest],
+                |row| row.get::<_, Vec<u8>>(0),
+            )
+            .map_err(into_tonic_status)?;
+        return helpers::assemble_range([(0, data)], offset, len);
+    }
+    // The chunks are read in a single statement, beginning with the last one at or before the
+    // offset.
+    let chunks = db
+        .prepare(
+            "SELECT position, data FROM blob_content_chunk
+            WHERE digest = $1 AND position < $2 + $3 AND position >= coalesce((
+                SELECT max(position) FROM blob_content_chunk
+                WHERE digest = $1 AND position <= $2
+            ), 0)
+            ORDER BY position",
+        )
+        .and_then(|mut statement| {
+            statement
+                .query_map(params3(&content.digest, offset, len as u64), |row| {
+                    Ok((row.get(0)?, row.get(1)?))
+                })?
+                .collect::<duckdb::Result<Vec<_>>>()
+        })
+        .map_err(into_tonic_status)?;
+    helpers::assemble_range(chunks, offset, len)
+}
+
+/// Append data to a BLOB, compressing the resulting data with the requested algorithm. Returns the
+/// new size of the BLOB.
+///
+/// Uncompressed content that is to remain uncompressed is read in chunks to compute the digest of
+/// the new content, whose chunks are then copied within the database, while compressed content and
+/// content stored before it was split into chunks is read in full and rewritten.
+/// This must be performed within a transaction, as the content and BLOB are written separately.
+/// Also returns the name of the file storing the content that was released, if any, which is to be
+/// removed once the transaction is committed.
+fn append_blob(
+    db: &Connection,
+    external: &ExternalFiles,
+    id: u64,
+    bytes: &[u8],
+    compression: Compression,
+) -> Result<(u64, Option<String>), Status> {
+    let (content, _) = blob_content(db, id)?;
+    if compression != Compression::None
+        || !content.is_uncompressed()
+        || (content.path.is_none() && !content.chunked)
+    {
+        let mut data = read_content(db, external, &content)?;
+        data.extend_from_slice(bytes);
+        let released = update_blob(
+            db,
+            external,
+            id,
+            Some((&data, compression)),
+            None,
+            &AttributeChanges::default(),
+        )?;
+        return Ok((data.len() as u64, released));
+    }
+
+    let mut verifier = helpers::Verifier::default();
+    for piece in content_pieces(db, external, &content) {
+        verifier.update(&piece?.1);
+    }
+    let digest = verifier.finish_appending(&content.digest, bytes)?;
+    let size = content
+        .size
+        .checked_add(bytes.len() as u64)
+        .ok_or_else(|| Status::out_of_range("BLOB is too large"))?;
+    if !add_content_ref(db, &digest).map_err(into_tonic_status)? {
+        append_content(db, external, &content, &digest, size, bytes)?;
+    }
+    let released = db
+        .execute(
+            "UPDATE blob SET digest = ? WHERE id = ?",
+            params2(&digest, id),
+        )
+        .and_then(|_| remove_content_ref(db, &content.digest))
+        .map_err(into_tonic_status)?;
+    Ok((size, released))
+}
+
+/// Store new uncompressed content of the given size, consisting of an existing uncompressed content
+/// followed by `bytes`, with a single reference to it.
+fn append_content(
+    db: &Connection,
+    external: &ExternalFiles,
+    content: &Content,
+    digest: &[u8],
+    size: u64,
+    bytes: &[u8],
+) -> Result<(), Status> {
+    let path = external.write_with(digest, size, |file| {
+        for piece in content_pieces(db, external, content) {
+            file.write_all(&piece?.1).map_err(helpers::file_status)?;
+        }
+        file.write_all(bytes).map_err(helpers::file_status)
+    })?;
+    insert_content_row(db, digest, &[], size, Compression::None, path.as_deref())
+        .map_err(into_tonic_status)?;
+    if path.is_some() {
+        return Ok(());
+    }
+    if content.path.is_none() {
+        let _rows_inserted = db
+            .execute(
+                "INSERT INTO blob_content_chunk(digest, position, data)
+                SELECT ?, position, data FROM blob_content_chunk WHERE digest = ?",
+                params2(digest, &content.digest),
+            )
+            .map_err(into_tonic_status)?;
+    } else {
+        for piece in content_pieces(db, external, content) {
+            let (position, chunk) = piece?;
+            insert_chunk(db, digest, position, &chunk).map_err(into_tonic_status)?;
+        }
+    }
+    for (index, chunk) in bytes.chunks(helpers::CONTENT_CHUNK_SIZE).enumerate() {
+        let position = content.size + (index * helpers::CONTENT_CHUNK_SIZE) as u64;
+        insert_chunk(db, digest, position, chunk).map_err(into_tonic_status)?;
+    }
+    Ok(())
+}
+
+/// Read an uncompressed content in chunks of at most [`helpers::CONTENT_CHUNK_SIZE`] bytes, along
+/// with the position of each.
+fn content_pieces<'a>(
+    db: &'a Connection,
+    external: &'a ExternalFiles,
+    content: &'a Content,
+) -> impl Iterator<Item = Result<(u64, Vec<u8>), Status>> + 'a {
+    (0..content.size)
+        .step_by(helpers::CONTENT_CHUNK_SIZE)
+        .map(move |position| {
+            // The length is at most the chunk size, which fits in a `usize`.
+            let len = (content.size - position).min(helpers::CONTENT_CHUNK_SIZE as u64) as usize;
+            read_content_range(db, external, content, position, len).map(|chunk| (position, chunk))
+        })
 }
 
 impl DatabaseBackend for DuckDb {
@@ -25,8 +1202,12 @@ impl DatabaseBackend for DuckDb {
 
     fn at_location(location: Location) -> Result<Self, Self::Error> {
         Ok(Self {
+            external: ExternalFiles::new(&location),
+            instance: instance(&location)?,
             location,
             initialized: AtomicBool::new(false),
+            sweeper: Sweeper::default(),
+            watchers: Watchers::default(),
         })
     }
 
@@ -35,11 +1216,66 @@ impl DatabaseBackend for DuckDb {
     }
 
     fn connect(&self) -> Result<Self::Connection, Self::Error> {
-        match &self.location() {
-            Location::InMemory => Connection::open_in_memory(),
-            Location::OnDisk { path } => Connection::open(path),
-        }
+        self.instance
+            .connection
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .try_clone()
+    }
+}
+
+/// The instance of the database at the given location, opening it if it is not already open.
+fn instance(location: &Location) -> duckdb::Result<Arc<Instance>> {
+    let path = match location {
+        Location::InMemory => return Ok(Arc::new(Instance::new(Connection::open_in_memory()?))),
+        Location::OnDisk { path } => path,
+    };
+    let mut instances = INSTANCES.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(instance) = instances.get(path).and_then(Weak::upgrade) {
+        return Ok(instance);
     }
+    let instance = Arc::new(Instance::new(Connection::open(path)?));
+    // Instances that have since been closed are removed so the map does not grow without bound.
+    instances.retain(|_, instance| instance.strong_count() != 0);
+    let _previous = instances.insert(path.clone(), Arc::downgrade(&instance));
+    drop(instances);
+    Ok(instance)
+}
+
+/// Remove all key-value pairs in every namespace that have expired as of `now`.
+fn remove_expired(conn: &Connection, now: i64) -> duckdb::Result<()> {
+    for namespace in std::iter::once(Namespace::default()).chain(namespaces(conn)?) {
+        let _rows_deleted = conn.execute(
+            &format!("DELETE FROM {} WHERE expires_at <= ?", namespace.table()),
+            [now],
+        )?;
+    }
+    Ok(())
+}
+
+/// Convert the values of the parameters of a statement so they can be bound.
+///
+/// A value must be provided for every parameter. Named parameters cannot be bound, as the DuckDB
+/// bindings do not expose the names of parameters.
+fn bind_params(
+    statement: &duckdb::Statement<'_>,
+    params: QueryParams,
+) -> Result<ParamsFromIter<Vec<duckdb::types::Value>>, Status> {
+    let QueryParams { positional, named } = params;
+    if !named.is_empty() {
+        return Err(Status::unimplemented(
+            "named parameters are not supported by DuckDB",
+        ));
+    }
+    let expected = statement.parameter_count();
+    if positional.len() != expected {
+        return Err(helpers::param_count_mismatch(expected, positional.len()));
+    }
+    let values = positional
+        .into_iter()
+        .map(try_from_protobuf_value)
+        .collect::<Result<Vec<duckdb::types::Value>, _>>()?;
+    Ok(duckdb::params_from_iter(values))
 }
 
 impl Queryable for DuckDb {
@@ -47,14 +1283,43 @@ impl Queryable for DuckDb {
     type QueryStream = DynStream<Result<query::QueryResult, Status>>;
 
     #[cfg_attr(feature = "tracing", tracing::instrument)]
-    async fn query(query: String, connection: Connection) -> (Self::QueryStream, Connection) {
+    async fn query(
+        query: String,
+        params: QueryParams,
+        connection: Connection,
+    ) -> (Self::QueryStream, Connection) {
         // Needed until rust-lang/rust#128095 is resolved. At that point, `stream!` in combination
         // with `drop(statement);` can be used.`
         let (tx, rx) = crossbeam::channel::bounded(64);
 
         match connection.prepare(&query) {
-            Ok(mut statement) => match statement.query([]) {
+            Ok(mut statement) => match bind_params(&statement, params)
+                .and_then(|params| statement.query(params).map_err(into_tonic_status))
+            {
                 Ok(mut rows) => {
+                    // The columns are only known once the statement has been executed. DuckDB
+                    // reports every result column as nullable, even those declared `NOT NULL`,
+                    // and does not expose which table column a result column comes from, so
+                    // whether it can be null is not known.
+                    let columns = rows
+                        .as_ref()
+                        .map(|statement| {
+                            statement
+                                .schema()
+                                .fields()
+                                .iter()
+                                .map(|field| query::Column {
+                                    name: field.name().clone(),
+                                    declared_type: Some(duckdb_type_name(field.data_type())),
+                                    nullable: None,
+                                })
+                                .collect()
+                        })
+                        .unwrap_or_default();
+                    let _res = tx.send(Ok(query::QueryResult {
+                        fields: Vec::new(),
+                        columns,
+                    }));
                     while let Ok(Some(row)) = rows.next() {
                         let column_count = row.as_ref().column_count();
                         let mut values = Vec::with_capacity(column_count);
@@ -74,11 +1339,14 @@ impl Queryable for DuckDb {
                                 }
                             }
                         }
-                        let _res = tx.send(Ok(query::QueryResult { fields: values }));
+                        let _res = tx.send(Ok(query::QueryResult {
+                            fields: values,
+                            columns: Vec::new(),
+                        }));
                     }
                 }
                 Err(err) => {
-                    let _res = tx.send(Err(into_tonic_status(err)));
+                    let _res = tx.send(Err(err));
                 }
             },
             Err(err) => {
@@ -99,12 +1367,16 @@ impl Queryable for DuckDb {
     #[cfg_attr(feature = "tracing", tracing::instrument)]
     async fn execute(
         query: String,
+        params: QueryParams,
         connection: Connection,
     ) -> (Result<query::RowsChanged, Status>, Connection) {
         match connection
             .prepare(&query)
-            .and_then(|mut statement| statement.execute([]))
-        {
+            .map_err(into_tonic_status)
+            .and_then(|mut statement| {
+                let params = bind_params(&statement, params)?;
+                statement.execute(params).map_err(into_tonic_status)
+            }) {
             Ok(rows_changed) => (
                 Ok(query::RowsChanged {
                     rows_changed: rows_changed
@@ -113,9 +1385,19 @@ impl Queryable for DuckDb {
                 }),
                 connection,
             ),
-            Err(err) => (Err(into_tonic_status(err)), connection),
+            Err(err) => (Err(err), connection),
         }
     }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument)]
+    fn attach(connection: &Connection, location: &Location, name: &str) -> Result<(), Status> {
+        // `ATTACH` does not accept parameters, so the path and name are quoted instead.
+        let path = helpers::attachable_path(location)?.replace('\'', "''");
+        let name = name.replace('"', "\"\"");
+        connection
+            .execute_batch(&format!("ATTACH '{path}' AS \"{name}\""))
+            .map_err(into_tonic_status)
+    }
 }
 
 #[async_trait]
@@ -123,41 +1405,84 @@ impl KvBackend for DuckDb {
     type GetStream = DynStream<Result<kv::GetResponse, Status>>;
     type SetStream = DynStream<Result<kv::SetResponse, Status>>;
     type DeleteStream = DynStream<Result<kv::DeleteResponse, Status>>;
+    type ScanStream = DynStream<Result<kv::ScanResponse, Status>>;
+    type CompareAndSwapStream = DynStream<Result<kv::CompareAndSwapResponse, Status>>;
+    type TransactionStream = DynStream<Result<kv::TransactionResponse, Status>>;
+    type WatchStream = DynStream<Result<kv::WatchResponse, Status>>;
+    type IncrementStream = DynStream<Result<kv::IncrementResponse, Status>>;
+    type HistoryStream = DynStream<Result<kv::HistoryResponse, Status>>;
 
     #[cfg_attr(feature = "tracing", tracing::instrument)]
     fn initialize(&self, connection: &Self::Connection) -> Result<(), Self::Error> {
-        let _res = connection.execute(
-            "CREATE TABLE IF NOT EXISTS kv (key TEXT PRIMARY KEY, value TEXT)",
+        // Stores created before expiration or versions were supported do not have the columns.
+        connection.execute_batch(&format!(
+            "CREATE TABLE IF NOT EXISTS kv ({KV_COLUMNS});
+            ALTER TABLE kv ADD COLUMN IF NOT EXISTS expires_at BIGINT;
+            ALTER TABLE kv ADD COLUMN IF NOT EXISTS version BIGINT;
+            CREATE TABLE IF NOT EXISTS kv_history ({HISTORY_COLUMNS});
+            CREATE SEQUENCE IF NOT EXISTS kv_version_seq START 1;"
+        ))?;
+        // Stores created before binary values were supported hold values as text.
+        let value_type = connection.query_row(
+            "SELECT data_type FROM information_schema.columns
+            WHERE table_name = 'kv' AND column_name = 'value'",
             [],
+            |row| row.get::<_, String>(0),
         )?;
+        if value_type != "BLOB" {
+            connection
+                .execute_batch("ALTER TABLE kv ALTER value TYPE BLOB USING encode(value);")?;
+        }
         self.initialized.store(true, Ordering::Relaxed);
         Ok(())
     }
 
     #[cfg_attr(feature = "tracing", tracing::instrument)]
     fn connect_kv(&self) -> Result<Self::Connection, Self::Error> {
-        let conn = self.connect()?;
+        let mut conn = self.connect()?;
         if !self.initialized.load(Ordering::Relaxed) {
-            KvBackend::initialize(self, &conn)?;
+            // DuckDB reports concurrent changes to the schema as conflicts rather than waiting for
+            // them, so the store is initialized by one connection at a time.
+            let _writer = self
+                .instance
+                .writer
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            if !self.initialized.load(Ordering::Relaxed) {
+                KvBackend::initialize(self, &conn)?;
+            }
+        }
+        let now = helpers::now_millis();
+        if self.sweeper.is_due(now) {
+            // Unlike other backends, expired pairs are removed before the connection is returned.
+            // Sweeping in the background on a clone of this connection would keep the instance open
+            // after the backend is dropped, so a backend opened later for the same path would
+            // create a second instance of the database. Expired pairs remain invisible if the sweep
+            // fails, and are removed by a later sweep.
+            let _res = in_transaction(&self.instance, &mut conn, |db| {
+                remove_expired(db, now).map_err(into_tonic_status)
+            });
         }
         Ok(conn)
     }
 
     #[cfg_attr(feature = "tracing", tracing::instrument)]
     async fn get(&self, request: StreamingRequest<kv::GetRequest>) -> RpcResponse<Self::GetStream> {
+        let namespace = Namespace::from_metadata(request.metadata())?;
         let mut stream = request.into_inner();
         let db = self.connect_kv().map_err(into_tonic_status)?;
+        let table = table(&db, &namespace)?;
+        let history = namespace.history_table();
         let stream = stream!({
-            while let Some(kv::GetRequest { key }) = stream.message().await? {
-                let value = db
-                    .query_row("SELECT value FROM kv WHERE key = ?", [&key], |row| {
-                        row.get(0)
-                    })
+            while let Some(kv::GetRequest {
+                key,
+                allow_missing,
+                version,
+            }) = stream.message().await?
+            {
+                let value = versioned_value(&db, &table, &history, &key, version)
                     .map_err(into_tonic_status)?;
-                yield Ok(kv::GetResponse {
-                    value: String::from_utf8(value)
-                        .expect("protobuf requires strings be valid UTF-8"),
-                });
+                yield helpers::get_response(&key, value, allow_missing);
             }
         })
         .instrument(trace_span!("DuckDB kv get query"));
@@ -166,16 +1491,36 @@ impl KvBackend for DuckDb {
 
     #[cfg_attr(feature = "tracing", tracing::instrument)]
     async fn set(&self, request: StreamingRequest<kv::SetRequest>) -> RpcResponse<Self::SetStream> {
+        let namespace = Namespace::from_metadata(request.metadata())?;
         let mut stream = request.into_inner();
-        let db = self.connect_kv().map_err(into_tonic_status)?;
+        let mut db = self.connect_kv().map_err(into_tonic_status)?;
+        let instance = Arc::clone(&self.instance);
+        let table = table(&db, &namespace)?;
+        let history = namespace.history_table();
+        let watchers = self.watchers.in_namespace(&namespace);
         let stream = stream!({
-            while let Some(kv::SetRequest { key, value }) = stream.message().await? {
-                db.execute(
-                    "INSERT OR REPLACE INTO kv (key, value) VALUES (?, ?)",
-                    [&key, &value],
-                )
-                .map_err(into_tonic_status)?;
-                yield Ok(kv::SetResponse { key });
+            while let Some(kv::SetRequest {
+                key,
+                value,
+                expiration,
+            }) = stream.message().await?
+            {
+                let expires_at = helpers::expires_at(expiration, helpers::now_millis())?;
+                let watched = watchers.is_watched();
+                let (old_value, version) = in_transaction(&instance, &mut db, |db| {
+                    let old_value = if watched {
+                        current_value(db, &table, &key).map_err(into_tonic_status)?
+                    } else {
+                        None
+                    };
+                    let version = put(db, &table, &history, &key, &value, expires_at)
+                        .map_err(into_tonic_status)?;
+                    Ok((old_value, version))
+                })?;
+                if watched {
+                    watchers.notify([helpers::put_change(key.clone(), old_value, value)]);
+                }
+                yield Ok(kv::SetResponse { key, version });
             }
         })
         .instrument(trace_span!("DuckDB kv set query"));
@@ -187,12 +1532,29 @@ impl KvBackend for DuckDb {
         &self,
         request: StreamingRequest<kv::DeleteRequest>,
     ) -> RpcResponse<Self::DeleteStream> {
+        let namespace = Namespace::from_metadata(request.metadata())?;
         let mut stream = request.into_inner();
-        let db = self.connect_kv().map_err(into_tonic_status)?;
+        let mut db = self.connect_kv().map_err(into_tonic_status)?;
+        let instance = Arc::clone(&self.instance);
+        let table = table(&db, &namespace)?;
+        let history = namespace.history_table();
+        let watchers = self.watchers.in_namespace(&namespace);
         let stream = stream!({
             while let Some(kv::DeleteRequest { key }) = stream.message().await? {
-                db.execute("DELETE FROM kv WHERE key = ?", [&key])
-                    .map_err(into_tonic_status)?;
+                let watched = watchers.is_watched();
+                let old_value = in_transaction(&instance, &mut db, |db| {
+                    let old_value = if watched {
+                        current_value(db, &table, &key).map_err(into_tonic_status)?
+                    } else {
+                        None
+                    };
+                    delete(db, &table, &history, &key).map_err(into_tonic_status)?;
+                    Ok(old_value)
+                })?;
+                // Nothing has changed if the key did not exist.
+                watchers.notify(
+                    old_value.map(|old_value| helpers::delete_change(key.clone(), old_value)),
+                );
                 yield Ok(kv::DeleteResponse { key });
             }
         })
@@ -202,18 +1564,23 @@ impl KvBackend for DuckDb {
 
     #[cfg_attr(feature = "tracing", tracing::instrument)]
     async fn eq(&self, request: StreamingRequest<kv::EqRequest>) -> RpcResponse<bool> {
+        let namespace = Namespace::from_metadata(request.metadata())?;
         let mut stream = request.into_inner();
         let db = self.connect_kv().map_err(into_tonic_status)?;
+        let table = table(&db, &namespace)?;
         let stream = Box::pin(stream!({
             while let Some(kv::EqRequest { key }) = stream.message().await? {
                 let value = db
-                    .query_row("SELECT value FROM kv WHERE key = ?", [&key], |row| {
-                        row.get(0)
-                    })
+                    .query_row(
+                        &format!(
+                            "SELECT value FROM {table}
+                            WHERE key = ? AND (expires_at IS NULL OR expires_at > ?)"
+                        ),
+                        params2(&key, helpers::now_millis()),
+                        |row| row.get::<_, Vec<u8>>(0),
+                    )
                     .map_err(into_tonic_status)?;
-                yield Ok::<_, Status>(
-                    String::from_utf8(value).expect("protobuf requires strings be valid UTF-8"),
-                );
+                yield Ok::<_, Status>(value);
             }
         }))
         .instrument(trace_span!("DuckDB kv eq query"));
@@ -222,23 +1589,343 @@ impl KvBackend for DuckDb {
 
     #[cfg_attr(feature = "tracing", tracing::instrument)]
     async fn not_eq(&self, request: StreamingRequest<kv::NotEqRequest>) -> RpcResponse<bool> {
+        let namespace = Namespace::from_metadata(request.metadata())?;
         let mut stream = request.into_inner();
         let db = self.connect_kv().map_err(into_tonic_status)?;
+        let table = table(&db, &namespace)?;
         let stream = Box::pin(stream!({
             while let Some(kv::NotEqRequest { key }) = stream.message().await? {
                 let value = db
-                    .query_row("SELECT value FROM kv WHERE key = ?", [&key], |row| {
-                        row.get(0)
-                    })
+                    .query_row(
+                        &format!(
+                            "SELECT value FROM {table}
+                            WHERE key = ? AND (expires_at IS NULL OR expires_at > ?)"
+                        ),
+                        params2(&key, helpers::now_millis()),
+                        |row| row.get::<_, Vec<u8>>(0),
+                    )
                     .map_err(into_tonic_status)?;
-                yield Ok::<_, Status>(
-                    String::from_utf8(value).expect("protobuf requires strings be valid UTF-8"),
-                );
+                yield Ok::<_, Status>(value);
             }
         }))
         .instrument(trace_span!("DuckDB kv not_eq query"));
         Ok(Response::new(helpers::all_not_eq(stream).await?))
     }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument)]
+    async fn scan(
+        &self,
+        request: StreamingRequest<kv::ScanRequest>,
+    ) -> RpcResponse<Self::ScanStream> {
+        let namespace = Namespace::from_metadata(request.metadata())?;
+        let mut stream = request.into_inner();
+        let db = self.connect_kv().map_err(into_tonic_status)?;
+        let table = table(&db, &namespace)?;
+        let stream = stream!({
+            while let Some(kv::ScanRequest {
+                prefix,
+                start,
+                end,
+                limit,
+                reverse,
+            }) = stream.message().await?
+            {
+                let KeyRange { start, end, prefix } = KeyRange::new(prefix, start, end);
+                let query = format!(
+                    "SELECT key, value FROM {table}
+                    WHERE key >= $1 AND ($2 IS NULL OR key < $2) AND starts_with(key, $3)
+                        AND (expires_at IS NULL OR expires_at > $4)
+                    ORDER BY key {}{}",
+                    if reverse { "DESC" } else { "ASC" },
+                    helpers::limit_clause(limit),
+                );
+                let pairs = db
+                    .prepare(&query)
+                    .and_then(|mut statement| {
+                        statement
+                            .query_map(params4(start, end, prefix, helpers::now_millis()), |row| {
+                                Ok((row.get::<_, Vec<u8>>(0)?, row.get::<_, Vec<u8>>(1)?))
+                            })?
+                            .collect::<Result<Vec<_>, _>>()
+                    })
+                    .map_err(into_tonic_status)?;
+                for (key, value) in pairs {
+                    yield Ok(kv::ScanResponse {
+                        key: helpers::stored_string(key, "key")?,
+                        value,
+                    });
+                }
+            }
+        })
+        .instrument(trace_span!("DuckDB kv scan query"));
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument)]
+    async fn compare_and_swap(
+        &self,
+        request: StreamingRequest<kv::CompareAndSwapRequest>,
+    ) -> RpcResponse<Self::CompareAndSwapStream> {
+        let namespace = Namespace::from_metadata(request.metadata())?;
+        let mut stream = request.into_inner();
+        let mut db = self.connect_kv().map_err(into_tonic_status)?;
+        let instance = Arc::clone(&self.instance);
+        let table = table(&db, &namespace)?;
+        let history = namespace.history_table();
+        let watchers = self.watchers.in_namespace(&namespace);
+        let stream = stream!({
+            while let Some(kv::CompareAndSwapRequest {
+                key,
+                expected_value,
+                new_value,
+                expiration,
+            }) = stream.message().await?
+            {
+                let expires_at =
+                    helpers::expires_at(expiration.map(Into::into), helpers::now_millis())?;
+                let _version = in_transaction(&instance, &mut db, |db| {
+                    let current_value =
+                        current_value(db, &table, &key).map_err(into_tonic_status)?;
+                    if current_value != expected_value {
+                        return Err(helpers::compare_and_swap_failure(&key, current_value));
+                    }
+                    put(db, &table, &history, &key, &new_value, expires_at)
+                        .map_err(into_tonic_status)
+                })?;
+                watchers.notify([helpers::put_change(key.clone(), expected_value, new_value)]);
+                yield Ok(kv::CompareAndSwapResponse { key });
+            }
+        })
+        .instrument(trace_span!("DuckDB kv compare_and_swap query"));
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument)]
+    async fn transaction(
+        &self,
+        request: StreamingRequest<kv::TransactionRequest>,
+    ) -> RpcResponse<Self::TransactionStream> {
+        let namespace = Namespace::from_metadata(request.metadata())?;
+        let mut stream = request.into_inner();
+        let mut db = self.connect_kv().map_err(into_tonic_status)?;
+        let instance = Arc::clone(&self.instance);
+        let table = table(&db, &namespace)?;
+        let history = namespace.history_table();
+        let watchers = self.watchers.in_namespace(&namespace);
+        let stream = stream!({
+            // If the stream ends before the transaction is committed, its writes are discarded.
+            let mut txn = Some(BufferedTransaction::default());
+
+            while let Some(kv::TransactionRequest { operation }) = stream.message().await? {
+                let active_txn = txn
+                    .as_mut()
+                    .ok_or_else(|| Status::failed_precondition("transaction has already ended"))?;
+                let outcome = match operation {
+                    Some(Operation::Get(kv::GetRequest {
+                        key,
+                        allow_missing,
+                        version,
+                    })) => {
+                        let value = active_txn.get(&key, version, || {
+                            versioned_value(&db, &table, &history, &key, version)
+                                .map_err(into_tonic_status)
+                        })?;
+                        Outcome::Get(helpers::get_response(&key, value, allow_missing)?)
+                    }
+                    Some(Operation::Set(kv::SetRequest {
+                        key,
+                        value,
+                        expiration,
+                    })) => {
+                        let expires_at = helpers::expires_at(expiration, helpers::now_millis())?;
+                        active_txn.set(key.clone(), value, expires_at);
+                        Outcome::Set(kv::SetResponse { key, version: 0 })
+                    }
+                    Some(Operation::Delete(kv::DeleteRequest { key })) => {
+                        active_txn.delete(key.clone());
+                        Outcome::Delete(kv::DeleteResponse { key })
+                    }
+                    Some(Operation::Commit(_)) => {
+                        let watched = watchers.is_watched();
+                        let (versions, changes) = in_transaction(&instance, &mut db, |db| {
+                            active_txn.apply(
+                                &mut TransactionTable {
+                                    db,
+                                    table: &table,
+                                    history: &history,
+                                },
+                                watched,
+                            )
+                        })?;
+                        txn = None;
+                        watchers.notify(changes);
+                        Outcome::Commit(kv::Commit { versions })
+                    }
+                    Some(Operation::Rollback(rollback)) => {
+                        txn = None;
+                        Outcome::Rollback(rollback)
+                    }
+                    None => Err(Status::invalid_argument("no operation was provided"))?,
+                };
+                yield Ok(kv::TransactionResponse {
+                    outcome: Some(outcome),
+                });
+            }
+        })
+        .instrument(trace_span!("DuckDB kv transaction query"));
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument)]
+    async fn watch(
+        &self,
+        request: tonic::Request<kv::WatchRequest>,
+    ) -> RpcResponse<Self::WatchStream> {
+        let namespace = Namespace::from_metadata(request.metadata())?;
+        let db = self.connect_kv().map_err(into_tonic_status)?;
+        let _table = table(&db, &namespace)?;
+        let stream = self
+            .watchers
+            .in_namespace(&namespace)
+            .watch(request.into_inner())?
+            .instrument(trace_span!("DuckDB kv watch query"));
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument)]
+    async fn increment(
+        &self,
+        request: StreamingRequest<kv::IncrementRequest>,
+    ) -> RpcResponse<Self::IncrementStream> {
+        let namespace = Namespace::from_metadata(request.metadata())?;
+        let mut stream = request.into_inner();
+        let mut db = self.connect_kv().map_err(into_tonic_status)?;
+        let instance = Arc::clone(&self.instance);
+        let table = table(&db, &namespace)?;
+        let history = namespace.history_table();
+        let watchers = self.watchers.in_namespace(&namespace);
+        let stream = stream!({
+            while let Some(kv::IncrementRequest {
+                key,
+                delta,
+                expiration,
+            }) = stream.message().await?
+            {
+                let (old_value, value) = in_transaction(&instance, &mut db, |db| {
+                    increment(
+                        db,
+                        &table,
+                        &history,
+                        &key,
+                        delta,
+                        expiration.map(Into::into),
+                    )
+                })?;
+                let new_value = value.to_string().into_bytes();
+                watchers.notify([helpers::put_change(key.clone(), old_value, new_value)]);
+                yield Ok(kv::IncrementResponse { key, value });
+            }
+        })
+        .instrument(trace_span!("DuckDB kv increment query"));
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument)]
+    async fn create_namespace(
+        &self,
+        request: tonic::Request<kv::CreateNamespaceRequest>,
+    ) -> RpcResponse<kv::CreateNamespaceResponse> {
+        let kv::CreateNamespaceRequest { name } = request.into_inner();
+        let namespace = Namespace::named(name.clone())?;
+        let mut db = self.connect_kv().map_err(into_tonic_status)?;
+        let storage_name = namespace.storage_name().unwrap_or_default();
+        // Both tables are created in one transaction, in which the namespace is first checked not
+        // to exist, so that concurrent creations cannot both succeed or leave half a namespace.
+        in_transaction(&self.instance, &mut db, |db| {
+            if table_exists(db, &storage_name).map_err(into_tonic_status)? {
+                return Err(namespace.already_exists());
+            }
+            db.execute_batch(&format!(
+                "CREATE TABLE {} ({KV_COLUMNS});
+                CREATE TABLE {} ({HISTORY_COLUMNS});",
+                namespace.table(),
+                namespace.history_table(),
+            ))
+            .map_err(into_tonic_status)
+        })?;
+        Ok(Response::new(kv::CreateNamespaceResponse { name }))
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument)]
+    async fn list_namespaces(
+        &self,
+        _request: tonic::Request<kv::ListNamespacesRequest>,
+    ) -> RpcResponse<kv::ListNamespacesResponse> {
+        let db = self.connect_kv().map_err(into_tonic_status)?;
+        let names = namespaces(&db)
+            .map_err(into_tonic_status)?
+            .iter()
+            .filter_map(|namespace| namespace.name().map(str::to_owned))
+            .collect();
+        Ok(Response::new(kv::ListNamespacesResponse { names }))
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument)]
+    async fn drop_namespace(
+        &self,
+        request: tonic::Request<kv::DropNamespaceRequest>,
+    ) -> RpcResponse<kv::DropNamespaceResponse> {
+        let kv::DropNamespaceRequest { name } = request.into_inner();
+        let namespace = Namespace::named(name.clone())?;
+        let db = self.connect_kv().map_err(into_tonic_status)?;
+        let table = table(&db, &namespace)?;
+        db.execute_batch(&format!(
+            "DROP TABLE {table};
+            DROP TABLE IF EXISTS {};",
+            namespace.history_table(),
+        ))
+        .map_err(into_tonic_status)?;
+        Ok(Response::new(kv::DropNamespaceResponse { name }))
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument)]
+    async fn history(
+        &self,
+        request: StreamingRequest<kv::HistoryRequest>,
+    ) -> RpcResponse<Self::HistoryStream> {
+        let namespace = Namespace::from_metadata(request.metadata())?;
+        let mut stream = request.into_inner();
+        let db = self.connect_kv().map_err(into_tonic_status)?;
+        let _table = table(&db, &namespace)?;
+        let history = namespace.history_table();
+        let stream = stream!({
+            while let Some(kv::HistoryRequest { key }) = stream.message().await? {
+                let writes = db
+                    .prepare(&format!(
+                        "SELECT version, written_at, value FROM {history}
+                        WHERE key = ? ORDER BY version"
+                    ))
+                    .and_then(|mut statement| {
+                        statement
+                            .query_map([&key], |row| {
+                                Ok(kv::HistoryResponse {
+                                    key: key.clone(),
+                                    version: row.get(0)?,
+                                    written_at: Some(helpers::timestamp(row.get(1)?)),
+                                    value: row.get(2)?,
+                                })
+                            })?
+                            .collect::<Result<Vec<_>, _>>()
+                    })
+                    .map_err(into_tonic_status)?;
+                for write in writes {
+                    yield Ok(write);
+                }
+            }
+        })
+        .instrument(trace_span!("DuckDB kv history query"));
+        Ok(Response::new(Box::pin(stream)))
+    }
 }
 
 #[async_trait]
@@ -247,17 +1934,60 @@ impl BlobBackend for DuckDb {
     type StoreStream = DynStream<Result<blob::StoreResponse, Status>>;
     type UpdateStream = DynStream<Result<blob::UpdateResponse, Status>>;
     type DeleteStream = DynStream<Result<blob::DeleteResponse, Status>>;
+    type UploadStream = DynStream<Result<blob::UploadResponse, Status>>;
+    type DownloadStream = DynStream<Result<blob::DownloadResponse, Status>>;
+    type ListStream = DynStream<Result<blob::ListResponse, Status>>;
+    type CopyStream = DynStream<Result<blob::CopyResponse, Status>>;
+    type AppendStream = DynStream<Result<blob::AppendResponse, Status>>;
+    type VerifyStream = DynStream<Result<blob::VerifyResponse, Status>>;
 
     #[cfg_attr(feature = "tracing", tracing::instrument)]
     fn initialize(&self, connection: &Self::Connection) -> Result<(), Self::Error> {
         connection.execute_batch(
             "CREATE SEQUENCE IF NOT EXISTS blob_id_seq START 1;
             CREATE TABLE IF NOT EXISTS blob(
-                id INTEGER PRIMARY KEY DEFAULT nextval('blob_id_seq'),
-                data BLOB,
-                metadata TEXT
-            );",
+                id UBIGINT PRIMARY KEY DEFAULT nextval('blob_id_seq'),
+                metadata TEXT,
+                digest BLOB
+            );
+            CREATE TABLE IF NOT EXISTS blob_content(
+                digest BLOB PRIMARY KEY,
+                data BLOB NOT NULL,
+                size BIGINT NOT NULL,
+                compression INTEGER NOT NULL,
+                path TEXT,
+                refs BIGINT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS blob_content_chunk(
+                digest BLOB NOT NULL,
+                position BIGINT NOT NULL,
+                data BLOB NOT NULL,
+                PRIMARY KEY (digest, position)
+            );
+            CREATE TABLE IF NOT EXISTS blob_attribute(
+                blob_id UBIGINT NOT NULL,
+                name TEXT NOT NULL,
+                value BLOB NOT NULL,
+                PRIMARY KEY (blob_id, name)
+            );
+            CREATE TABLE IF NOT EXISTS blob_idempotency(
+                idempotency_key TEXT PRIMARY KEY,
+                blob_id UBIGINT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS blob_schema(version INTEGER NOT NULL);",
+        )?;
+        let version = connection.query_row(
+            "SELECT coalesce(max(version), 0) FROM blob_schema",
+            [],
+            |row| row.get::<_, i64>(0),
         )?;
+        if version < BLOB_SCHEMA_VERSION {
+            upgrade_blob_store(connection)?;
+            let _rows_inserted = connection.execute(
+                "INSERT INTO blob_schema(version) VALUES(?)",
+                [BLOB_SCHEMA_VERSION],
+            )?;
+        }
         self.initialized.store(true, Ordering::Relaxed);
         Ok(())
     }
@@ -266,11 +1996,24 @@ impl BlobBackend for DuckDb {
     fn connect_blob(&self) -> Result<Self::Connection, Self::Error> {
         let conn = self.connect()?;
         if !self.initialized.load(Ordering::Relaxed) {
-            BlobBackend::initialize(self, &conn)?;
+            // DuckDB reports concurrent changes to the schema as conflicts rather than waiting for
+            // them, so the store is initialized by one connection at a time.
+            let _writer = self
+                .instance
+                .writer
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            if !self.initialized.load(Ordering::Relaxed) {
+                BlobBackend::initialize(self, &conn)?;
+            }
         }
         Ok(conn)
     }
 
+    fn set_external_threshold(&self, threshold: Option<u64>) {
+        self.external.set_threshold(threshold);
+    }
+
     #[cfg_attr(feature = "tracing", tracing::instrument)]
     async fn get(
         &self,
@@ -278,24 +2021,32 @@ impl BlobBackend for DuckDb {
     ) -> RpcResponse<Self::GetStream> {
         let mut stream = request.into_inner();
         let db = self.connect_blob().map_err(into_tonic_status)?;
+        let external = self.external.clone();
 
         let stream = stream!({
-            while let Some(blob::GetRequest { id }) = stream.message().await? {
-                let (data, metadata) = db
-                    .query_row(
-                        "SELECT data, metadata FROM blob WHERE id = ?",
-                        [id],
-                        |row| {
-                            let data: Vec<u8> = row.get(0)?;
-                            let metadata: Option<String> = row.get(1)?;
-                            Ok((data, metadata))
-                        },
-                    )
+            while let Some(blob::GetRequest { id, offset, length }) = stream.message().await? {
+                // The BLOB is read within a transaction, so that its size, data, and attributes are
+                // all read from the same snapshot of the database.
+                db.execute_batch("BEGIN TRANSACTION")
                     .map_err(into_tonic_status)?;
+                let (content, metadata) = blob_content(&db, id)?;
+                let (offset, len) = helpers::byte_range(content.size, offset, length)?;
+                let data = if offset == 0 && len as u64 == content.size {
+                    read_content(&db, &external, &content)?
+                } else if content.is_uncompressed() {
+                    read_content_range(&db, &external, &content, offset, len)?
+                } else {
+                    let data = read_content(&db, &external, &content)?;
+                    data[offset as usize..][..len].to_vec()
+                };
+                let attributes = read_attributes(&db, id)?;
+                db.execute_batch("COMMIT").map_err(into_tonic_status)?;
 
                 yield Ok(blob::GetResponse {
                     bytes: data,
                     metadata,
+                    attributes,
+                    digest: content.digest,
                 });
             }
         })
@@ -309,18 +2060,15 @@ impl BlobBackend for DuckDb {
         request: StreamingRequest<blob::StoreRequest>,
     ) -> RpcResponse<Self::StoreStream> {
         let mut stream = request.into_inner();
-        let db = self.connect_blob().map_err(into_tonic_status)?;
+        let mut db = self.connect_blob().map_err(into_tonic_status)?;
+        let instance = Arc::clone(&self.instance);
+        let external = self.external.clone();
 
         let stream = stream!({
-            while let Some(blob::StoreRequest { bytes, metadata }) = stream.message().await? {
-                let id = db
-                    .query_row(
-                        "INSERT INTO blob(data, metadata) VALUES(?, ?) RETURNING id",
-                        params2(bytes, metadata),
-                        |row| row.get(0),
-                    )
-                    .map_err(into_tonic_status)?;
-                yield Ok(blob::StoreResponse { id });
+            while let Some(request) = stream.message().await? {
+                let response =
+                    in_transaction(&instance, &mut db, |db| store_blob(db, &external, &request))?;
+                yield Ok(response);
             }
         })
         .instrument(trace_span!("DuckDB blob store query"));
@@ -333,7 +2081,9 @@ impl BlobBackend for DuckDb {
         request: StreamingRequest<blob::UpdateRequest>,
     ) -> RpcResponse<Self::UpdateStream> {
         let mut stream = request.into_inner();
-        let db = self.connect_blob().map_err(into_tonic_status)?;
+        let mut db = self.connect_blob().map_err(into_tonic_status)?;
+        let instance = Arc::clone(&self.instance);
+        let external = self.external.clone();
 
         let stream = stream!({
             while let Some(blob::UpdateRequest {
@@ -341,28 +2091,20 @@ impl BlobBackend for DuckDb {
                 bytes,
                 should_update_metadata,
                 metadata,
+                attributes,
+                remove_attributes,
+                compression,
             }) = stream.message().await?
             {
-                match (bytes, should_update_metadata) {
-                    (None, false) => {}
-                    (Some(bytes), true) => {
-                        db.execute(
-                            "UPDATE blob SET data = ?, metadata = ? WHERE id = ?",
-                            params3(bytes, metadata, id),
-                        )
-                        .map_err(into_tonic_status)?;
-                    }
-                    (None, true) => {
-                        db.execute(
-                            "UPDATE blob SET metadata = ? WHERE id = ?",
-                            params2(metadata, id),
-                        )
-                        .map_err(into_tonic_status)?;
-                    }
-                    (Some(bytes), false) => {
-                        db.execute("UPDATE blob SET data = ? WHERE id = ?", params2(bytes, id))
-                            .map_err(into_tonic_status)?;
-                    }
+                let compression = Compression::try_from(compression).map_err(into_tonic_status)?;
+                let data = bytes.as_deref().map(|bytes| (bytes, compression));
+                let metadata = should_update_metadata.then_some(metadata);
+                let attributes = AttributeChanges::new(remove_attributes, attributes);
+                let released = in_transaction(&instance, &mut db, |db| {
+                    update_blob(db, &external, id, data, metadata.clone(), &attributes)
+                })?;
+                if let Some(path) = released {
+                    external.remove(&path);
                 }
                 yield Ok(blob::UpdateResponse { id });
             }
@@ -377,11 +2119,17 @@ impl BlobBackend for DuckDb {
         request: StreamingRequest<blob::DeleteRequest>,
     ) -> RpcResponse<Self::DeleteStream> {
         let mut stream = request.into_inner();
-        let db = self.connect_blob().map_err(into_tonic_status)?;
+        let mut db = self.connect_blob().map_err(into_tonic_status)?;
+        let instance = Arc::clone(&self.instance);
+        let external = self.external.clone();
         let stream = stream!({
             while let Some(blob::DeleteRequest { id }) = stream.message().await? {
-                db.execute("DELETE FROM blob WHERE id = ?", [id])
-                    .map_err(into_tonic_status)?;
+                let released = in_transaction(&instance, &mut db, |db| {
+                    delete_blob(db, id).map_err(into_tonic_status)
+                })?;
+                if let Some(path) = released {
+                    external.remove(&path);
+                }
                 yield Ok(blob::DeleteResponse { id });
             }
         })
@@ -396,13 +2144,14 @@ impl BlobBackend for DuckDb {
 
         let stream = Box::pin(stream!({
             while let Some(blob::EqDataRequest { id }) = stream.message().await? {
-                let data = db
-                    .query_row("SELECT data FROM blob WHERE id = ?", [id], |row| {
+                // BLOBs with identical data have identical digests, so the data need not be read.
+                let digest = db
+                    .query_row("SELECT digest FROM blob WHERE id = ?", [id], |row| {
                         row.get::<_, Vec<u8>>(0)
                     })
                     .map_err(into_tonic_status)?;
 
-                yield Ok::<_, Status>(data);
+                yield Ok::<_, Status>(digest);
             }
         }))
         .instrument(trace_span!("DuckDB blob eq_data query"));
@@ -419,16 +2168,331 @@ impl BlobBackend for DuckDb {
 
         let stream = Box::pin(stream!({
             while let Some(blob::NotEqDataRequest { id }) = stream.message().await? {
-                let data = db
-                    .query_row("SELECT data FROM blob WHERE id = ?", [id], |row| {
+                // BLOBs with identical data have identical digests, so the data need not be read.
+                let digest = db
+                    .query_row("SELECT digest FROM blob WHERE id = ?", [id], |row| {
                         row.get::<_, Vec<u8>>(0)
                     })
                     .map_err(into_tonic_status)?;
 
-                yield Ok::<_, Status>(data);
+                yield Ok::<_, Status>(digest);
             }
         }))
         .instrument(trace_span!("DuckDB blob not_eq_data query"));
         Ok(Response::new(helpers::all_not_eq(stream).await?))
     }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument)]
+    async fn upload(
+        &self,
+        request: StreamingRequest<blob::UploadRequest>,
+    ) -> RpcResponse<Self::UploadStream> {
+        let mut stream = request.into_inner();
+        let mut db = self.connect_blob().map_err(into_tonic_status)?;
+        let instance = Arc::clone(&self.instance);
+        let external = self.external.clone();
+
+        let stream = stream!({
+            // Chunks are staged in a temporary table, which is private to this connection and is
+            // discarded along with it if the upload is never finalized.
+            db.execute_batch(
+                "CREATE TEMP TABLE IF NOT EXISTS blob_upload(
+                    position BIGINT PRIMARY KEY,
+                    data BLOB NOT NULL
+                );",
+            )
+            .map_err(into_tonic_status)?;
+            let mut upload = helpers::Upload::default();
+
+            while let Some(blob::UploadRequest { part }) = stream.message().await? {
+                match part {
+                    Some(upload_request::Part::Chunk(chunk)) => {
+                        let offset = upload.accept(&chunk)?;
+                        if !chunk.bytes.is_empty() {
+                            db.execute(
+                                "INSERT INTO temp.blob_upload(position, data) VALUES(?, ?)",
+                                params2(offset, chunk.bytes),
+                            )
+                            .map_err(into_tonic_status)?;
+                        }
+                    }
+                    Some(upload_request::Part::Finalize(blob::Finalize {
+                        metadata,
+                        attributes,
+                        compression,
+                    })) => {
+                        let compression =
+                            Compression::try_from(compression).map_err(into_tonic_status)?;
+                        let (size, digest) = std::mem::take(&mut upload).finish();
+                        let attributes = helpers::encode_attributes(attributes);
+                        let id = in_transaction(&instance, &mut db, |db| {
+                            finalize_upload(
+                                db,
+                                &external,
+                                size,
+                                &digest,
+                                compression,
+                                metadata.clone(),
+                                &attributes,
+                            )
+                        })?;
+                        yield Ok(blob::UploadResponse { id, size, digest });
+                    }
+                    None => Err(Status::invalid_argument(
+                        "no chunk or finalization was provided",
+                    ))?,
+                }
+            }
+        })
+        .instrument(trace_span!("DuckDB blob upload query"));
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument)]
+    async fn download(
+        &self,
+        request: StreamingRequest<blob::DownloadRequest>,
+    ) -> RpcResponse<Self::DownloadStream> {
+        let mut stream = request.into_inner();
+        let db = self.connect_blob().map_err(into_tonic_status)?;
+        let external = self.external.clone();
+
+        let stream = stream!({
+            while let Some(blob::DownloadRequest { id, chunk_size }) = stream.message().await? {
+                // Each BLOB is read within a transaction, so that its chunks are all read from the
+                // same snapshot of the database. If the stream ends before the BLOB has been read,
+                // the connection is closed with the transaction still open, which ends it.
+                db.execute_batch("BEGIN TRANSACTION")
+                    .map_err(into_tonic_status)?;
+                let (content, metadata) = blob_content(&db, id)?;
+                // Compressed content is decompressed in full before being split into chunks.
+                // Uncompressed content is verified as its chunks are read.
+                let (data, mut verifier) = if content.is_uncompressed() {
+                    (None, Some(helpers::Verifier::default()))
+                } else {
+                    (Some(read_content(&db, &external, &content)?), None)
+                };
+                let chunks = helpers::chunks(content.size, chunk_size)?;
+                let attributes = read_attributes(&db, id)?;
+
+                yield Ok(helpers::download_info(
+                    id,
+                    content.size,
+                    content.digest.clone(),
+                    metadata,
+                    attributes,
+                ));
+                for (offset, len) in chunks {
+                    let bytes = match &data {
+                        Some(data) => data[offset as usize..][..len].to_vec(),
+                        None => read_content_range(&db, &external, &content, offset, len)?,
+                    };
+                    if let Some(verifier) = &mut verifier {
+                        verifier.update(&bytes);
+                    }
+                    yield Ok(helpers::download_chunk(id, offset, bytes));
+                }
+                db.execute_batch("COMMIT").map_err(into_tonic_status)?;
+                if let Some(verifier) = verifier {
+                    verifier.finish(&content.digest)?;
+                }
+            }
+        })
+        .instrument(trace_span!("DuckDB blob download query"));
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument)]
+    async fn list(
+        &self,
+        request: tonic::Request<blob::ListRequest>,
+    ) -> RpcResponse<Self::ListStream> {
+        let blob::ListRequest {
+            metadata,
+            metadata_prefix,
+            start_id,
+            end_id,
+            limit,
+            attributes,
+        } = request.into_inner();
+        let db = self.connect_blob().map_err(into_tonic_status)?;
+
+        let stream = stream!({
+            let mut query = "SELECT blob.id, blob.metadata, blob_content.size
+                FROM blob JOIN blob_content USING (digest)
+                WHERE blob.id >= $1 AND ($2 IS NULL OR blob.id < $2)
+                    AND ($3 IS NULL OR blob.metadata = $3)
+                    AND ($4 IS NULL OR starts_with(blob.metadata, $4))"
+                .to_owned();
+            let mut params: Vec<duckdb::types::Value> = vec![
+                start_id.unwrap_or(0).into(),
+                end_id.into(),
+                metadata.into(),
+                metadata_prefix.into(),
+            ];
+            for (name, value) in helpers::encode_attributes(attributes) {
+                query.push_str(&format!(
+                    " AND EXISTS (SELECT * FROM blob_attribute
+                        WHERE blob_id = blob.id AND name = ${} AND value = ${})",
+                    params.len() + 1,
+                    params.len() + 2,
+                ));
+                params.extend([name.into(), value.into()]);
+            }
+            query.push_str(&format!(" ORDER BY blob.id LIMIT ${}", params.len() + 1));
+            let mut remaining = limit.map_or(usize::MAX, |limit| {
+                usize::try_from(limit).unwrap_or(usize::MAX)
+            });
+            // The listing is collected in pages, each continuing after the last BLOB of the
+            // previous page, all within one transaction so that the listing is consistent.
+            db.execute_batch("BEGIN TRANSACTION")
+                .map_err(into_tonic_status)?;
+            while remaining > 0 {
+                let page_size = remaining.min(helpers::LIST_PAGE_SIZE);
+                let page_params = params.iter().cloned().chain([(page_size as i64).into()]);
+                let blobs = db
+                    .prepare(&query)
+                    .and_then(|mut statement| {
+                        statement
+                            .query_map(duckdb::params_from_iter(page_params), |row| {
+                                Ok(blob::ListResponse {
+                                    id: row.get(0)?,
+                                    metadata: row.get(1)?,
+                                    size: row.get(2)?,
+                                    attributes: None,
+                                })
+                            })?
+                            .collect::<Result<Vec<_>, _>>()
+                    })
+                    .map_err(into_tonic_status)?;
+                remaining -= blobs.len();
+                let next_id = blobs
+                    .last()
+                    .filter(|_| blobs.len() == page_size)
+                    .and_then(|blob| blob.id.checked_add(1));
+                for mut blob in blobs {
+                    blob.attributes = read_attributes(&db, blob.id)?;
+                    yield Ok(blob);
+                }
+                let Some(next_id) = next_id else {
+                    break;
+                };
+                params[0] = next_id.into();
+            }
+            db.execute_batch("COMMIT").map_err(into_tonic_status)?;
+        })
+        .instrument(trace_span!("DuckDB blob list query"));
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument)]
+    async fn copy(
+        &self,
+        request: StreamingRequest<blob::CopyRequest>,
+    ) -> RpcResponse<Self::CopyStream> {
+        let mut stream = request.into_inner();
+        let mut db = self.connect_blob().map_err(into_tonic_status)?;
+        let instance = Arc::clone(&self.instance);
+
+        let stream = stream!({
+            while let Some(blob::CopyRequest {
+                id,
+                should_update_metadata,
+                metadata,
+            }) = stream.message().await?
+            {
+                let metadata = should_update_metadata.then_some(metadata);
+                let id =
+                    in_transaction(&instance, &mut db, |db| copy_blob(db, id, metadata.clone()))?;
+                yield Ok(blob::CopyResponse { id });
+            }
+        })
+        .instrument(trace_span!("DuckDB blob copy query"));
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument)]
+    async fn append(
+        &self,
+        request: StreamingRequest<blob::AppendRequest>,
+    ) -> RpcResponse<Self::AppendStream> {
+        let mut stream = request.into_inner();
+        let mut db = self.connect_blob().map_err(into_tonic_status)?;
+        let instance = Arc::clone(&self.instance);
+        let external = self.external.clone();
+
+        let stream = stream!({
+            while let Some(blob::AppendRequest {
+                id,
+                bytes,
+                compression,
+            }) = stream.message().await?
+            {
+                let compression = Compression::try_from(compression).map_err(into_tonic_status)?;
+                let (size, released) = in_transaction(&instance, &mut db, |db| {
+                    append_blob(db, &external, id, &bytes, compression)
+                })?;
+                if let Some(path) = released {
+                    external.remove(&path);
+                }
+                yield Ok(blob::AppendResponse { id, size });
+            }
+        })
+        .instrument(trace_span!("DuckDB blob append query"));
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument)]
+    async fn verify(
+        &self,
+        _request: tonic::Request<blob::VerifyRequest>,
+    ) -> RpcResponse<Self::VerifyStream> {
+        let db = self.connect_blob().map_err(into_tonic_status)?;
+        let external = self.external.clone();
+
+        let stream = stream!({
+            // The result of verifying content shared by several BLOBs, by its digest.
+            let mut verified = HashMap::<Vec<u8>, Option<String>>::new();
+            let mut start_id = 0;
+            // The BLOBs are read in pages, each continuing after the last BLOB of the previous
+            // page, all within one transaction so that content is not deleted while it is read.
+            db.execute_batch("BEGIN TRANSACTION")
+                .map_err(into_tonic_status)?;
+            loop {
+                let blobs = blobs_to_verify(&db, start_id, helpers::LIST_PAGE_SIZE)
+                    .map_err(into_tonic_status)?;
+                let next_id = blobs
+                    .last()
+                    .filter(|_| blobs.len() == helpers::LIST_PAGE_SIZE)
+                    .and_then(|&(id, _)| id.checked_add(1));
+                for (id, content) in blobs {
+                    let Some((content, refs)) = content else {
+                        yield Ok(helpers::missing_content(id));
+                        continue;
+                    };
+                    let message = match verified.get(&content.digest) {
+                        Some(message) => message.clone(),
+                        None => {
+                            let message = content_corruption(&db, &external, &content)?;
+                            // Content referred to by a single BLOB is never seen again.
+                            if refs > 1 {
+                                let _previous = verified.insert(content.digest, message.clone());
+                            }
+                            message
+                        }
+                    };
+                    if let Some(message) = message {
+                        yield Ok(blob::VerifyResponse { id, message });
+                    }
+                }
+                let Some(next_id) = next_id else {
+                    break;
+                };
+                start_id = next_id;
+            }
+            db.execute_batch("COMMIT").map_err(into_tonic_status)?;
+        })
+        .instrument(trace_span!("DuckDB blob verify query"));
+        Ok(Response::new(Box::pin(stream)))
+    }
 }
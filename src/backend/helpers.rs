@@ -1,6 +1,43 @@
+use crate::proto::blob::{self, download_response, Compression};
+use crate::proto::kv::set_request::Expiration;
+use crate::proto::kv::watch_request::Target;
+use crate::proto::kv::{
+    self, compare_and_swap_request, increment_request, ChangeKind, CompareAndSwapFailure,
+};
+use crate::Location;
+use async_stream::stream;
 use futures::{Stream, StreamExt as _};
+use prost::Message as _;
 use sha2::{Digest as _, Sha256};
-use std::collections::BTreeSet;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::{self, File};
+use std::io::{self, Read as _, Seek as _, SeekFrom, Write as _};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tonic::metadata::MetadataMap;
+use tonic::{Code, Status};
+
+/// How often expired key-value pairs are removed from a store, in milliseconds.
+const SWEEP_INTERVAL_MILLIS: i64 = 60_000;
+/// How many times the removal of expired key-value pairs is attempted before it is left to the
+/// next sweep.
+const SWEEP_ATTEMPTS: u32 = 10;
+/// How long to wait before retrying a failed removal of expired key-value pairs.
+const SWEEP_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+/// The number of changes that may be buffered for a watcher before it is considered to have
+/// fallen behind.
+const WATCH_CAPACITY: usize = 1_024;
+/// The maximum length of a namespace name.
+const MAX_NAMESPACE_LEN: usize = 64;
+/// The size of each chunk of a BLOB being downloaded if the client does not specify one, in bytes.
+const DEFAULT_CHUNK_SIZE: u32 = 1 << 20;
+/// The maximum size of each chunk of a BLOB being downloaded, in bytes. This leaves ample room
+/// below tonic's default message size limit of 4 MiB.
+const MAX_CHUNK_SIZE: u32 = 3 << 20;
 
 #[cfg_attr(feature = "tracing", tracing::instrument(skip(stream)))]
 pub(super) async fn all_eq<S, T, E>(mut stream: S) -> Result<bool, E>
@@ -44,3 +81,1005 @@ where
 
     Ok(true)
 }
+
+/// The range of keys requested by a scan.
+#[derive(Debug)]
+pub(super) struct KeyRange {
+    /// The inclusive lower bound. This is never less than the prefix.
+    pub(super) start: String,
+    /// The exclusive upper bound, if any.
+    pub(super) end: Option<String>,
+    /// The prefix that all keys must begin with. This is empty if there is no prefix.
+    pub(super) prefix: String,
+}
+
+impl KeyRange {
+    pub(super) fn new(prefix: Option<String>, start: Option<String>, end: Option<String>) -> Self {
+        let prefix = prefix.unwrap_or_default();
+        // Any key beginning with the prefix is at least the prefix itself, so the lower bound can be
+        // tightened without changing the result.
+        let start = match start {
+            Some(start) if start > prefix => start,
+            _ => prefix.clone(),
+        };
+        Self { start, end, prefix }
+    }
+}
+
+/// The largest number of BLOBs collected by a listing at once. Listings are collected in pages of at
+/// most this many BLOBs, each of which is sent before the next is collected.
+pub(super) const LIST_PAGE_SIZE: usize = 256;
+
+/// The `LIMIT` clause of a SQL query, if any. The clause is preceded by a space.
+#[cfg(any(feature = "duckdb", feature = "sqlite"))]
+pub(super) fn limit_clause(limit: Option<u64>) -> String {
+    match limit {
+        // Both SQLite and DuckDB treat the limit as a signed 64-bit integer.
+        Some(limit) => format!(" LIMIT {}", i64::try_from(limit).unwrap_or(i64::MAX)),
+        None => String::new(),
+    }
+}
+
+/// The current time in milliseconds since the Unix epoch.
+pub(super) fn now_millis() -> i64 {
+    let millis = match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_millis(),
+        // The system clock is before the Unix epoch. Nothing reasonable can be done.
+        Err(_) => 0,
+    };
+    i64::try_from(millis).unwrap_or(i64::MAX)
+}
+
+/// Convert a time in milliseconds since the Unix epoch to a timestamp.
+pub(super) fn timestamp(millis: i64) -> prost_types::Timestamp {
+    prost_types::Timestamp {
+        seconds: millis.div_euclid(1_000),
+        nanos: i32::try_from(millis.rem_euclid(1_000)).unwrap_or_default() * 1_000_000,
+    }
+}
+
+/// Determine when a key-value pair expires, in milliseconds since the Unix epoch.
+///
+/// Returns `None` if the pair never expires.
+pub(super) fn expires_at(expiration: Option<Expiration>, now: i64) -> Result<Option<i64>, Status> {
+    let millis = |seconds: i64, nanos: i32| {
+        seconds
+            .saturating_mul(1_000)
+            .saturating_add(i64::from(nanos) / 1_000_000)
+    };
+
+    match expiration {
+        None => Ok(None),
+        Some(Expiration::Ttl(ttl)) => {
+            if ttl.seconds < 0 || ttl.nanos < 0 {
+                return Err(Status::invalid_argument("ttl cannot be negative"));
+            }
+            Ok(Some(now.saturating_add(millis(ttl.seconds, ttl.nanos))))
+        }
+        Some(Expiration::ExpiresAt(timestamp)) => {
+            Ok(Some(millis(timestamp.seconds, timestamp.nanos)))
+        }
+    }
+}
+
+impl From<compare_and_swap_request::Expiration> for Expiration {
+    fn from(expiration: compare_and_swap_request::Expiration) -> Self {
+        match expiration {
+            compare_and_swap_request::Expiration::Ttl(ttl) => Self::Ttl(ttl),
+            compare_and_swap_request::Expiration::ExpiresAt(timestamp) => {
+                Self::ExpiresAt(timestamp)
+            }
+        }
+    }
+}
+
+impl From<increment_request::Expiration> for Expiration {
+    fn from(expiration: increment_request::Expiration) -> Self {
+        match expiration {
+            increment_request::Expiration::Ttl(ttl) => Self::Ttl(ttl),
+            increment_request::Expiration::ExpiresAt(timestamp) => Self::ExpiresAt(timestamp),
+        }
+    }
+}
+
+/// The response to a get of `key`, which has the given value and version.
+///
+/// A key that does not exist is an error unless the request allows it to be missing.
+pub(super) fn get_response(
+    key: &str,
+    value: Option<(Vec<u8>, u64)>,
+    allow_missing: bool,
+) -> Result<kv::GetResponse, Status> {
+    match value {
+        Some((value, version)) => Ok(kv::GetResponse {
+            value: Some(value),
+            version,
+        }),
+        None if allow_missing => Ok(kv::GetResponse {
+            value: None,
+            version: 0,
+        }),
+        None => Err(Status::not_found(format!("key {key} not found"))),
+    }
+}
+
+/// The value of `key` after adding `delta` to its current value, which must be a base-10 integer.
+/// A key that does not exist is treated as having a value of zero.
+pub(super) fn increment(
+    key: &str,
+    current_value: Option<&[u8]>,
+    delta: i64,
+) -> Result<i64, Status> {
+    let current = match current_value {
+        Some(value) => std::str::from_utf8(value)
+            .ok()
+            .and_then(|value| value.parse::<i64>().ok())
+            .ok_or_else(|| {
+                Status::failed_precondition(format!("value of key {key} is not an integer"))
+            })?,
+        None => 0,
+    };
+    current
+        .checked_add(delta)
+        .ok_or_else(|| Status::out_of_range(format!("adding {delta} to key {key} would overflow")))
+}
+
+/// The status returned when a compare-and-swap fails because the key does not have the expected
+/// value. The current value is included in the details of the status.
+pub(super) fn compare_and_swap_failure(key: &str, current_value: Option<Vec<u8>>) -> Status {
+    Status::with_details(
+        Code::FailedPrecondition,
+        format!("key {key} does not have the expected value"),
+        CompareAndSwapFailure { current_value }
+            .encode_to_vec()
+            .into(),
+    )
+}
+
+/// The operations of a KV transaction that has not yet been committed.
+///
+/// Writes are buffered rather than applied to a database transaction left open while the client
+/// sends further operations, which would block every other writer for as long as the client takes.
+/// The value of each key read from the store is instead recorded, and the commit fails if any of
+/// them has been written since.
+#[derive(Debug, Default)]
+pub(super) struct BufferedTransaction {
+    /// The value and version of each key read from the store, as first observed.
+    reads: HashMap<String, Option<(Vec<u8>, u64)>>,
+    /// The writes made in the transaction, in order.
+    writes: Vec<BufferedWrite>,
+}
+
+/// A write made in a transaction that has not yet been committed.
+#[derive(Debug)]
+struct BufferedWrite {
+    /// The key that was written.
+    key: String,
+    /// The value that was set and when it expires, in milliseconds since the Unix epoch, or `None`
+    /// if the key was deleted.
+    value: Option<(Vec<u8>, Option<i64>)>,
+}
+
+/// Access to a KV store within a database transaction, through which a [`BufferedTransaction`] is
+/// committed.
+pub(super) trait TransactionTarget {
+    /// The current value and version of `key`, or `None` if it does not exist.
+    fn current(&mut self, key: &str) -> Result<Option<(Vec<u8>, u64)>, Status>;
+
+    /// Set `key` to `value`, returning the version of the write.
+    fn put(&mut self, key: &str, value: &[u8], expires_at: Option<i64>) -> Result<u64, Status>;
+
+    /// Delete `key`, if it exists.
+    fn delete(&mut self, key: &str) -> Result<(), Status>;
+}
+
+impl BufferedTransaction {
+    /// The value and version of `key`, observing the writes made earlier in the transaction.
+    /// Otherwise, `read` is called to get the value from the store, at `version` if provided.
+    ///
+    /// Versions are assigned when the transaction is committed, so a value written in the
+    /// transaction has a version of zero.
+    pub(super) fn get(
+        &mut self,
+        key: &str,
+        version: Option<u64>,
+        read: impl FnOnce() -> Result<Option<(Vec<u8>, u64)>, Status>,
+    ) -> Result<Option<(Vec<u8>, u64)>, Status> {
+        // Earlier versions of a key never change, so they cannot conflict with the transaction.
+        if version.is_some() {
+            return read();
+        }
+        if let Some(write) = self.writes.iter().rev().find(|write| write.key == key) {
+            return Ok(match &write.value {
+                Some((value, expires_at))
+                    if expires_at.is_none_or(|expires_at| expires_at > now_millis()) =>
+                {
+                    Some((value.clone(), 0))
+                }
+                _ => None,
+            });
+        }
+        if let Some(value) = self.reads.get(key) {
+            return Ok(value.clone());
+        }
+        let value = read()?;
+        let _previous = self.reads.insert(key.to_owned(), value.clone());
+        Ok(value)
+    }
+
+    /// Set `key` to `value` when the transaction is committed.
+    pub(super) fn set(&mut self, key: String, value: Vec<u8>, expires_at: Option<i64>) {
+        self.writes.push(BufferedWrite {
+            key,
+            value: Some((value, expires_at)),
+        });
+    }
+
+    /// Delete `key` when the transaction is committed.
+    pub(super) fn delete(&mut self, key: String) {
+        self.writes.push(BufferedWrite { key, value: None });
+    }
+
+    /// Apply the transaction to `target`, which must be accessed within a database transaction.
+    ///
+    /// Fails with an `ABORTED` status if any key read by the transaction has been written since it
+    /// was read. Otherwise, returns the version of each set in order, along with the changes to
+    /// notify watchers of once the database transaction is committed if there are any `watched`.
+    pub(super) fn apply(
+        &self,
+        target: &mut impl TransactionTarget,
+        watched: bool,
+    ) -> Result<(Vec<u64>, Vec<kv::WatchResponse>), Status> {
+        for (key, observed) in &self.reads {
+            if target.current(key)? != *observed {
+                return Err(Status::aborted(format!(
+                    "key {key} was written after being read by the transaction"
+                )));
+            }
+        }
+        let mut versions = Vec::new();
+        let mut changes = Vec::new();
+        for BufferedWrite { key, value } in &self.writes {
+            let old_value = if watched {
+                target.current(key)?.map(|(value, _)| value)
+            } else {
+                None
+            };
+            match value {
+                Some((value, expires_at)) => {
+                    versions.push(target.put(key, value, *expires_at)?);
+                    if watched {
+                        changes.push(put_change(key.clone(), old_value, value.clone()));
+                    }
+                }
+                None => {
+                    target.delete(key)?;
+                    changes
+                        .extend(old_value.map(|old_value| delete_change(key.clone(), old_value)));
+                }
+            }
+        }
+        Ok((versions, changes))
+    }
+}
+
+/// The status returned when the number of values provided for the parameters of a query does not
+/// match the number of parameters.
+pub(super) fn param_count_mismatch(expected: usize, provided: usize) -> Status {
+    Status::invalid_argument(format!(
+        "query has {expected} parameters, but {provided} values were provided"
+    ))
+}
+
+/// The path of a database that is to be attached to another connection.
+///
+/// Only databases on disk can be attached, as an in-memory database is private to its connection.
+pub(super) fn attachable_path(location: &Location) -> Result<&str, Status> {
+    match location {
+        Location::InMemory => Err(Status::failed_precondition(
+            "an in-memory database cannot be attached",
+        )),
+        Location::OnDisk { path } => path.to_str().ok_or_else(|| {
+            Status::failed_precondition("the path of the attached database is not valid UTF-8")
+        }),
+    }
+}
+
+/// The SHA-256 digest of the data of a BLOB, which identifies its content.
+pub(super) fn digest(bytes: &[u8]) -> Vec<u8> {
+    Sha256::digest(bytes).to_vec()
+}
+
+/// The status returned when data read in full does not match the digest it was stored with.
+fn checksum_mismatch() -> Status {
+    Status::data_loss("stored data does not match its digest")
+}
+
+/// Verify that data read in full matches the digest it was stored with.
+pub(super) fn verify(data: &[u8], digest: &[u8]) -> Result<(), Status> {
+    if Sha256::digest(data)[..] == *digest {
+        Ok(())
+    } else {
+        Err(checksum_mismatch())
+    }
+}
+
+/// Verifies data read in chunks against the digest it was stored with.
+#[derive(Debug, Default)]
+pub(super) struct Verifier {
+    /// The digest of the chunks read so far.
+    hasher: Sha256,
+}
+
+impl Verifier {
+    /// Add the next chunk of the data.
+    pub(super) fn update(&mut self, chunk: &[u8]) {
+        self.hasher.update(chunk);
+    }
+
+    /// Verify that the chunks read make up data matching the digest.
+    pub(super) fn finish(self, digest: &[u8]) -> Result<(), Status> {
+        if self.hasher.finalize()[..] == *digest {
+            Ok(())
+        } else {
+            Err(checksum_mismatch())
+        }
+    }
+
+    /// Verify that the chunks read make up data matching the digest, returning the digest of that
+    /// data once `appended` is added to its end.
+    pub(super) fn finish_appending(
+        self,
+        digest: &[u8],
+        appended: &[u8],
+    ) -> Result<Vec<u8>, Status> {
+        let mut hasher = self.hasher.clone();
+        self.finish(digest)?;
+        hasher.update(appended);
+        Ok(hasher.finalize().to_vec())
+    }
+}
+
+/// Decode a stored string, such as a key or the name of an attribute. Strings are only ever stored
+/// as valid UTF-8, so anything else indicates corruption.
+pub(super) fn stored_string(bytes: Vec<u8>, what: &str) -> Result<String, Status> {
+    String::from_utf8(bytes).map_err(|_| Status::data_loss(format!("a stored {what} is not UTF-8")))
+}
+
+/// Determine whether reading a content found it to be corrupted, returning a description of the
+/// problem if so. Errors other than data loss are returned as is.
+pub(super) fn corruption<T>(read: Result<T, Status>) -> Result<Option<String>, Status> {
+    match read {
+        Ok(_) => Ok(None),
+        Err(status) if status.code() == Code::DataLoss => Ok(Some(status.message().to_owned())),
+        Err(status) => Err(status),
+    }
+}
+
+/// The status returned when a BLOB does not exist.
+pub(super) fn blob_not_found(id: u64) -> Status {
+    Status::not_found(format!("id {id} not found"))
+}
+
+/// Split uncompressed content stored in a database into chunks of at most this many bytes, so that
+/// no single value has to hold all of it. Content processed in full without being held in memory is
+/// read in chunks of the same size.
+pub(super) const CONTENT_CHUNK_SIZE: usize = 1 << 20;
+
+/// Assemble `len` bytes of a content beginning at `offset` from the stored chunks that overlap
+/// them, given in order along with the position of each.
+///
+/// A gap between the chunks means that part of the content is missing, which is data loss.
+#[cfg(any(feature = "duckdb", feature = "sqlite"))]
+pub(super) fn assemble_range(
+    chunks: impl IntoIterator<Item = (u64, Vec<u8>)>,
+    offset: u64,
+    len: usize,
+) -> Result<Vec<u8>, Status> {
+    let mut bytes = Vec::with_capacity(len);
+    for (position, chunk) in chunks {
+        let next = offset + bytes.len() as u64;
+        if bytes.len() == len || position > next {
+            break;
+        }
+        let start = usize::try_from(next - position).unwrap_or(usize::MAX);
+        if let Some(chunk) = chunk.get(start..) {
+            bytes.extend_from_slice(&chunk[..chunk.len().min(len - bytes.len())]);
+        }
+    }
+    if bytes.len() == len {
+        Ok(bytes)
+    } else {
+        Err(Status::data_loss("stored data is missing a chunk"))
+    }
+}
+
+/// The response reporting a BLOB whose content is missing entirely.
+pub(super) fn missing_content(id: u64) -> blob::VerifyResponse {
+    blob::VerifyResponse {
+        id,
+        message: "stored data is missing".to_owned(),
+    }
+}
+
+/// Compress the data of a BLOB with the requested algorithm, returning the algorithm used and the
+/// data as it is to be stored.
+///
+/// The data is stored uncompressed if compressing it does not make it smaller, which allows ranges
+/// of it to be read without reading it in full.
+pub(super) fn compress(data: &[u8], compression: Compression) -> (Compression, Cow<'_, [u8]>) {
+    let compressed = match compression {
+        Compression::None => None,
+        Compression::Zstd => zstd::bulk::compress(data, zstd::DEFAULT_COMPRESSION_LEVEL).ok(),
+        Compression::Lz4 => Some(lz4_flex::compress(data)),
+    };
+    match compressed {
+        Some(compressed) if compressed.len() < data.len() => (compression, Cow::Owned(compressed)),
+        _ => (Compression::None, Cow::Borrowed(data)),
+    }
+}
+
+/// Decompress data that was stored with the given algorithm. The size is that of the data once
+/// decompressed, which the decompressed data is checked against, so that callers can rely on it.
+pub(super) fn decompress(bytes: Vec<u8>, compression: i32, size: u64) -> Result<Vec<u8>, Status> {
+    let size = usize::try_from(size)
+        .map_err(|_| Status::out_of_range("BLOB is too large to decompress"))?;
+    let data = match Compression::try_from(compression) {
+        Ok(Compression::None) => Ok(bytes),
+        Ok(Compression::Zstd) => {
+            zstd::bulk::decompress(&bytes, size).map_err(|err| err.to_string())
+        }
+        Ok(Compression::Lz4) => lz4_flex::decompress(&bytes, size).map_err(|err| err.to_string()),
+        Err(_) => Err(format!("unknown compression algorithm {compression}")),
+    };
+    let data = data.map_err(|err| {
+        Status::data_loss(format!("stored data could not be decompressed: {err}"))
+    })?;
+    if data.len() != size {
+        return Err(Status::data_loss(format!(
+            "stored data is {} bytes, but the BLOB is {size} bytes",
+            data.len()
+        )));
+    }
+    Ok(data)
+}
+
+/// The files storing the data of large BLOBs outside of the database, in a directory alongside it.
+///
+/// Data is only stored in files once a size threshold is set, and never for in-memory databases.
+/// Each file is named after the digest of its content, followed by a suffix that makes the name
+/// unique. This ensures that removing the file of content that is no longer referenced can never
+/// remove a file written for the same content afterwards.
+///
+/// Files are written before the transaction referring to them is committed and removed only after
+/// the transaction releasing them is committed, so a failed transaction can leave behind a file
+/// that is never referenced, but never a reference to a missing file.
+#[derive(Debug, Clone)]
+pub(super) struct ExternalFiles {
+    /// The directory the files are stored in, or `None` if the database is in memory.
+    dir: Option<Arc<Path>>,
+    /// The size above which data is stored in a file, in bytes. Data is never stored in a file if
+    /// this is `u64::MAX`.
+    threshold: Arc<AtomicU64>,
+}
+
+impl ExternalFiles {
+    /// The files for the database at `location`. The directory is named after the database, with
+    /// `-blobs` appended.
+    pub(super) fn new(location: &Location) -> Self {
+        let dir = match location {
+            Location::InMemory => None,
+            Location::OnDisk { path } => {
+                let mut dir = path.clone().into_os_string();
+                dir.push("-blobs");
+                Some(PathBuf::from(dir).into())
+            }
+        };
+        Self {
+            dir,
+            threshold: Arc::new(AtomicU64::new(u64::MAX)),
+        }
+    }
+
+    /// Store data larger than `threshold` bytes in files, or no data if the threshold is `None`.
+    pub(super) fn set_threshold(&self, threshold: Option<u64>) {
+        self.threshold
+            .store(threshold.unwrap_or(u64::MAX), Ordering::Relaxed);
+    }
+
+    /// The path of the file with the given name.
+    fn path(&self, name: &str) -> Result<PathBuf, Status> {
+        match &self.dir {
+            Some(dir) => Ok(dir.join(name)),
+            None => Err(Status::data_loss(format!(
+                "BLOB file {name} cannot be stored alongside an in-memory database"
+            ))),
+        }
+    }
+
+    /// Store `len` bytes of data in a new file if it is larger than the threshold, returning the
+    /// name of the file. The data is written to the file by `write`.
+    ///
+    /// Returns `None` without calling `write` if the data is to be stored in the database.
+    pub(super) fn write_with(
+        &self,
+        digest: &[u8],
+        len: u64,
+        write: impl FnOnce(&mut File) -> Result<(), Status>,
+    ) -> Result<Option<String>, Status> {
+        let Some(dir) = &self.dir else {
+            return Ok(None);
+        };
+        if len <= self.threshold.load(Ordering::Relaxed) {
+            return Ok(None);
+        }
+        fs::create_dir_all(dir).map_err(file_status)?;
+
+        let digest = digest
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<String>();
+        let mut suffix = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_nanos() as u64);
+        let (name, mut file) = loop {
+            let name = format!("{digest}-{suffix:016x}");
+            match File::create_new(dir.join(&name)) {
+                Ok(file) => break (name, file),
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                    suffix = suffix.wrapping_add(1);
+                }
+                Err(err) => return Err(file_status(err)),
+            }
+        };
+        let written = write(&mut file).and_then(|()| file.sync_all().map_err(file_status));
+        if let Err(status) = written {
+            self.remove(&name);
+            return Err(status);
+        }
+        Ok(Some(name))
+    }
+
+    /// Store data in a new file if it is larger than the threshold, returning the name of the file.
+    ///
+    /// Returns `None` if the data is to be stored in the database.
+    pub(super) fn write(&self, digest: &[u8], data: &[u8]) -> Result<Option<String>, Status> {
+        self.write_with(digest, data.len() as u64, |file| {
+            file.write_all(data).map_err(file_status)
+        })
+    }
+
+    /// Read the file with the given name in full.
+    pub(super) fn read(&self, name: &str) -> Result<Vec<u8>, Status> {
+        fs::read(self.path(name)?).map_err(file_status)
+    }
+
+    /// Read `len` bytes of the file with the given name, beginning at `offset`.
+    pub(super) fn read_range(
+        &self,
+        name: &str,
+        offset: u64,
+        len: usize,
+    ) -> Result<Vec<u8>, Status> {
+        let mut file = File::open(self.path(name)?).map_err(file_status)?;
+        let mut bytes = vec![0; len];
+        file.seek(SeekFrom::Start(offset))
+            .and_then(|_| file.read_exact(&mut bytes))
+            .map_err(file_status)?;
+        Ok(bytes)
+    }
+
+    /// Remove the file with the given name once no content refers to it.
+    pub(super) fn remove(&self, name: &str) {
+        // The content referring to the file is already gone, so there is nowhere to report a
+        // failure. A file that is not removed is never read again.
+        if let Ok(path) = self.path(name) {
+            let _res = fs::remove_file(path);
+        }
+    }
+}
+
+/// Convert an error accessing a BLOB file to a status. A missing file is reported as data loss, as
+/// files are only removed once nothing refers to them.
+pub(super) fn file_status(err: io::Error) -> Status {
+    match err.kind() {
+        io::ErrorKind::NotFound | io::ErrorKind::UnexpectedEof => {
+            Status::data_loss(format!("BLOB file is missing or truncated: {err}"))
+        }
+        _ => Status::internal(format!("failed to access BLOB file: {err}")),
+    }
+}
+
+/// An attribute of a BLOB as it is stored: its name and its value, encoded as a protobuf message.
+///
+/// Values with the same encoding are considered equal, which allows attributes to be compared
+/// without being decoded.
+pub(super) type StoredAttribute = (String, Vec<u8>);
+
+/// Encode the attributes of a BLOB for storage.
+pub(super) fn encode_attributes(attributes: Option<prost_types::Struct>) -> Vec<StoredAttribute> {
+    attributes
+        .into_iter()
+        .flat_map(|attributes| attributes.fields)
+        .map(|(name, value)| (name, value.encode_to_vec()))
+        .collect()
+}
+
+/// Decode the stored attributes of a BLOB. Returns `None` if the BLOB has no attributes.
+pub(super) fn decode_attributes(
+    attributes: impl IntoIterator<Item = StoredAttribute>,
+) -> Result<Option<prost_types::Struct>, Status> {
+    let fields = attributes
+        .into_iter()
+        .map(
+            |(name, value)| match prost_types::Value::decode(value.as_slice()) {
+                Ok(value) => Ok((name, value)),
+                Err(err) => Err(Status::data_loss(format!(
+                    "attribute {name} could not be decoded: {err}"
+                ))),
+            },
+        )
+        .collect::<Result<BTreeMap<_, _>, _>>()?;
+    Ok((!fields.is_empty()).then_some(prost_types::Struct { fields }))
+}
+
+/// Changes to the attributes of a BLOB requested by an update.
+#[derive(Debug, Default)]
+pub(super) struct AttributeChanges {
+    /// The names of attributes to remove. No attribute is both removed and set.
+    pub(super) removed: Vec<String>,
+    /// The attributes to add or replace.
+    pub(super) set: Vec<StoredAttribute>,
+}
+
+impl AttributeChanges {
+    /// The changes removing the named attributes and setting the given ones.
+    pub(super) fn new(removed: Vec<String>, set: Option<prost_types::Struct>) -> Self {
+        let set = encode_attributes(set);
+        // Setting an attribute replaces it anyway, and some backends cannot remove and insert the
+        // same key within a transaction.
+        let removed = removed
+            .into_iter()
+            .filter(|name| !set.iter().any(|(set_name, _)| set_name == name))
+            .collect();
+        Self { removed, set }
+    }
+}
+
+/// Tracks the progress of a BLOB being uploaded in chunks.
+#[derive(Debug, Default)]
+pub(super) struct Upload {
+    /// The number of bytes received so far.
+    size: u64,
+    /// The digest of the bytes received so far.
+    hasher: Sha256,
+}
+
+impl Upload {
+    /// Accept the next chunk of the BLOB, which must begin where the previous chunk ended.
+    ///
+    /// Returns the offset at which the chunk is to be written.
+    pub(super) fn accept(&mut self, chunk: &blob::BlobChunk) -> Result<u64, Status> {
+        if chunk.offset != self.size {
+            return Err(Status::invalid_argument(format!(
+                "expected a chunk at offset {}, but received one at offset {}",
+                self.size, chunk.offset
+            )));
+        }
+        self.size = self
+            .size
+            .checked_add(chunk.bytes.len() as u64)
+            .ok_or_else(|| Status::out_of_range("BLOB is too large"))?;
+        self.hasher.update(&chunk.bytes);
+        Ok(chunk.offset)
+    }
+
+    /// Finish receiving the BLOB, returning its size and digest.
+    pub(super) fn finish(self) -> (u64, Vec<u8>) {
+        (self.size, self.hasher.finalize().to_vec())
+    }
+}
+
+/// The position and length of the range of a BLOB with the given size requested by a get.
+pub(super) fn byte_range(
+    size: u64,
+    offset: Option<u64>,
+    length: Option<u64>,
+) -> Result<(u64, usize), Status> {
+    let offset = offset.unwrap_or(0);
+    let Some(remaining) = size.checked_sub(offset) else {
+        return Err(Status::out_of_range(format!(
+            "offset {offset} is past the end of the BLOB, which is {size} bytes"
+        )));
+    };
+    let len = length.map_or(remaining, |length| length.min(remaining));
+    let len = usize::try_from(len)
+        .map_err(|_| Status::out_of_range("requested range is too large to return"))?;
+    Ok((offset, len))
+}
+
+/// The position and length of each chunk of a BLOB with the given size, as requested by a
+/// download.
+pub(super) fn chunks(
+    size: u64,
+    chunk_size: Option<u32>,
+) -> Result<impl Iterator<Item = (u64, usize)>, Status> {
+    let chunk_size = chunk_size.unwrap_or(DEFAULT_CHUNK_SIZE);
+    if chunk_size == 0 || chunk_size > MAX_CHUNK_SIZE {
+        return Err(Status::invalid_argument(format!(
+            "chunk size must be between 1 and {MAX_CHUNK_SIZE} bytes"
+        )));
+    }
+    let chunk_size = u64::from(chunk_size);
+    Ok((0..size).step_by(chunk_size as usize).map(move |offset| {
+        // The length is at most the chunk size, which fits in a `u32`.
+        (offset, chunk_size.min(size - offset) as usize)
+    }))
+}
+
+/// The response preceding the data of a BLOB being downloaded.
+pub(super) const fn download_info(
+    id: u64,
+    size: u64,
+    digest: Vec<u8>,
+    metadata: Option<String>,
+    attributes: Option<prost_types::Struct>,
+) -> blob::DownloadResponse {
+    blob::DownloadResponse {
+        id,
+        part: Some(download_response::Part::Info(blob::BlobInfo {
+            size,
+            metadata,
+            attributes,
+            digest,
+        })),
+    }
+}
+
+/// A response containing a chunk of a BLOB being downloaded.
+pub(super) const fn download_chunk(id: u64, offset: u64, bytes: Vec<u8>) -> blob::DownloadResponse {
+    blob::DownloadResponse {
+        id,
+        part: Some(download_response::Part::Chunk(blob::BlobChunk {
+            offset,
+            bytes,
+        })),
+    }
+}
+
+/// Tracks when expired key-value pairs were last removed from a store.
+///
+/// Expired pairs are never visible, so removing them is only necessary to reclaim space. Rather
+/// than holding a connection open indefinitely, which some backends do not permit, stores remove
+/// expired pairs when a connection is established if enough time has passed, in the background
+/// where the backend permits it. See [`sweep_in_background`].
+#[derive(Debug, Default)]
+pub(super) struct Sweeper {
+    /// The last time a sweep was started, in milliseconds since the Unix epoch.
+    last_sweep: AtomicI64,
+}
+
+impl Sweeper {
+    /// Determine if a sweep should be performed now. If so, it is assumed that the caller will
+    /// perform it.
+    pub(super) fn is_due(&self, now: i64) -> bool {
+        let last_sweep = self.last_sweep.load(Ordering::Relaxed);
+        now.saturating_sub(last_sweep) >= SWEEP_INTERVAL_MILLIS
+            && self
+                .last_sweep
+                .compare_exchange(last_sweep, now, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+    }
+}
+
+/// Remove expired key-value pairs on a thread of its own, so that the request establishing the
+/// connection that started the sweep is not delayed. `sweep` is called with the current time, and
+/// is retried a few times if it fails, such as when the database is locked by another connection.
+pub(super) fn sweep_in_background<E>(mut sweep: impl FnMut(i64) -> Result<(), E> + Send + 'static) {
+    // Expired pairs remain invisible if the thread cannot be spawned, and are removed by a later
+    // sweep.
+    let _res = std::thread::Builder::new()
+        .name("buffdb-sweeper".to_owned())
+        .spawn(move || {
+            for _ in 0..SWEEP_ATTEMPTS {
+                if sweep(now_millis()).is_ok() {
+                    return;
+                }
+                std::thread::sleep(SWEEP_RETRY_INTERVAL);
+            }
+        });
+}
+
+/// Describe the value associated with a key being set.
+pub(super) fn put_change(
+    key: String,
+    old_value: Option<Vec<u8>>,
+    new_value: Vec<u8>,
+) -> kv::WatchResponse {
+    kv::WatchResponse {
+        kind: ChangeKind::Put.into(),
+        key,
+        old_value,
+        new_value: Some(new_value),
+    }
+}
+
+/// Describe a key being deleted.
+pub(super) fn delete_change(key: String, old_value: Vec<u8>) -> kv::WatchResponse {
+    kv::WatchResponse {
+        kind: ChangeKind::Delete.into(),
+        key,
+        old_value: Some(old_value),
+        new_value: None,
+    }
+}
+
+/// A keyspace within the KV store.
+///
+/// Each namespace other than the default is stored separately, in a table or column family whose
+/// name is derived from that of the namespace. Names are restricted to characters that are valid
+/// in an identifier, so the derived name can be interpolated into a query.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(super) struct Namespace {
+    name: Option<String>,
+}
+
+impl Namespace {
+    /// The prefix of the table or column family storing a namespace other than the default.
+    pub(super) const PREFIX: &'static str = "kv:";
+
+    /// The namespace with the given name, provided the name is valid.
+    pub(super) fn named(name: String) -> Result<Self, Status> {
+        let is_valid = !name.is_empty()
+            && name.len() <= MAX_NAMESPACE_LEN
+            && name
+                .bytes()
+                .all(|byte| byte.is_ascii_alphanumeric() || byte == b'_' || byte == b'-');
+        if !is_valid {
+            return Err(Status::invalid_argument(format!(
+                "invalid namespace name {name:?}"
+            )));
+        }
+        Ok(Self { name: Some(name) })
+    }
+
+    /// The namespace requested by the metadata of a request.
+    pub(super) fn from_metadata(metadata: &MetadataMap) -> Result<Self, Status> {
+        match metadata.get(crate::kv::NAMESPACE_METADATA_KEY) {
+            Some(name) => {
+                let name = name.to_str().map_err(|_| {
+                    Status::invalid_argument("namespace name must be ASCII".to_owned())
+                })?;
+                Self::named(name.to_owned())
+            }
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// The name of the namespace, or `None` for the default namespace.
+    pub(super) fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// The name of the table or column family storing the namespace, if it is not the default.
+    pub(super) fn storage_name(&self) -> Option<String> {
+        self.name
+            .as_ref()
+            .map(|name| format!("{}{name}", Self::PREFIX))
+    }
+
+    /// The name of the table storing the namespace, quoted as necessary for use in a query.
+    #[cfg(any(feature = "duckdb", feature = "sqlite"))]
+    pub(super) fn table(&self) -> String {
+        match self.storage_name() {
+            Some(name) => format!("\"{name}\""),
+            None => "kv".to_owned(),
+        }
+    }
+
+    /// The name of the table storing the history of the namespace, quoted as necessary for use in a
+    /// query.
+    #[cfg(any(feature = "duckdb", feature = "sqlite"))]
+    pub(super) fn history_table(&self) -> String {
+        match &self.name {
+            Some(name) => format!("\"kv_history:{name}\""),
+            None => "kv_history".to_owned(),
+        }
+    }
+
+    /// The status returned when the namespace does not exist.
+    pub(super) fn not_found(&self) -> Status {
+        Status::not_found(format!(
+            "namespace {} does not exist",
+            self.name().unwrap_or_default()
+        ))
+    }
+
+    /// The status returned when the namespace already exists.
+    pub(super) fn already_exists(&self) -> Status {
+        Status::already_exists(format!(
+            "namespace {} already exists",
+            self.name().unwrap_or_default()
+        ))
+    }
+}
+
+/// Notifies watchers of changes to key-value pairs.
+///
+/// Clones share the same set of watchers, allowing a write path to notify watchers without
+/// borrowing the backend. Changes are only seen by watchers of the same namespace.
+#[derive(Debug, Clone)]
+pub(super) struct Watchers {
+    sender: broadcast::Sender<(Namespace, kv::WatchResponse)>,
+    namespace: Namespace,
+}
+
+impl Default for Watchers {
+    fn default() -> Self {
+        Self {
+            sender: broadcast::channel(WATCH_CAPACITY).0,
+            namespace: Namespace::default(),
+        }
+    }
+}
+
+impl Watchers {
+    /// The watchers of changes made within the given namespace.
+    pub(super) fn in_namespace(&self, namespace: &Namespace) -> Self {
+        Self {
+            sender: self.sender.clone(),
+            namespace: namespace.clone(),
+        }
+    }
+
+    /// Whether anyone is currently watching for changes.
+    ///
+    /// Determining the previous value of a pair requires an additional read, which can be skipped
+    /// if no one is watching.
+    pub(super) fn is_watched(&self) -> bool {
+        self.sender.receiver_count() > 0
+    }
+
+    /// Notify watchers of changes that have been made.
+    pub(super) fn notify(&self, changes: impl IntoIterator<Item = kv::WatchResponse>) {
+        for change in changes {
+            // An error only indicates that there are no watchers.
+            let _res = self.sender.send((self.namespace.clone(), change));
+        }
+    }
+
+    /// Stream all subsequent changes to the requested keys.
+    pub(super) fn watch(
+        &self,
+        kv::WatchRequest { target }: kv::WatchRequest,
+    ) -> Result<impl Stream<Item = Result<kv::WatchResponse, Status>> + Send + 'static, Status>
+    {
+        let is_match: Box<dyn Fn(&str) -> bool + Send + Sync> = match target {
+            Some(Target::Key(target)) => Box::new(move |key| key == target),
+            Some(Target::Prefix(target)) => Box::new(move |key| key.starts_with(&target)),
+            None => return Err(Status::invalid_argument("no key or prefix was provided")),
+        };
+        let namespace = self.namespace.clone();
+        let mut receiver = self.sender.subscribe();
+
+        Ok(stream!({
+            loop {
+                match receiver.recv().await {
+                    Ok((change_namespace, change))
+                        if change_namespace == namespace && is_match(&change.key) =>
+                    {
+                        yield Ok(change)
+                    }
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(count)) => {
+                        yield Err(Status::data_loss(format!(
+                            "watcher fell behind; {count} changes were dropped"
+                        )));
+                        break;
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        }))
+    }
+}
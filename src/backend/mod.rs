@@ -1,14 +1,32 @@
 //! Backend implementations for various databases.
 //!
 //! Note that backends must be enabled at compile time using the appropriate feature flag.
+//!
+//! The KV and BLOB stores never hold a write open across a yield point of a stream, as it would
+//! block other writers for as long as the client takes to send the next request or read a response.
+//! Each write is performed in a transaction of its own, and a KV transaction buffers its writes
+//! until it is committed. Raw queries are the exception: a transaction begun by a query remains
+//! open until a later query in the stream ends it, as the client requested.
+//!
+//! Streams that download, list, or verify BLOBs do hold a read transaction, or a snapshot in the
+//! case of RocksDB, across yield points, so that everything they return is consistent. This does
+//! not block writers, but the transaction remains open for as long as the client takes to read the
+//! responses, and the data it refers to is kept until then: SQLite cannot checkpoint its
+//! write-ahead log past the transaction, and DuckDB and RocksDB retain the versions it observes.
 
 mod arc;
+// Backends report errors as `tonic::Status`, which is large. It is returned by the RPCs regardless,
+// so boxing it within the backends would only add an allocation to every error.
 #[cfg(feature = "duckdb")]
+#[allow(clippy::result_large_err)]
 mod duckdb;
+#[allow(clippy::result_large_err)]
 mod helpers;
 #[cfg(feature = "rocksdb")]
+#[allow(clippy::result_large_err)]
 mod rocksdb;
 #[cfg(feature = "sqlite")]
+#[allow(clippy::result_large_err)]
 mod sqlite;
 
 use std::sync::Arc;
@@ -68,6 +86,18 @@ pub trait KvBackend: DatabaseBackend + Send + Sync {
     type SetStream: Stream<Item = Result<kv::SetResponse, tonic::Status>>;
     /// A stream for the response to a `delete` command.
     type DeleteStream: Stream<Item = Result<kv::DeleteResponse, tonic::Status>>;
+    /// A stream for the response to a `scan` command.
+    type ScanStream: Stream<Item = Result<kv::ScanResponse, tonic::Status>>;
+    /// A stream for the response to a `compare_and_swap` command.
+    type CompareAndSwapStream: Stream<Item = Result<kv::CompareAndSwapResponse, tonic::Status>>;
+    /// A stream for the response to a `transaction` command.
+    type TransactionStream: Stream<Item = Result<kv::TransactionResponse, tonic::Status>>;
+    /// A stream for the response to a `watch` command.
+    type WatchStream: Stream<Item = Result<kv::WatchResponse, tonic::Status>>;
+    /// A stream for the response to an `increment` command.
+    type IncrementStream: Stream<Item = Result<kv::IncrementResponse, tonic::Status>>;
+    /// A stream for the response to a `history` command.
+    type HistoryStream: Stream<Item = Result<kv::HistoryResponse, tonic::Status>>;
 
     /// Initialize the key-value store.
     fn initialize(
@@ -101,6 +131,61 @@ pub trait KvBackend: DatabaseBackend + Send + Sync {
 
     /// Determine if all provided keys have different values.
     async fn not_eq(&self, request: StreamingRequest<kv::NotEqRequest>) -> RpcResponse<bool>;
+
+    /// Obtain all key-value pairs within the requested range, ordered lexicographically by key.
+    async fn scan(
+        &self,
+        request: StreamingRequest<kv::ScanRequest>,
+    ) -> RpcResponse<Self::ScanStream>;
+
+    /// Set the value for each key, provided the key currently has the expected value.
+    async fn compare_and_swap(
+        &self,
+        request: StreamingRequest<kv::CompareAndSwapRequest>,
+    ) -> RpcResponse<Self::CompareAndSwapStream>;
+
+    /// Perform the requested operations atomically, applying all writes only once the transaction
+    /// is committed.
+    async fn transaction(
+        &self,
+        request: StreamingRequest<kv::TransactionRequest>,
+    ) -> RpcResponse<Self::TransactionStream>;
+
+    /// Stream changes made to the requested keys as they occur.
+    async fn watch(
+        &self,
+        request: tonic::Request<kv::WatchRequest>,
+    ) -> RpcResponse<Self::WatchStream>;
+
+    /// Atomically add the delta to the integer value of each key, creating the key if necessary.
+    async fn increment(
+        &self,
+        request: StreamingRequest<kv::IncrementRequest>,
+    ) -> RpcResponse<Self::IncrementStream>;
+
+    /// Create a namespace, which is a keyspace separate from all others.
+    async fn create_namespace(
+        &self,
+        request: tonic::Request<kv::CreateNamespaceRequest>,
+    ) -> RpcResponse<kv::CreateNamespaceResponse>;
+
+    /// List the names of all namespaces other than the default.
+    async fn list_namespaces(
+        &self,
+        request: tonic::Request<kv::ListNamespacesRequest>,
+    ) -> RpcResponse<kv::ListNamespacesResponse>;
+
+    /// Drop a namespace, deleting all key-value pairs within it.
+    async fn drop_namespace(
+        &self,
+        request: tonic::Request<kv::DropNamespaceRequest>,
+    ) -> RpcResponse<kv::DropNamespaceResponse>;
+
+    /// Obtain every recorded write to each key, ordered by version.
+    async fn history(
+        &self,
+        request: StreamingRequest<kv::HistoryRequest>,
+    ) -> RpcResponse<Self::HistoryStream>;
 }
 
 /// A backend that supports BLOB operations.
@@ -114,6 +199,18 @@ pub trait BlobBackend: DatabaseBackend + Send + Sync {
     type UpdateStream: Stream<Item = Result<blob::UpdateResponse, tonic::Status>>;
     /// A stream for the response to a `delete` command.
     type DeleteStream: Stream<Item = Result<blob::DeleteResponse, tonic::Status>>;
+    /// A stream for the response to an `upload` command.
+    type UploadStream: Stream<Item = Result<blob::UploadResponse, tonic::Status>>;
+    /// A stream for the response to a `download` command.
+    type DownloadStream: Stream<Item = Result<blob::DownloadResponse, tonic::Status>>;
+    /// A stream for the response to a `list` command.
+    type ListStream: Stream<Item = Result<blob::ListResponse, tonic::Status>>;
+    /// A stream for the response to a `copy` command.
+    type CopyStream: Stream<Item = Result<blob::CopyResponse, tonic::Status>>;
+    /// A stream for the response to an `append` command.
+    type AppendStream: Stream<Item = Result<blob::AppendResponse, tonic::Status>>;
+    /// A stream for the response to a `verify` command.
+    type VerifyStream: Stream<Item = Result<blob::VerifyResponse, tonic::Status>>;
 
     /// Initialize the BLOB store.
     fn initialize(
@@ -130,6 +227,14 @@ pub trait BlobBackend: DatabaseBackend + Send + Sync {
         Ok(conn)
     }
 
+    /// Store the data of BLOBs larger than `threshold` bytes in files in a directory alongside the
+    /// database, rather than in the database itself. If `None`, all data that is stored afterwards
+    /// is kept in the database.
+    ///
+    /// This is transparent to clients, and data already stored is unaffected. In-memory databases
+    /// always keep data in the database.
+    fn set_external_threshold(&self, threshold: Option<u64>);
+
     /// Get the BLOB and associated metadata given the ID.
     async fn get(
         &self,
@@ -162,4 +267,47 @@ pub trait BlobBackend: DatabaseBackend + Send + Sync {
         &self,
         request: StreamingRequest<blob::NotEqDataRequest>,
     ) -> RpcResponse<bool>;
+
+    /// Store BLOBs sent in chunks, returning the ID of each once it is finalized.
+    async fn upload(
+        &self,
+        request: StreamingRequest<blob::UploadRequest>,
+    ) -> RpcResponse<Self::UploadStream>;
+
+    /// Get the BLOB and associated metadata given the ID, sending the BLOB in chunks.
+    ///
+    /// The data is verified against its digest as it is sent, so corruption is only reported by an
+    /// error following the last chunk. Callers must check that the stream ends without an error
+    /// before relying on the data received.
+    async fn download(
+        &self,
+        request: StreamingRequest<blob::DownloadRequest>,
+    ) -> RpcResponse<Self::DownloadStream>;
+
+    /// List the BLOBs matching the request in ascending order of their IDs, along with their
+    /// metadata and size.
+    async fn list(
+        &self,
+        request: tonic::Request<blob::ListRequest>,
+    ) -> RpcResponse<Self::ListStream>;
+
+    /// Duplicate the BLOB given the ID, along with its metadata and attributes, returning the ID of
+    /// the copy.
+    async fn copy(
+        &self,
+        request: StreamingRequest<blob::CopyRequest>,
+    ) -> RpcResponse<Self::CopyStream>;
+
+    /// Append data to the BLOB given the ID, returning its new size.
+    async fn append(
+        &self,
+        request: StreamingRequest<blob::AppendRequest>,
+    ) -> RpcResponse<Self::AppendStream>;
+
+    /// Verify the data of every BLOB against its digest, returning those that are corrupted or
+    /// missing in ascending order of their IDs.
+    async fn verify(
+        &self,
+        request: tonic::Request<blob::VerifyRequest>,
+    ) -> RpcResponse<Self::VerifyStream>;
 }
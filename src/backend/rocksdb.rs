@@ -1,23 +1,66 @@
-use crate::backend::{helpers, BlobBackend, DatabaseBackend, KvBackend};
+//! A backend utilizing RocksDB.
+//!
+//! RocksDB iterators are not `Send`, so they cannot be held across a yield point of a stream. Any
+//! iteration is therefore performed by a synchronous function that collects its results, which the
+//! stream yields afterwards.
+
+use crate::backend::helpers::{
+    self, AttributeChanges, BufferedTransaction, ExternalFiles, KeyRange, Namespace,
+    StoredAttribute, Sweeper, TransactionTarget, Watchers,
+};
+use crate::backend::{BlobBackend, DatabaseBackend, KvBackend};
+use crate::conv::{try_into_protobuf_any, RocksDbValue};
 use crate::interop::into_tonic_status;
-use crate::proto::{blob, kv};
+use crate::proto::blob::{upload_request, Compression};
+use crate::proto::kv::transaction_request::Operation;
+use crate::proto::kv::transaction_response::Outcome;
+use crate::proto::{blob, kv, query};
+use crate::queryable::{QueryParams, Queryable};
 use crate::tracing_shim::{trace_span, Instrument as _};
 use crate::{DynStream, Location, RpcResponse, StreamingRequest};
 use async_stream::stream;
 use rand::{Rng, SeedableRng};
-use rocksdb::TransactionDB;
+use rocksdb::{
+    ColumnFamily, DBIteratorWithThreadMode, Direction, IteratorMode, SnapshotWithThreadMode,
+    Transaction, TransactionDB,
+};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::Write as _;
 use tonic::{async_trait, Response, Status};
 
+mod language;
+
+use self::language::{Query, Range};
+
 /// A backend utilizing RocksDb.
 #[derive(Debug)]
 pub struct RocksDb {
     location: Location,
+    sweeper: Sweeper,
+    watchers: Watchers,
+    external: ExternalFiles,
 }
 
 fn generate_id() -> u64 {
     rand::rngs::SmallRng::from_entropy().r#gen()
 }
 
+/// The smallest byte string that is greater than every byte string beginning with `prefix`.
+///
+/// Returns `None` if there is no such string, which is the case if `prefix` is empty or consists
+/// solely of `0xFF` bytes.
+fn prefix_successor(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut successor = prefix.to_vec();
+    while let Some(last) = successor.pop() {
+        if last < u8::MAX {
+            successor.push(last + 1);
+            return Some(successor);
+        }
+    }
+    None
+}
+
 macro_rules! cf_handle {
     ($db:expr, $name:expr) => {
         match $db.cf_handle($name) {
@@ -27,120 +70,2320 @@ macro_rules! cf_handle {
     };
 }
 
+/// The key under which the most recently allocated version is stored.
+const VERSION_KEY: &[u8] = b"version";
+
+/// A key-value pair as returned by an iterator.
+type KvBytes = (Box<[u8]>, Box<[u8]>);
+
+/// Allocate the version of a write within the transaction performing it.
+///
+/// The most recently allocated version is locked until the transaction ends, so writes are committed
+/// in the order of their versions, as with the other backends.
+fn next_version(db: &TransactionDB, txn: &Transaction<'_, TransactionDB>) -> Result<u64, Status> {
+    let version_col = cf_handle!(db, "kv_version")?;
+    let version = txn
+        .get_for_update_cf(version_col, VERSION_KEY, true)
+        .map_err(into_tonic_status)?
+        .and_then(|bytes| Some(u64::from_be_bytes(bytes.as_slice().try_into().ok()?)))
+        .unwrap_or(0)
+        + 1;
+    txn.put_cf(version_col, VERSION_KEY, version.to_be_bytes())
+        .map_err(into_tonic_status)?;
+    Ok(version)
+}
+
+/// Decode the time at which a key-value pair expires, in milliseconds since the Unix epoch.
+fn decode_expiration(bytes: &[u8]) -> Option<i64> {
+    Some(i64::from_be_bytes(bytes.try_into().ok()?))
+}
+
+/// Determine if the key-value pair has expired as of `now`.
+fn is_expired(
+    db: &TransactionDB,
+    expiry_col: &ColumnFamily,
+    key: &[u8],
+    now: i64,
+) -> Result<bool, Status> {
+    Ok(db
+        .get_cf(expiry_col, key)
+        .map_err(into_tonic_status)?
+        .and_then(|bytes| decode_expiration(&bytes))
+        .is_some_and(|expires_at| expires_at <= now))
+}
+
+/// The column families storing a namespace.
+struct KvColumns<'a> {
+    /// The value of each pair.
+    data: &'a ColumnFamily,
+    /// When each pair expires, for those that do.
+    expiry: &'a ColumnFamily,
+    /// Every recorded write to each pair.
+    history: &'a ColumnFamily,
+}
+
+/// The names of the column families storing the values, expirations, and history of a namespace.
+fn column_family_names(namespace: &Namespace) -> [String; 3] {
+    match namespace.name() {
+        Some(name) => [
+            format!("{}{name}", Namespace::PREFIX),
+            format!("kv_expiry:{name}"),
+            format!("kv_history:{name}"),
+        ],
+        None => [
+            "default".to_owned(),
+            "kv_expiry".to_owned(),
+            "kv_history".to_owned(),
+        ],
+    }
+}
+
+/// Get the column families storing a namespace.
+fn kv_columns<'a>(db: &'a TransactionDB, namespace: &Namespace) -> Result<KvColumns<'a>, Status> {
+    let [data_name, expiry_name, history_name] = column_family_names(namespace);
+    match (
+        db.cf_handle(&data_name),
+        db.cf_handle(&expiry_name),
+        db.cf_handle(&history_name),
+    ) {
+        (Some(data), Some(expiry), Some(history)) => Ok(KvColumns {
+            data,
+            expiry,
+            history,
+        }),
+        _ if namespace.name().is_none() => {
+            Err(Status::internal("Failed to get ColumnFamily handle"))
+        }
+        _ => Err(namespace.not_found()),
+    }
+}
+
+/// All namespaces other than the default, ordered by name.
+fn namespaces(db: &TransactionDB) -> Result<Vec<Namespace>, rocksdb::Error> {
+    let mut names = TransactionDB::list_cf(&rocksdb::Options::default(), db.path())?
+        .into_iter()
+        .filter_map(|name| Some(name.strip_prefix(Namespace::PREFIX)?.to_owned()))
+        .collect::<Vec<_>>();
+    names.sort_unstable();
+    Ok(names
+        .into_iter()
+        .filter_map(|name| Namespace::named(name).ok())
+        .collect())
+}
+
+/// The key under which a write to `key` is stored in the history of its namespace.
+///
+/// Keys are prefixed with their length, so that the writes to each key are contiguous and ordered
+/// by version.
+fn history_key(key: &str, version: u64) -> Vec<u8> {
+    let len = u32::try_from(key.len()).unwrap_or(u32::MAX);
+    [&len.to_be_bytes(), key.as_bytes(), &version.to_be_bytes()].concat()
+}
+
+/// The position of a reverse iterator over the history of a namespace such that the first entry
+/// returned is the last one at or before `history_key`. Given the key of a write to some key at
+/// some version, this is the most recent write to that key as of that version, if there is one.
+fn history_mode(history_key: &[u8]) -> IteratorMode<'_> {
+    IteratorMode::From(history_key, Direction::Reverse)
+}
+
+/// Encode a write for storage in the history of a namespace. A value of `None` records the deletion
+/// of the key.
+fn encode_write(written_at: i64, value: Option<&[u8]>) -> Vec<u8> {
+    let mut bytes = written_at.to_be_bytes().to_vec();
+    match value {
+        Some(value) => {
+            bytes.push(1);
+            bytes.extend_from_slice(value);
+        }
+        None => bytes.push(0),
+    }
+    bytes
+}
+
+/// Decode the write stored under `entry_key` in the history of a namespace, provided it is a write
+/// to `key`.
+fn decode_write(key: &str, (entry_key, bytes): &KvBytes) -> Option<kv::HistoryResponse> {
+    let prefix = history_key(key, 0);
+    let prefix = &prefix[..prefix.len() - 8];
+    let version = entry_key.strip_prefix(prefix)?;
+    let version = u64::from_be_bytes(version.try_into().ok()?);
+    let (written_at, value) = bytes.split_first_chunk::<8>()?;
+    let value = match value.split_first()? {
+        (1, value) => Some(value.to_vec()),
+        _ => None,
+    };
+    Some(kv::HistoryResponse {
+        key: key.to_owned(),
+        version,
+        written_at: Some(helpers::timestamp(i64::from_be_bytes(*written_at))),
+        value,
+    })
+}
+
+/// The first write to `key` returned by an iterator over the history of a namespace, if any.
+fn first_write(
+    mut writes: impl Iterator<Item = Result<KvBytes, rocksdb::Error>>,
+    key: &str,
+) -> Result<Option<kv::HistoryResponse>, Status> {
+    match writes.next() {
+        Some(entry) => Ok(decode_write(key, &entry.map_err(into_tonic_status)?)),
+        None => Ok(None),
+    }
+}
+
+/// Collect every write to the key, ordered by version.
+fn writes(
+    db: &TransactionDB,
+    columns: &KvColumns<'_>,
+    key: &str,
+) -> Result<Vec<kv::HistoryResponse>, Status> {
+    let start = history_key(key, 0);
+    let mut writes = Vec::new();
+    for entry in db.iterator_cf(
+        columns.history,
+        IteratorMode::From(&start, Direction::Forward),
+    ) {
+        let Some(write) = decode_write(key, &entry.map_err(into_tonic_status)?) else {
+            break;
+        };
+        writes.push(write);
+    }
+    Ok(writes)
+}
+
+/// Record a write to the key in the history of the namespace. A value of `None` records the
+/// deletion of the key.
+fn record_write(
+    txn: &Transaction<'_, TransactionDB>,
+    columns: &KvColumns<'_>,
+    key: &str,
+    version: u64,
+    value: Option<&[u8]>,
+) -> Result<(), Status> {
+    txn.put_cf(
+        columns.history,
+        history_key(key, version),
+        encode_write(helpers::now_millis(), value),
+    )
+    .map_err(into_tonic_status)
+}
+
+/// Get the value associated with the key, provided it exists and has not expired.
+fn get_unexpired(
+    db: &TransactionDB,
+    columns: &KvColumns<'_>,
+    key: &str,
+) -> Result<Option<Vec<u8>>, Status> {
+    match db.get_cf(columns.data, key).map_err(into_tonic_status)? {
+        Some(_) if is_expired(db, columns.expiry, key.as_bytes(), helpers::now_millis())? => {
+            Ok(None)
+        }
+        value => Ok(value),
+    }
+}
+
+/// Get the value associated with the key and the version of the write that produced it.
+///
+/// If a version is provided, the value as of that version is read from the history of the
+/// namespace, regardless of whether it has since expired. Otherwise, the current value is read,
+/// provided it exists and has not expired.
+fn get_versioned(
+    db: &TransactionDB,
+    columns: &KvColumns<'_>,
+    key: &str,
+    version: Option<u64>,
+) -> Result<Option<(Vec<u8>, u64)>, Status> {
+    // Everything is read from one snapshot, so a concurrent write cannot pair the current value
+    // with the version of another write.
+    let snapshot = db.snapshot();
+    let start = history_key(key, version.unwrap_or(u64::MAX));
+    let write = first_write(
+        snapshot.iterator_cf(columns.history, history_mode(&start)),
+        key,
+    )?;
+    if version.is_some() {
+        // The most recent write may have deleted the key.
+        return Ok(write.and_then(|write| Some((write.value?, write.version))));
+    }
+    let value = snapshot
+        .get_cf(columns.data, key)
+        .map_err(into_tonic_status)?;
+    let expires_at = snapshot
+        .get_cf(columns.expiry, key)
+        .map_err(into_tonic_status)?
+        .and_then(|bytes| decode_expiration(&bytes));
+    let now = helpers::now_millis();
+    Ok(value
+        .filter(|_| !expires_at.is_some_and(|expires_at| expires_at <= now))
+        .map(|value| (value, write.map_or(0, |write| write.version))))
+}
+
+/// Get the value associated with the key within a transaction, provided it exists and has not
+/// expired.
+///
+/// Both the value and its expiration are locked, ensuring that no other writer can modify the pair
+/// until the transaction ends.
+fn get_unexpired_for_update(
+    txn: &Transaction<'_, TransactionDB>,
+    columns: &KvColumns<'_>,
+    key: &str,
+) -> Result<Option<Vec<u8>>, Status> {
+    let value = txn
+        .get_for_update_cf(columns.data, key, true)
+        .map_err(into_tonic_status)?;
+    let expires_at = txn
+        .get_for_update_cf(columns.expiry, key, true)
+        .map_err(into_tonic_status)?
+        .and_then(|bytes| decode_expiration(&bytes));
+    let now = helpers::now_millis();
+    Ok(value.filter(|_| !expires_at.is_some_and(|expires_at| expires_at <= now)))
+}
+
+/// Get the value associated with the key within a transaction, along with the version of the write
+/// that produced it.
+///
+/// This behaves as [`get_versioned`], except that the current value is locked as described by
+/// [`get_unexpired_for_update`].
+fn get_versioned_for_update(
+    txn: &Transaction<'_, TransactionDB>,
+    columns: &KvColumns<'_>,
+    key: &str,
+    version: Option<u64>,
+) -> Result<Option<(Vec<u8>, u64)>, Status> {
+    let start = history_key(key, version.unwrap_or(u64::MAX));
+    let write = first_write(txn.iterator_cf(columns.history, history_mode(&start)), key)?;
+    if version.is_some() {
+        // The most recent write may have deleted the key.
+        return Ok(write.and_then(|write| Some((write.value?, write.version))));
+    }
+    let value = get_unexpired_for_update(txn, columns, key)?;
+    Ok(value.map(|value| (value, write.map_or(0, |write| write.version))))
+}
+
+/// Set the value associated with the key within a transaction, recording the write in the history
+/// of the namespace.
+fn put(
+    txn: &Transaction<'_, TransactionDB>,
+    columns: &KvColumns<'_>,
+    key: &str,
+    version: u64,
+    value: &[u8],
+    expires_at: Option<i64>,
+) -> Result<(), Status> {
+    record_write(txn, columns, key, version, Some(value))?;
+    txn.put_cf(columns.data, key, value)
+        .map_err(into_tonic_status)?;
+    if let Some(expires_at) = expires_at {
+        txn.put_cf(columns.expiry, key, expires_at.to_be_bytes())
+    } else {
+        txn.delete_cf(columns.expiry, key)
+    }
+    .map_err(into_tonic_status)
+}
+
+/// A namespace accessed within a transaction, to which a [`BufferedTransaction`] is applied when it
+/// is committed.
+struct TransactionColumns<'a> {
+    /// The database the transaction was started on.
+    db: &'a TransactionDB,
+    /// The transaction applying the writes.
+    txn: &'a Transaction<'a, TransactionDB>,
+    /// The column families storing the namespace.
+    columns: &'a KvColumns<'a>,
+}
+
+impl TransactionTarget for TransactionColumns<'_> {
+    fn current(&mut self, key: &str) -> Result<Option<(Vec<u8>, u64)>, Status> {
+        get_versioned_for_update(self.txn, self.columns, key, None)
+    }
+
+    fn put(&mut self, key: &str, value: &[u8], expires_at: Option<i64>) -> Result<u64, Status> {
+        let version = next_version(self.db, self.txn)?;
+        put(self.txn, self.columns, key, version, value, expires_at)?;
+        Ok(version)
+    }
+
+    fn delete(&mut self, key: &str) -> Result<(), Status> {
+        // Deleting a key that does not exist is not a write.
+        if get_unexpired_for_update(self.txn, self.columns, key)?.is_some() {
+            let version = next_version(self.db, self.txn)?;
+            record_write(self.txn, self.columns, key, version, None)?;
+        }
+        self.txn
+            .delete_cf(self.columns.data, key)
+            .and_then(|()| self.txn.delete_cf(self.columns.expiry, key))
+            .map_err(into_tonic_status)
+    }
+}
+
+/// Remove all key-value pairs in every namespace that have expired as of `now`.
+///
+/// Each pair is removed in a transaction of its own, in which its expiration is checked again once
+/// locked, so that a pair renewed since it was found to have expired is kept.
+fn remove_expired(db: &TransactionDB, now: i64) -> Result<(), rocksdb::Error> {
+    for namespace in std::iter::once(Namespace::default()).chain(namespaces(db)?) {
+        let Ok(columns) = kv_columns(db, &namespace) else {
+            continue;
+        };
+        let has_expired =
+            |bytes: &[u8]| decode_expiration(bytes).is_some_and(|expires_at| expires_at <= now);
+        for entry in db.iterator_cf(columns.expiry, IteratorMode::Start) {
+            let (key, expires_at) = entry?;
+            if !has_expired(&expires_at) {
+                continue;
+            }
+            let txn = db.transaction();
+            // The value is locked before the expiration, in the same order as writers lock them.
+            let _value = txn.get_for_update_cf(columns.data, &key, true)?;
+            if txn
+                .get_for_update_cf(columns.expiry, &key, true)?
+                .is_some_and(|expires_at| has_expired(&expires_at))
+            {
+                txn.delete_cf(columns.data, &key)?;
+                txn.delete_cf(columns.expiry, &key)?;
+            }
+            txn.commit()?;
+        }
+    }
+    Ok(())
+}
+
+/// Collect the key-value pairs within the given range, ordered by key.
+fn scan_range(
+    db: &TransactionDB,
+    namespace: &Namespace,
+    KeyRange { start, end, prefix }: KeyRange,
+    limit: usize,
+    reverse: bool,
+) -> Result<Vec<(Box<[u8]>, Box<[u8]>)>, Status> {
+    let columns = kv_columns(db, namespace)?;
+    let now = helpers::now_millis();
+    let start = start.into_bytes();
+    // Keys beginning with the prefix are strictly less than its successor, so the prefix can be
+    // expressed as part of the upper bound.
+    let end = [
+        end.map(String::into_bytes),
+        prefix_successor(prefix.as_bytes()),
+    ]
+    .into_iter()
+    .flatten()
+    .min();
+
+    let mode = match (&end, reverse) {
+        (_, false) => IteratorMode::From(&start, Direction::Forward),
+        (Some(end), true) => IteratorMode::From(end, Direction::Reverse),
+        (None, true) => IteratorMode::End,
+    };
+
+    let mut pairs = Vec::new();
+    for pair in db.iterator_cf(columns.data, mode) {
+        if pairs.len() >= limit {
+            break;
+        }
+        let (key, value) = pair.map_err(into_tonic_status)?;
+        let past_end = end.as_deref().is_some_and(|end| *key >= *end);
+        if reverse {
+            // Seeking in reverse is inclusive of the upper bound, so it may need to be skipped.
+            if past_end {
+                continue;
+            }
+            if *key < *start {
+                break;
+            }
+        } else if past_end {
+            break;
+        }
+        if is_expired(db, columns.expiry, &key, now)? {
+            continue;
+        }
+        pairs.push((key, value));
+    }
+    Ok(pairs)
+}
+
+/// Reads the column families of a database, either as it currently is or as it was when a snapshot
+/// of it was taken.
+trait Reader {
+    /// Get the value of a key.
+    fn get_cf(
+        &self,
+        cf: &ColumnFamily,
+        key: impl AsRef<[u8]>,
+    ) -> Result<Option<Vec<u8>>, rocksdb::Error>;
+
+    /// Iterate over the entries of a column family.
+    fn iterator_cf<'a>(
+        &'a self,
+        cf: &ColumnFamily,
+        mode: IteratorMode<'_>,
+    ) -> DBIteratorWithThreadMode<'a, TransactionDB>;
+}
+
+impl Reader for TransactionDB {
+    fn get_cf(
+        &self,
+        cf: &ColumnFamily,
+        key: impl AsRef<[u8]>,
+    ) -> Result<Option<Vec<u8>>, rocksdb::Error> {
+        Self::get_cf(self, cf, key)
+    }
+
+    fn iterator_cf<'a>(
+        &'a self,
+        cf: &ColumnFamily,
+        mode: IteratorMode<'_>,
+    ) -> DBIteratorWithThreadMode<'a, TransactionDB> {
+        Self::iterator_cf(self, cf, mode)
+    }
+}
+
+impl Reader for SnapshotWithThreadMode<'_, TransactionDB> {
+    fn get_cf(
+        &self,
+        cf: &ColumnFamily,
+        key: impl AsRef<[u8]>,
+    ) -> Result<Option<Vec<u8>>, rocksdb::Error> {
+        Self::get_cf(self, cf, key)
+    }
+
+    fn iterator_cf<'a>(
+        &'a self,
+        cf: &ColumnFamily,
+        mode: IteratorMode<'_>,
+    ) -> DBIteratorWithThreadMode<'a, TransactionDB> {
+        Self::iterator_cf(self, cf, mode)
+    }
+}
+
+/// The column families storing the BLOB store.
+///
+/// The data of each BLOB is stored once per distinct content, keyed by an ID unrelated to that of
+/// any BLOB. Each BLOB refers to its content by the digest of its data, and each content tracks the
+/// number of BLOBs referring to it.
+struct BlobColumns<'a> {
+    /// The digest of the data of each BLOB.
+    digest: &'a ColumnFamily,
+    /// The metadata of each BLOB, for those that have any.
+    metadata: &'a ColumnFamily,
+    /// The attributes of each BLOB, keyed by the ID of the BLOB followed by the name of the
+    /// attribute.
+    attribute: &'a ColumnFamily,
+    /// The ID and reference count of each content, keyed by its digest.
+    content: &'a ColumnFamily,
+    /// The size, compression algorithm, and chunks of each content, keyed by its ID.
+    chunk: &'a ColumnFamily,
+    /// The ID of the BLOB stored with each idempotency key.
+    idempotency: &'a ColumnFamily,
+    /// The idempotency key each BLOB was stored with, for those stored with one.
+    idempotency_key: &'a ColumnFamily,
+}
+
+/// Get the column families storing the BLOB store.
+fn blob_columns(db: &TransactionDB) -> Result<BlobColumns<'_>, Status> {
+    Ok(BlobColumns {
+        digest: cf_handle!(db, "blob_digest")?,
+        metadata: cf_handle!(db, "metadata")?,
+        attribute: cf_handle!(db, "blob_attribute")?,
+        content: cf_handle!(db, "blob_content")?,
+        chunk: cf_handle!(db, "blob_chunk")?,
+        idempotency: cf_handle!(db, "blob_idempotency")?,
+        idempotency_key: cf_handle!(db, "blob_idempotency_key")?,
+    })
+}
+
+/// The key under which the digest and metadata of a BLOB are stored. Keys are big-endian, so that
+/// BLOBs are iterated in ascending order of their IDs.
+const fn blob_key(id: u64) -> [u8; 8] {
+    id.to_be_bytes()
+}
+
+/// The key under which an attribute of a BLOB is stored. The attributes of each BLOB are
+/// contiguous, as the key begins with that of the BLOB.
+fn attribute_key(id: u64, name: &str) -> Vec<u8> {
+    [&blob_key(id), name.as_bytes()].concat()
+}
+
+/// Encode the ID and reference count of a content.
+fn encode_content(content_id: u64, refs: u64) -> Vec<u8> {
+    [content_id.to_le_bytes(), refs.to_be_bytes()].concat()
+}
+
+/// Decode the ID and reference count of a content.
+fn decode_content(bytes: &[u8]) -> Option<(u64, u64)> {
+    let (content_id, refs) = bytes.split_first_chunk::<8>()?;
+    Some((
+        u64::from_le_bytes(*content_id),
+        u64::from_be_bytes(refs.try_into().ok()?),
+    ))
+}
+
+/// Encode the size of a content, followed by the algorithm it is compressed with if it is
+/// compressed or stored in a file, followed by the name of the file if there is one.
+fn encode_size(size: u64, compression: Compression, path: Option<&str>) -> Vec<u8> {
+    let mut bytes = size.to_be_bytes().to_vec();
+    if compression != Compression::None || path.is_some() {
+        bytes.push(compression as u8);
+    }
+    if let Some(path) = path {
+        bytes.extend_from_slice(path.as_bytes());
+    }
+    bytes
+}
+
+/// How the content of a BLOB is stored.
+struct Content {
+    /// The ID the size and chunks of the content are stored under.
+    id: u64,
+    /// The size of the content once decompressed.
+    size: u64,
+    /// The algorithm the content is compressed with.
+    compression: i32,
+    /// The name of the file storing the content, if it is not stored in chunks.
+    path: Option<String>,
+    /// The digest of the content once decompressed.
+    digest: Vec<u8>,
+}
+
+impl Content {
+    /// Whether the content is stored uncompressed, such that ranges of it can be read directly.
+    const fn is_uncompressed(&self) -> bool {
+        self.compression == Compression::None as i32
+    }
+}
+
+/// Decode the size of the content with the given digest stored under `content_id`, along with the
+/// algorithm it is compressed with and the file storing it.
+fn decode_size(content_id: u64, digest: &[u8], bytes: &[u8]) -> Option<Content> {
+    let (size, rest) = bytes.split_first_chunk::<8>()?;
+    let (compression, path) = match rest.split_first() {
+        None => (Compression::None as i32, None),
+        Some((compression, [])) => (i32::from(*compression), None),
+        Some((compression, path)) => (
+            i32::from(*compression),
+            Some(String::from_utf8(path.to_vec()).ok()?),
+        ),
+    };
+    Some(Content {
+        id: content_id,
+        size: u64::from_be_bytes(*size),
+        compression,
+        path,
+        digest: digest.to_vec(),
+    })
+}
+
+/// The key under which the size of a content is stored. The key of each of its chunks begins with
+/// this key.
+const fn content_key(content_id: u64) -> [u8; 8] {
+    content_id.to_le_bytes()
+}
+
+/// The key under which the chunk of a content beginning at `offset` is stored. Chunks of the same
+/// content are ordered by their offset.
+fn chunk_key(content_id: u64, offset: u64) -> Vec<u8> {
+    [content_id.to_le_bytes(), offset.to_be_bytes()].concat()
+}
+
+/// The offset of the chunk stored under `key`, provided it is a chunk of the content with the given
+/// ID.
+fn decode_chunk_key(content_id: u64, key: &[u8]) -> Option<u64> {
+    let offset = key.strip_prefix(&content_key(content_id))?;
+    Some(u64::from_be_bytes(offset.try_into().ok()?))
+}
+
+/// Generate an ID for which `is_used` returns `false`.
+fn unused_id(is_used: impl Fn(u64) -> bool) -> Result<u64, Status> {
+    // Check for a collision of the generated identifier. If there is one, try once more before
+    // erroring. Note that there is a TOCTOU issue here, but the odds of any collision at all is so
+    // low that it is hardly worth worrying about that. If this somehow becomes a plausible issue,
+    // the ID can be extended to 128 bits from the current 64, rendering a collision all but
+    // impossible.
+    let id = generate_id();
+    if !is_used(id) {
+        return Ok(id);
+    }
+    let id = generate_id();
+    if !is_used(id) {
+        return Ok(id);
+    }
+    Err(Status::internal("failed to generate unique id"))
+}
+
+/// Generate an ID that is not in use by any BLOB.
+fn unused_blob_id(db: &TransactionDB, columns: &BlobColumns<'_>) -> Result<u64, Status> {
+    unused_id(|id| matches!(db.get_cf(columns.digest, blob_key(id)), Ok(Some(_))))
+}
+
+/// Generate an ID that is not in use by any content, including content still being uploaded.
+fn unused_content_id(db: &TransactionDB, chunk_col: &ColumnFamily) -> Result<u64, Status> {
+    unused_id(|content_id| {
+        let prefix = content_key(content_id);
+        matches!(
+            db.iterator_cf(chunk_col, IteratorMode::From(&prefix, Direction::Forward)).next(),
+            Some(Ok((key, _))) if key.starts_with(&prefix)
+        )
+    })
+}
+
+/// Get the ID and reference count of the content with the given digest, if it is stored.
+///
+/// The content is locked, ensuring that no other writer can modify its reference count until the
+/// transaction ends.
+fn content_for_update(
+    txn: &Transaction<'_, TransactionDB>,
+    columns: &BlobColumns<'_>,
+    digest: &[u8],
+) -> Result<Option<(u64, u64)>, rocksdb::Error> {
+    Ok(txn
+        .get_for_update_cf(columns.content, digest, true)?
+        .and_then(|bytes| decode_content(&bytes)))
+}
+
+/// Add a reference to the stored content with the given digest, returning whether there is any.
+fn add_content_ref(
+    txn: &Transaction<'_, TransactionDB>,
+    columns: &BlobColumns<'_>,
+    digest: &[u8],
+) -> Result<bool, rocksdb::Error> {
+    let Some((content_id, refs)) = content_for_update(txn, columns, digest)? else {
+        return Ok(false);
+    };
+    txn.put_cf(
+        columns.content,
+        digest,
+        encode_content(content_id, refs + 1),
+    )?;
+    Ok(true)
+}
+
+/// Remove a reference to the stored content with the given digest, deleting the content once no
+/// BLOB refers to it.
+///
+/// Returns the name of the file storing the deleted content, if any, which is to be removed once
+/// the transaction is committed.
+fn remove_content_ref(
+    db: &TransactionDB,
+    txn: &Transaction<'_, TransactionDB>,
+    columns: &BlobColumns<'_>,
+    digest: &[u8],
+) -> Result<Option<String>, rocksdb::Error> {
+    match content_for_update(txn, columns, digest)? {
+        Some((content_id, refs)) if refs > 1 => {
+            txn.put_cf(
+                columns.content,
+                digest,
+                encode_content(content_id, refs - 1),
+            )?;
+            Ok(None)
+        }
+        Some((content_id, _)) => {
+            let path = txn
+                .get_cf(columns.chunk, content_key(content_id))?
+                .and_then(|bytes| decode_size(content_id, digest, &bytes))
+                .and_then(|content| content.path);
+            txn.delete_cf(columns.content, digest)?;
+            delete_content_chunks(db, txn, columns.chunk, content_id)?;
+            Ok(path)
+        }
+        None => Ok(None),
+    }
+}
+
+/// Store the size of new content under `content_id`, along with the algorithm it is compressed
+/// with and the file storing it, with a single reference to it. Any chunks must be stored
+/// separately.
+fn put_content(
+    txn: &Transaction<'_, TransactionDB>,
+    columns: &BlobColumns<'_>,
+    content_id: u64,
+    digest: &[u8],
+    size: u64,
+    compression: Compression,
+    path: Option<&str>,
+) -> Result<(), rocksdb::Error> {
+    txn.put_cf(
+        columns.chunk,
+        content_key(content_id),
+        encode_size(size, compression, path),
+    )?;
+    txn.put_cf(columns.content, digest, encode_content(content_id, 1))
+}
+
+/// Store new content consisting of `data` under `content_id`, compressed with the requested
+/// algorithm, with a single reference to it. The content is stored in a file if it is large
+/// enough, and as a single chunk otherwise.
+fn insert_content(
+    txn: &Transaction<'_, TransactionDB>,
+    columns: &BlobColumns<'_>,
+    external: &ExternalFiles,
+    content_id: u64,
+    digest: &[u8],
+    data: &[u8],
+    compression: Compression,
+) -> Result<(), Status> {
+    let (compression, bytes) = helpers::compress(data, compression);
+    let path = external.write(digest, &bytes)?;
+    if path.is_none() && !bytes.is_empty() {
+        txn.put_cf(columns.chunk, chunk_key(content_id, 0), &*bytes)
+            .map_err(into_tonic_status)?;
+    }
+    put_content(
+        txn,
+        columns,
+        content_id,
+        digest,
+        data.len() as u64,
+        compression,
+        path.as_deref(),
+    )
+    .map_err(into_tonic_status)
+}
+
+/// Add a reference to the content consisting of `data`, storing it under `content_id` only if it
+/// is not already stored. Returns the digest of the content.
+fn store_content(
+    txn: &Transaction<'_, TransactionDB>,
+    columns: &BlobColumns<'_>,
+    external: &ExternalFiles,
+    content_id: u64,
+    data: &[u8],
+    compression: Compression,
+) -> Result<Vec<u8>, Status> {
+    let digest = helpers::digest(data);
+    if !add_content_ref(txn, columns, &digest).map_err(into_tonic_status)? {
+        insert_content(
+            txn,
+            columns,
+            external,
+            content_id,
+            &digest,
+            data,
+            compression,
+        )?;
+    }
+    Ok(digest)
+}
+
+/// Store a BLOB whose content is already referenced.
+fn put_blob(
+    txn: &Transaction<'_, TransactionDB>,
+    columns: &BlobColumns<'_>,
+    id: u64,
+    digest: &[u8],
+    metadata: Option<String>,
+) -> Result<(), rocksdb::Error> {
+    txn.put_cf(columns.digest, blob_key(id), digest)?;
+    match metadata {
+        Some(metadata) => txn.put_cf(columns.metadata, blob_key(id), metadata),
+        None => Ok(()),
+    }
+}
+
+/// Store a new BLOB as requested, returning its ID and the digest of its data. If a BLOB with the
+/// requested ID or idempotency key already exists, nothing is stored and that BLOB is returned.
+fn store_blob(
+    db: &TransactionDB,
+    columns: &BlobColumns<'_>,
+    external: &ExternalFiles,
+    request: blob::StoreRequest,
+) -> Result<blob::StoreResponse, Status> {
+    let blob::StoreRequest {
+        bytes,
+        metadata,
+        attributes,
+        compression,
+        id,
+        idempotency_key,
+    } = request;
+    let compression = Compression::try_from(compression).map_err(into_tonic_status)?;
+    let existing = |id, digest| blob::StoreResponse {
+        id,
+        digest,
+        already_exists: true,
+    };
+
+    let txn = db.transaction();
+    // The key and ID are locked, so that concurrent requests store no more than one BLOB.
+    if let Some(key) = &idempotency_key {
+        let stored_id = txn
+            .get_for_update_cf(columns.idempotency, key, true)
+            .map_err(into_tonic_status)?;
+        if let Some(stored_id) = stored_id {
+            let stored_id = <[u8; 8]>::try_from(stored_id.as_slice())
+                .map(u64::from_be_bytes)
+                .map_err(|_| Status::data_loss(format!("idempotency key {key} is corrupt")))?;
+            return Ok(existing(stored_id, blob_digest(db, columns, stored_id)?));
+        }
+    }
+    let id = match id {
+        Some(id) => {
+            let digest = txn
+                .get_for_update_cf(columns.digest, blob_key(id), true)
+                .map_err(into_tonic_status)?;
+            if let Some(digest) = digest {
+                return Ok(existing(id, digest));
+            }
+            id
+        }
+        None => unused_blob_id(db, columns)?,
+    };
+
+    let content_id = unused_content_id(db, columns.chunk)?;
+    let digest = store_content(&txn, columns, external, content_id, &bytes, compression)?;
+    let attributes = helpers::encode_attributes(attributes);
+    put_blob(&txn, columns, id, &digest, metadata)
+        .and_then(|()| set_attributes(&txn, columns, id, &attributes))
+        .and_then(|()| match &idempotency_key {
+            Some(key) => txn
+                .put_cf(columns.idempotency, key, blob_key(id))
+                .and_then(|()| txn.put_cf(columns.idempotency_key, blob_key(id), key)),
+            None => Ok(()),
+        })
+        .and_then(|()| txn.commit())
+        .map_err(into_tonic_status)?;
+    Ok(blob::StoreResponse {
+        id,
+        digest,
+        already_exists: false,
+    })
+}
+
+/// Add attributes to a BLOB, replacing any existing attributes with the same names.
+fn set_attributes(
+    txn: &Transaction<'_, TransactionDB>,
+    columns: &BlobColumns<'_>,
+    id: u64,
+    attributes: &[StoredAttribute],
+) -> Result<(), rocksdb::Error> {
+    for (name, value) in attributes {
+        txn.put_cf(columns.attribute, attribute_key(id, name), value)?;
+    }
+    Ok(())
+}
+
+/// Read the key and value of each attribute of a BLOB.
+fn attribute_entries(
+    db: &impl Reader,
+    columns: &BlobColumns<'_>,
+    id: u64,
+) -> Result<Vec<(Box<[u8]>, Box<[u8]>)>, rocksdb::Error> {
+    let prefix = blob_key(id);
+    let mut entries = Vec::new();
+    for entry in db.iterator_cf(
+        columns.attribute,
+        IteratorMode::From(&prefix, Direction::Forward),
+    ) {
+        let (key, value) = entry?;
+        if !key.starts_with(&prefix) {
+            break;
+        }
+        entries.push((key, value));
+    }
+    Ok(entries)
+}
+
+/// Read the attributes of a BLOB as they are stored.
+fn stored_attributes(
+    db: &impl Reader,
+    columns: &BlobColumns<'_>,
+    id: u64,
+) -> Result<Vec<StoredAttribute>, Status> {
+    let prefix_len = blob_key(id).len();
+    attribute_entries(db, columns, id)
+        .map_err(into_tonic_status)?
+        .into_iter()
+        .map(|(key, value)| {
+            let name = helpers::stored_string(key[prefix_len..].to_vec(), "attribute name")?;
+            Ok((name, value.into_vec()))
+        })
+        .collect()
+}
+
+/// Read the attributes of a BLOB. Returns `None` if the BLOB has no attributes.
+fn read_attributes(
+    db: &impl Reader,
+    columns: &BlobColumns<'_>,
+    id: u64,
+) -> Result<Option<prost_types::Struct>, Status> {
+    helpers::decode_attributes(stored_attributes(db, columns, id)?)
+}
+
+/// Update the data, metadata, and attributes of a BLOB. The data or metadata is not changed if it
+/// is `None`. New data is compressed with the algorithm given alongside it.
+fn update_blob(
+    db: &TransactionDB,
+    columns: &BlobColumns<'_>,
+    external: &ExternalFiles,
+    id: u64,
+    data: Option<(&[u8], Compression)>,
+    metadata: Option<Option<String>>,
+    attributes: &AttributeChanges,
+) -> Result<(), Status> {
+    let txn = db.transaction();
+    let old_digest = txn
+        .get_for_update_cf(columns.digest, blob_key(id), true)
+        .map_err(into_tonic_status)?;
+    // Updating a BLOB that does not exist has no effect.
+    let Some(old_digest) = old_digest else {
+        return Ok(());
+    };
+    let released = if let Some((data, compression)) = data {
+        // The new content is referenced before the old content is released, so content shared by
+        // both is never deleted.
+        let content_id = unused_content_id(db, columns.chunk)?;
+        let digest = store_content(&txn, columns, external, content_id, data, compression)?;
+        txn.put_cf(columns.digest, blob_key(id), digest)
+            .and_then(|()| remove_content_ref(db, &txn, columns, &old_digest))
+            .map_err(into_tonic_status)?
+    } else {
+        None
+    };
+    if let Some(metadata) = metadata {
+        if let Some(metadata) = metadata {
+            txn.put_cf(columns.metadata, blob_key(id), metadata)
+        } else {
+            txn.delete_cf(columns.metadata, blob_key(id))
+        }
+        .map_err(into_tonic_status)?;
+    }
+    for name in &attributes.removed {
+        txn.delete_cf(columns.attribute, attribute_key(id, name))
+            .map_err(into_tonic_status)?;
+    }
+    set_attributes(&txn, columns, id, &attributes.set)
+        .and_then(|()| txn.commit())
+        .map_err(into_tonic_status)?;
+    if let Some(path) = released {
+        external.remove(&path);
+    }
+    Ok(())
+}
+
+/// Delete a BLOB and its attributes, along with its content if no other BLOB refers to it.
+///
+/// Returns the name of the file storing the content that was deleted, if any, which is to be
+/// removed once the transaction is committed.
+fn delete_blob(
+    db: &TransactionDB,
+    txn: &Transaction<'_, TransactionDB>,
+    columns: &BlobColumns<'_>,
+    id: u64,
+) -> Result<Option<String>, rocksdb::Error> {
+    let digest = txn.get_for_update_cf(columns.digest, blob_key(id), true)?;
+    txn.delete_cf(columns.digest, blob_key(id))?;
+    txn.delete_cf(columns.metadata, blob_key(id))?;
+    if let Some(key) = txn.get_for_update_cf(columns.idempotency_key, blob_key(id), true)? {
+        txn.delete_cf(columns.idempotency, key)?;
+        txn.delete_cf(columns.idempotency_key, blob_key(id))?;
+    }
+    for (key, _) in attribute_entries(db, columns, id)? {
+        txn.delete_cf(columns.attribute, key)?;
+    }
+    match digest {
+        Some(digest) => remove_content_ref(db, txn, columns, &digest),
+        None => Ok(None),
+    }
+}
+
+/// Duplicate a BLOB under a new ID, along with its attributes, returning the ID of the copy. The
+/// metadata of the original BLOB is copied if `metadata` is `None`.
+fn copy_blob(
+    db: &TransactionDB,
+    columns: &BlobColumns<'_>,
+    id: u64,
+    metadata: Option<Option<String>>,
+) -> Result<u64, Status> {
+    let copy_id = unused_blob_id(db, columns)?;
+    let txn = db.transaction();
+    let digest = txn
+        .get_for_update_cf(columns.digest, blob_key(id), true)
+        .map_err(into_tonic_status)?
+        .ok_or_else(|| helpers::blob_not_found(id))?;
+    let metadata = match metadata {
+        Some(metadata) => metadata,
+        None => read_metadata(db, columns, id)?,
+    };
+    let attributes = stored_attributes(db, columns, id)?;
+    if !add_content_ref(&txn, columns, &digest).map_err(into_tonic_status)? {
+        return Err(Status::data_loss(format!(
+            "the content of BLOB {id} is missing"
+        )));
+    }
+    put_blob(&txn, columns, copy_id, &digest, metadata)
+        .and_then(|()| set_attributes(&txn, columns, copy_id, &attributes))
+        .and_then(|()| txn.commit())
+        .map_err(into_tonic_status)?;
+    Ok(copy_id)
+}
+
+/// Append data to a BLOB, compressing the resulting data with the requested algorithm. Returns the
+/// new size of the BLOB.
+///
+/// Uncompressed content that is to remain uncompressed is read in chunks to compute the digest of
+/// the new content, to which the data is then appended as a new chunk, while compressed content is
+/// read in full and rewritten.
+fn append_blob(
+    db: &TransactionDB,
+    columns: &BlobColumns<'_>,
+    external: &ExternalFiles,
+    id: u64,
+    bytes: &[u8],
+    compression: Compression,
+) -> Result<u64, Status> {
+    let txn = db.transaction();
+    let old_digest = txn
+        .get_for_update_cf(columns.digest, blob_key(id), true)
+        .map_err(into_tonic_status)?
+        .ok_or_else(|| helpers::blob_not_found(id))?;
+    // The BLOB is locked, so its content cannot change before the transaction ends.
+    let content = blob_content(db, columns, id)?;
+    let (size, released) = if compression != Compression::None || !content.is_uncompressed() {
+        let mut data = read_content(db, columns.chunk, external, &content)?;
+        data.extend_from_slice(bytes);
+
+        // The new content is referenced before the old content is released, so content shared by
+        // both is never deleted.
+        let content_id = unused_content_id(db, columns.chunk)?;
+        let digest = store_content(&txn, columns, external, content_id, &data, compression)?;
+        let released = txn
+            .put_cf(columns.digest, blob_key(id), digest)
+            .and_then(|()| remove_content_ref(db, &txn, columns, &old_digest))
+            .map_err(into_tonic_status)?;
+        (data.len() as u64, released)
+    } else {
+        let mut verifier = helpers::Verifier::default();
+        for piece in content_pieces(db, columns.chunk, external, &content) {
+            verifier.update(&piece?.1);
+        }
+        let digest = verifier.finish_appending(&content.digest, bytes)?;
+        let size = content
+            .size
+            .checked_add(bytes.len() as u64)
+            .ok_or_else(|| Status::out_of_range("BLOB is too large"))?;
+        // The new content is referenced before the old content is released, so content shared by
+        // both is never deleted.
+        let released = if add_content_ref(&txn, columns, &digest).map_err(into_tonic_status)? {
+            txn.put_cf(columns.digest, blob_key(id), digest)
+                .and_then(|()| remove_content_ref(db, &txn, columns, &old_digest))
+                .map_err(into_tonic_status)?
+        } else {
+            txn.put_cf(columns.digest, blob_key(id), &digest)
+                .map_err(into_tonic_status)?;
+            append_content(db, &txn, columns, external, &content, &digest, bytes)?
+        };
+        (size, released)
+    };
+    txn.commit().map_err(into_tonic_status)?;
+    if let Some(path) = released {
+        external.remove(&path);
+    }
+    Ok(size)
+}
+
+/// Store new uncompressed content consisting of an existing uncompressed content followed by
+/// `bytes`, in place of the existing content, which is released.
+///
+/// If nothing else refers to the existing content and both are kept in the database, `bytes` is
+/// added to the existing content as a new chunk rather than copying it. Returns the name of the
+/// file storing the content that was released, if any, which is to be removed once the transaction
+/// is committed.
+fn append_content(
+    db: &TransactionDB,
+    txn: &Transaction<'_, TransactionDB>,
+    columns: &BlobColumns<'_>,
+    external: &ExternalFiles,
+    content: &Content,
+    digest: &[u8],
+    bytes: &[u8],
+) -> Result<Option<String>, Status> {
+    let size = content.size + bytes.len() as u64;
+    let path = external.write_with(digest, size, |file| {
+        for piece in content_pieces(db, columns.chunk, external, content) {
+            file.write_all(&piece?.1).map_err(helpers::file_status)?;
+        }
+        file.write_all(bytes).map_err(helpers::file_status)
+    })?;
+    if path.is_none() && content.path.is_none() {
+        let existing =
+            content_for_update(txn, columns, &content.digest).map_err(into_tonic_status)?;
+        if let Some((content_id, 1)) = existing {
+            txn.delete_cf(columns.content, &content.digest)
+                .and_then(|()| {
+                    txn.put_cf(columns.chunk, chunk_key(content_id, content.size), bytes)
+                })
+                .and_then(|()| {
+                    put_content(
+                        txn,
+                        columns,
+                        content_id,
+                        digest,
+                        size,
+                        Compression::None,
+                        None,
+                    )
+                })
+                .map_err(into_tonic_status)?;
+            return Ok(None);
+        }
+    }
+
+    let content_id = unused_content_id(db, columns.chunk)?;
+    if path.is_none() {
+        for piece in content_pieces(db, columns.chunk, external, content) {
+            let (offset, chunk) = piece?;
+            txn.put_cf(columns.chunk, chunk_key(content_id, offset), chunk)
+                .map_err(into_tonic_status)?;
+        }
+        txn.put_cf(columns.chunk, chunk_key(content_id, content.size), bytes)
+            .map_err(into_tonic_status)?;
+    }
+    put_content(
+        txn,
+        columns,
+        content_id,
+        digest,
+        size,
+        Compression::None,
+        path.as_deref(),
+    )
+    .and_then(|()| remove_content_ref(db, txn, columns, &content.digest))
+    .map_err(into_tonic_status)
+}
+
+/// Read an uncompressed content in chunks of at most [`helpers::CONTENT_CHUNK_SIZE`] bytes, along
+/// with the offset of each.
+fn content_pieces<'a>(
+    db: &'a impl Reader,
+    chunk_col: &'a ColumnFamily,
+    external: &'a ExternalFiles,
+    content: &'a Content,
+) -> impl Iterator<Item = Result<(u64, Vec<u8>), Status>> + 'a {
+    (0..content.size)
+        .step_by(helpers::CONTENT_CHUNK_SIZE)
+        .map(move |offset| {
+            // The length is at most the chunk size, which fits in a `usize`.
+            let len = (content.size - offset).min(helpers::CONTENT_CHUNK_SIZE as u64) as usize;
+            read_content_range(db, chunk_col, external, content, offset, len)
+                .map(|chunk| (offset, chunk))
+        })
+}
+
+/// The version of the layout of the BLOB store that is written by this version of the crate.
+///
+/// Stores written by earlier versions, including those written before the version was recorded,
+/// are upgraded once, when first connected to. The version they were upgraded to is then recorded
+/// in the `blob_schema` column family, so that the upgrade, which removes data once it has been
+/// moved, is never repeated.
+const BLOB_SCHEMA_VERSION: u64 = 1;
+
+/// Upgrade a BLOB store written by an earlier version of the crate to the current layout, unless it
+/// has already been upgraded.
+fn upgrade_blob_store(db: &TransactionDB) -> Result<(), rocksdb::Error> {
+    let Some(schema_col) = db.cf_handle("blob_schema") else {
+        return Ok(());
+    };
+    let version = db
+        .get_cf(schema_col, VERSION_KEY)?
+        .and_then(|bytes| Some(u64::from_be_bytes(bytes.as_slice().try_into().ok()?)))
+        .unwrap_or(0);
+    if version >= BLOB_SCHEMA_VERSION {
+        return Ok(());
+    }
+    // Stores created before deduplication was supported hold the data of each BLOB in the `data`
+    // column family, or in chunks if it was uploaded.
+    migrate_blob_data(db)?;
+    migrate_uploaded_blobs(db)?;
+    // The keys are rewritten in the same transaction as the version is recorded, as rewriting
+    // them again would reverse them.
+    let txn = db.transaction();
+    migrate_blob_keys(db, &txn)?;
+    txn.put_cf(schema_col, VERSION_KEY, BLOB_SCHEMA_VERSION.to_be_bytes())?;
+    txn.commit()
+}
+
+/// Move BLOBs stored before deduplication was supported into the column families storing content.
+///
+/// Such BLOBs are stored whole in the `data` column family, keyed by their ID. The ID of each BLOB
+/// is reused as the ID of its content, as all such BLOBs are moved before any other content is
+/// stored.
+fn migrate_blob_data(db: &TransactionDB) -> Result<(), rocksdb::Error> {
+    let (Some(data_col), Ok(columns)) = (db.cf_handle("data"), blob_columns(db)) else {
+        return Ok(());
+    };
+    // Each BLOB is moved in its own transaction to avoid holding every BLOB in memory at once.
+    for entry in db.iterator_cf(data_col, IteratorMode::Start) {
+        let (key, data) = entry?;
+        let Ok(id) = <[u8; 8]>::try_from(&*key) else {
+            continue;
+        };
+        let id = u64::from_le_bytes(id);
+        let txn = db.transaction();
+        // Migrated content is kept in the database, where it was already stored.
+        let digest = helpers::digest(&data);
+        if !add_content_ref(&txn, &columns, &digest)? {
+            if !data.is_empty() {
+                txn.put_cf(columns.chunk, chunk_key(id, 0), &*data)?;
+            }
+            put_content(
+                &txn,
+                &columns,
+                id,
+                &digest,
+                data.len() as u64,
+                Compression::None,
+                None,
+            )?;
+        }
+        txn.put_cf(columns.digest, blob_key(id), digest)?;
+        txn.delete_cf(data_col, &key)?;
+        txn.commit()?;
+    }
+    Ok(())
+}
+
+/// Rewrite the keys of BLOBs stored before BLOBs could be listed, which are little-endian, as
+/// big-endian.
+///
+/// The metadata of every BLOB was keyed little-endian until now, so every metadata key is
+/// rewritten. Digests have been keyed big-endian since listing was supported, so whether each digest
+/// key is little-endian is inferred from the metadata and content stored alongside it, as the
+/// content of a BLOB migrated from the `data` column family shares its ID. Digest keys with no such
+/// evidence are taken to be in the order most of the others are in.
+fn migrate_blob_keys(
+    db: &TransactionDB,
+    txn: &Transaction<'_, TransactionDB>,
+) -> Result<(), rocksdb::Error> {
+    let Ok(columns) = blob_columns(db) else {
+        return Ok(());
+    };
+    let mut digests = Vec::new();
+    let (mut little_endian, mut big_endian) = (0_usize, 0_usize);
+    for entry in db.iterator_cf(columns.digest, IteratorMode::Start) {
+        let (key, digest) = entry?;
+        let Ok(key) = <[u8; 8]>::try_from(&*key) else {
+            continue;
+        };
+        let mut reversed = key;
+        reversed.reverse();
+        let content_id_key = db
+            .get_cf(columns.content, &digest)?
+            .and_then(|bytes| decode_content(&bytes))
+            .map(|(content_id, _)| content_key(content_id));
+        let is_keyed = |key: [u8; 8]| -> Result<bool, rocksdb::Error> {
+            Ok(db.get_cf(columns.metadata, key)?.is_some() || content_id_key == Some(key))
+        };
+        let is_little_endian = match (is_keyed(key)?, is_keyed(reversed)?) {
+            (true, false) => Some(true),
+            (false, true) => Some(false),
+            _ => None,
+        };
+        match is_little_endian {
+            Some(true) => little_endian += 1,
+            Some(false) => big_endian += 1,
+            None => {}
+        }
+        digests.push((key, digest, is_little_endian));
+    }
+    // Every old key is deleted before any new key is written, as a new key may equal an old one.
+    let mut rewritten = Vec::new();
+    for (key, digest, is_little_endian) in digests {
+        if is_little_endian.unwrap_or(little_endian > big_endian) {
+            txn.delete_cf(columns.digest, key)?;
+            rewritten.push((u64::from_le_bytes(key), digest));
+        }
+    }
+    let mut metadata = Vec::new();
+    for entry in db.iterator_cf(columns.metadata, IteratorMode::Start) {
+        let (key, value) = entry?;
+        let Ok(key) = <[u8; 8]>::try_from(&*key) else {
+            continue;
+        };
+        txn.delete_cf(columns.metadata, key)?;
+        metadata.push((u64::from_le_bytes(key), value));
+    }
+    for (id, digest) in rewritten {
+        txn.put_cf(columns.digest, blob_key(id), digest)?;
+    }
+    for (id, value) in metadata {
+        txn.put_cf(columns.metadata, blob_key(id), value)?;
+    }
+    Ok(())
+}
+
+/// Refer to BLOBs uploaded before deduplication was supported as content.
+///
+/// The size and chunks of such BLOBs are already stored as those of content are, keyed by the ID of
+/// the BLOB, but no content refers to them. The ID of each BLOB is reused as the ID of its content,
+/// unless the same content is already stored, in which case its chunks are deleted. BLOBs whose
+/// chunks do not make up the whole of their data are left as they are, as their digest is unknown.
+fn migrate_uploaded_blobs(db: &TransactionDB) -> Result<(), rocksdb::Error> {
+    let Ok(columns) = blob_columns(db) else {
+        return Ok(());
+    };
+    let mut content_ids = HashSet::new();
+    for entry in db.iterator_cf(columns.content, IteratorMode::Start) {
+        let (_, bytes) = entry?;
+        if let Some((content_id, _)) = decode_content(&bytes) {
+            let _is_new = content_ids.insert(content_id);
+        }
+    }
+    // The size of an uploaded BLOB is stored alone under its ID, as that of uncompressed content is.
+    let mut uploaded = Vec::new();
+    for entry in db.iterator_cf(columns.chunk, IteratorMode::Start) {
+        let (key, size) = entry?;
+        let (Ok(id), Ok(size)) = (<[u8; 8]>::try_from(&*key), <[u8; 8]>::try_from(&*size)) else {
+            continue;
+        };
+        let id = u64::from_le_bytes(id);
+        if !content_ids.contains(&id) {
+            uploaded.push((id, u64::from_be_bytes(size)));
+        }
+    }
+    // Each BLOB is read and moved separately to avoid holding every BLOB in memory at once.
+    for (id, size) in uploaded {
+        let mut upload = helpers::Upload::default();
+        let prefix = content_key(id);
+        for entry in db.iterator_cf(
+            columns.chunk,
+            IteratorMode::From(&prefix, Direction::Forward),
+        ) {
+            let (key, bytes) = entry?;
+            if !key.starts_with(&prefix) {
+                break;
+            }
+            let Some(offset) = decode_chunk_key(id, &key) else {
+                continue;
+            };
+            let chunk = blob::BlobChunk {
+                offset,
+                bytes: bytes.into_vec(),
+            };
+            if upload.accept(&chunk).is_err() {
+                break;
+            }
+        }
+        let (uploaded_size, digest) = upload.finish();
+        if uploaded_size != size {
+            continue;
+        }
+        let txn = db.transaction();
+        if add_content_ref(&txn, &columns, &digest)? {
+            delete_content_chunks(db, &txn, columns.chunk, id)?;
+        } else {
+            txn.put_cf(columns.content, &digest, encode_content(id, 1))?;
+        }
+        txn.put_cf(columns.digest, blob_key(id), digest)?;
+        txn.commit()?;
+    }
+    Ok(())
+}
+
+/// Get the digest of the data of a BLOB.
+fn blob_digest(db: &impl Reader, columns: &BlobColumns<'_>, id: u64) -> Result<Vec<u8>, Status> {
+    db.get_cf(columns.digest, blob_key(id))
+        .map_err(into_tonic_status)?
+        .ok_or_else(|| helpers::blob_not_found(id))
+}
+
+/// Get the content of a BLOB.
+fn blob_content(db: &impl Reader, columns: &BlobColumns<'_>, id: u64) -> Result<Content, Status> {
+    let digest = blob_digest(db, columns, id)?;
+    let missing = || Status::data_loss(format!("the content of BLOB {id} is missing"));
+    let (content_id, _) = db
+        .get_cf(columns.content, &digest)
+        .map_err(into_tonic_status)?
+        .and_then(|bytes| decode_content(&bytes))
+        .ok_or_else(missing)?;
+    db.get_cf(columns.chunk, content_key(content_id))
+        .map_err(into_tonic_status)?
+        .and_then(|bytes| decode_size(content_id, &digest, &bytes))
+        .ok_or_else(missing)
+}
+
+/// Read a content in full, decompressing it if necessary and verifying it against its digest.
+fn read_content(
+    db: &impl Reader,
+    chunk_col: &ColumnFamily,
+    external: &ExternalFiles,
+    content: &Content,
+) -> Result<Vec<u8>, Status> {
+    let data = if let Some(path) = &content.path {
+        helpers::decompress(external.read(path)?, content.compression, content.size)?
+    } else if content.is_uncompressed() {
+        read_content_chunks(db, chunk_col, content.id, 0, content.size as usize)?
+    } else {
+        // Compressed content is stored as a single chunk.
+        let bytes = db
+            .get_cf(chunk_col, chunk_key(content.id, 0))
+            .map_err(into_tonic_status)?
+            .ok_or_else(|| {
+                Status::data_loss(format!("content {} is missing chunks", content.id))
+            })?;
+        helpers::decompress(bytes, content.compression, content.size)?
+    };
+    helpers::verify(&data, &content.digest)?;
+    Ok(data)
+}
+
+/// Collect up to `limit` BLOBs, in ascending order of their IDs, starting from `start_id`, along
+/// with the digest of the data of each.
+fn blob_digests(
+    db: &impl Reader,
+    columns: &BlobColumns<'_>,
+    start_id: u64,
+    limit: usize,
+) -> Result<Vec<(u64, Vec<u8>)>, Status> {
+    let start = blob_key(start_id);
+    let mut blobs = Vec::new();
+    // BLOB keys are big-endian, so BLOBs are iterated in order of their IDs.
+    for entry in db.iterator_cf(
+        columns.digest,
+        IteratorMode::From(&start, Direction::Forward),
+    ) {
+        if blobs.len() == limit {
+            break;
+        }
+        let (key, digest) = entry.map_err(into_tonic_status)?;
+        let Ok(id) = <[u8; 8]>::try_from(&*key) else {
+            continue;
+        };
+        blobs.push((u64::from_be_bytes(id), digest.into_vec()));
+    }
+    Ok(blobs)
+}
+
+/// Verify the content of a BLOB against its digest, returning a description of the problem if it is
+/// corrupted or missing.
+///
+/// Uncompressed content is read and verified one chunk at a time, while compressed content is read
+/// in full.
+fn content_corruption(
+    db: &impl Reader,
+    columns: &BlobColumns<'_>,
+    external: &ExternalFiles,
+    id: u64,
+) -> Result<Option<String>, Status> {
+    helpers::corruption(blob_content(db, columns, id).and_then(|content| {
+        if !content.is_uncompressed() {
+            return read_content(db, columns.chunk, external, &content).map(drop);
+        }
+        let mut verifier = helpers::Verifier::default();
+        content_pieces(db, columns.chunk, external, &content).try_for_each(|piece| {
+            verifier.update(&piece?.1);
+            Ok(())
+        })?;
+        verifier.finish(&content.digest)
+    }))
+}
+
+/// Read `len` bytes of a content, beginning at `offset`. The content must not be compressed.
+///
+/// The range read is not verified against the digest of the content.
+fn read_content_range(
+    db: &impl Reader,
+    chunk_col: &ColumnFamily,
+    external: &ExternalFiles,
+    content: &Content,
+    offset: u64,
+    len: usize,
+) -> Result<Vec<u8>, Status> {
+    match &content.path {
+        Some(path) => external.read_range(path, offset, len),
+        // Only the chunks overlapping the requested range are read.
+        None => read_content_chunks(db, chunk_col, content.id, offset, len),
+    }
+}
+
+/// Read `len` bytes of a content, beginning at `offset`. The content must not be compressed.
+fn read_content_chunks(
+    db: &impl Reader,
+    chunk_col: &ColumnFamily,
+    content_id: u64,
+    offset: u64,
+    len: usize,
+) -> Result<Vec<u8>, Status> {
+    let end = offset.saturating_add(len as u64);
+    // The requested range begins within the last chunk beginning at or before the offset.
+    let start_key = chunk_key(content_id, offset);
+    let first_offset = match db
+        .iterator_cf(
+            chunk_col,
+            IteratorMode::From(&start_key, Direction::Reverse),
+        )
+        .next()
+    {
+        Some(entry) => {
+            let (key, _) = entry.map_err(into_tonic_status)?;
+            decode_chunk_key(content_id, &key).unwrap_or(0)
+        }
+        None => 0,
+    };
+
+    let mut bytes = Vec::with_capacity(len);
+    let start_key = chunk_key(content_id, first_offset);
+    for entry in db.iterator_cf(
+        chunk_col,
+        IteratorMode::From(&start_key, Direction::Forward),
+    ) {
+        let (key, chunk) = entry.map_err(into_tonic_status)?;
+        let Some(chunk_offset) = decode_chunk_key(content_id, &key) else {
+            break;
+        };
+        if chunk_offset >= end {
+            break;
+        }
+        // Both bounds are within the chunk, whose length fits in a `usize`.
+        let from = offset.saturating_sub(chunk_offset).min(chunk.len() as u64) as usize;
+        let to = (end - chunk_offset).min(chunk.len() as u64) as usize;
+        bytes.extend_from_slice(&chunk[from..to]);
+    }
+
+    if bytes.len() != len {
+        return Err(Status::data_loss(format!(
+            "content {content_id} is missing chunks"
+        )));
+    }
+    Ok(bytes)
+}
+
+/// Collect up to `limit` of the BLOBs matching a listing, in ascending order of their IDs, starting
+/// from `start_id`. The range, metadata, and attributes requested are respected, but not the limit.
+///
+/// Also returns the ID to continue the listing from if the page is full.
+fn list_blobs(
+    db: &impl Reader,
+    columns: &BlobColumns<'_>,
+    request: &blob::ListRequest,
+    start_id: u64,
+    limit: usize,
+) -> Result<(Vec<blob::ListResponse>, Option<u64>), Status> {
+    let blob::ListRequest {
+        metadata,
+        metadata_prefix,
+        end_id,
+        attributes,
+        ..
+    } = request;
+    let required_attributes = helpers::encode_attributes(attributes.clone());
+    let start = blob_key(start_id);
+    let mut blobs = Vec::new();
+    for entry in db.iterator_cf(
+        columns.digest,
+        IteratorMode::From(&start, Direction::Forward),
+    ) {
+        let (key, _) = entry.map_err(into_tonic_status)?;
+        let Ok(id) = <[u8; 8]>::try_from(&*key) else {
+            continue;
+        };
+        let id = u64::from_be_bytes(id);
+        if end_id.is_some_and(|end_id| id >= end_id) {
+            break;
+        }
+
+        // There is no index on metadata, so it is read for every BLOB in the range.
+        let blob_metadata = read_metadata(db, columns, id)?;
+        let is_match = (metadata.is_none() && metadata_prefix.is_none())
+            || blob_metadata.as_deref().is_some_and(|value| {
+                metadata.as_deref().is_none_or(|metadata| value == metadata)
+                    && metadata_prefix
+                        .as_deref()
+                        .is_none_or(|prefix| value.starts_with(prefix))
+            });
+        if !is_match {
+            continue;
+        }
+        let blob_attributes = stored_attributes(db, columns, id)?;
+        if !required_attributes
+            .iter()
+            .all(|attribute| blob_attributes.contains(attribute))
+        {
+            continue;
+        }
+        let content = blob_content(db, columns, id)?;
+        blobs.push(blob::ListResponse {
+            id,
+            metadata: blob_metadata,
+            size: content.size,
+            attributes: helpers::decode_attributes(blob_attributes)?,
+        });
+        if blobs.len() >= limit {
+            return Ok((blobs, id.checked_add(1)));
+        }
+    }
+    Ok((blobs, None))
+}
+
+/// Read the metadata of a BLOB, if it has any.
+fn read_metadata(
+    db: &impl Reader,
+    columns: &BlobColumns<'_>,
+    id: u64,
+) -> Result<Option<String>, Status> {
+    db.get_cf(columns.metadata, blob_key(id))
+        .map_err(into_tonic_status)?
+        .map(|value| helpers::stored_string(value, "metadata"))
+        .transpose()
+}
+
+/// Write the chunks of a content to a file, in order of their offsets.
+fn write_content_chunks(
+    db: &TransactionDB,
+    chunk_col: &ColumnFamily,
+    content_id: u64,
+    file: &mut File,
+) -> Result<(), Status> {
+    let start = chunk_key(content_id, 0);
+    for entry in db.iterator_cf(chunk_col, IteratorMode::From(&start, Direction::Forward)) {
+        let (key, chunk) = entry.map_err(into_tonic_status)?;
+        if decode_chunk_key(content_id, &key).is_none() {
+            break;
+        }
+        file.write_all(&chunk).map_err(helpers::file_status)?;
+    }
+    Ok(())
+}
+
+/// Delete the size and chunks of a content.
+fn delete_content_chunks(
+    db: &TransactionDB,
+    txn: &Transaction<'_, TransactionDB>,
+    chunk_col: &ColumnFamily,
+    content_id: u64,
+) -> Result<(), rocksdb::Error> {
+    let prefix = content_key(content_id);
+    for entry in db.iterator_cf(chunk_col, IteratorMode::From(&prefix, Direction::Forward)) {
+        let (key, _) = entry?;
+        if !key.starts_with(&prefix) {
+            break;
+        }
+        txn.delete_cf(chunk_col, key)?;
+    }
+    Ok(())
+}
+
+/// A BLOB being uploaded in chunks.
+///
+/// Chunks are written as they are received, under an ID for new content. Until the upload is
+/// finalized, the content is not visible, as its size has not been stored. If the upload is dropped
+/// before then, its chunks are deleted.
+struct PendingUpload<'a> {
+    /// The database the BLOB is being written to.
+    db: &'a TransactionDB,
+    /// The column families storing the BLOB store.
+    columns: &'a BlobColumns<'a>,
+    /// The files storing large content.
+    external: &'a ExternalFiles,
+    /// The ID the chunks are stored under.
+    content_id: u64,
+    /// The chunks received so far.
+    progress: helpers::Upload,
+    /// Whether the BLOB has been stored, in which case its chunks must be kept.
+    is_finalized: bool,
+}
+
+impl<'a> PendingUpload<'a> {
+    /// Begin uploading a new BLOB.
+    fn new(
+        db: &'a TransactionDB,
+        columns: &'a BlobColumns<'a>,
+        external: &'a ExternalFiles,
+    ) -> Result<Self, Status> {
+        Ok(Self {
+            db,
+            columns,
+            external,
+            content_id: unused_content_id(db, columns.chunk)?,
+            progress: helpers::Upload::default(),
+            is_finalized: false,
+        })
+    }
+
+    /// Write the next chunk of the BLOB.
+    fn write(&mut self, chunk: blob::BlobChunk) -> Result<(), Status> {
+        let offset = self.progress.accept(&chunk)?;
+        if chunk.bytes.is_empty() {
+            return Ok(());
+        }
+        self.db
+            .put_cf(
+                self.columns.chunk,
+                chunk_key(self.content_id, offset),
+                chunk.bytes,
+            )
+            .map_err(into_tonic_status)
+    }
+
+    /// Finish uploading the BLOB, storing it along with its metadata and attributes. Returns the
+    /// ID, size, and digest of the BLOB.
+    fn finalize(
+        mut self,
+        compression: Compression,
+        metadata: Option<String>,
+        attributes: &[StoredAttribute],
+    ) -> Result<blob::UploadResponse, Status> {
+        let (size, digest) = std::mem::take(&mut self.progress).finish();
+        let id = unused_blob_id(self.db, self.columns)?;
+        let txn = self.db.transaction();
+        if add_content_ref(&txn, self.columns, &digest).map_err(into_tonic_status)? {
+            // The content is already stored, so the chunks are not needed.
+            delete_content_chunks(self.db, &txn, self.columns.chunk, self.content_id)
+                .map_err(into_tonic_status)?;
+        } else if compression != Compression::None {
+            // Compressed content is stored as a single chunk, which replaces those uploaded.
+            let data = read_content_chunks(
+                self.db,
+                self.columns.chunk,
+                self.content_id,
+                0,
+                size as usize,
+            )?;
+            delete_content_chunks(self.db, &txn, self.columns.chunk, self.content_id)
+                .map_err(into_tonic_status)?;
+            insert_content(
+                &txn,
+                self.columns,
+                self.external,
+                self.content_id,
+                &digest,
+                &data,
+                compression,
+            )?;
+        } else {
+            // Large content is moved from its chunks to a file.
+            let path = self.external.write_with(&digest, size, |file| {
+                write_content_chunks(self.db, self.columns.chunk, self.content_id, file)
+            })?;
+            if path.is_some() {
+                delete_content_chunks(self.db, &txn, self.columns.chunk, self.content_id)
+                    .map_err(into_tonic_status)?;
+            }
+            put_content(
+                &txn,
+                self.columns,
+                self.content_id,
+                &digest,
+                size,
+                Compression::None,
+                path.as_deref(),
+            )
+            .map_err(into_tonic_status)?;
+        }
+        put_blob(&txn, self.columns, id, &digest, metadata)
+            .and_then(|()| set_attributes(&txn, self.columns, id, attributes))
+            .and_then(|()| txn.commit())
+            .map_err(into_tonic_status)?;
+        self.is_finalized = true;
+        Ok(blob::UploadResponse { id, size, digest })
+    }
+}
+
+impl Drop for PendingUpload<'_> {
+    fn drop(&mut self) {
+        if self.is_finalized {
+            return;
+        }
+        let txn = self.db.transaction();
+        // There is nowhere to report a failure, and any remaining chunks are not visible.
+        let _res = delete_content_chunks(self.db, &txn, self.columns.chunk, self.content_id)
+            .and_then(|()| txn.commit());
+    }
+}
+
 impl DatabaseBackend for RocksDb {
     type Connection = TransactionDB;
     type Error = rocksdb::Error;
 
     fn at_location(location: Location) -> Result<Self, Self::Error> {
-        Ok(Self { location })
+        Ok(Self {
+            external: ExternalFiles::new(&location),
+            location,
+            sweeper: Sweeper::default(),
+            watchers: Watchers::default(),
+        })
     }
 
     fn location(&self) -> &Location {
         &self.location
     }
 
-    fn connect(&self) -> Result<Self::Connection, Self::Error> {
-        match &self.location() {
-            #[allow(clippy::unimplemented)]
-            Location::InMemory => unimplemented!(),
-            Location::OnDisk { path } => {
-                let mut opts = rocksdb::Options::default();
-                opts.create_if_missing(true);
-                opts.create_missing_column_families(true);
-                let txn_opts = rocksdb::TransactionDBOptions::default();
-                let fields = vec!["data", "metadata"];
-                Self::Connection::open_cf(&opts, &txn_opts, path, fields)
+    fn connect(&self) -> Result<Self::Connection, Self::Error> {
+        open(&self.location)
+    }
+}
+
+/// Open the database at the given location, along with every column family.
+fn open(location: &Location) -> Result<TransactionDB, rocksdb::Error> {
+    match location {
+        #[allow(clippy::unimplemented)]
+        Location::InMemory => unimplemented!(),
+        Location::OnDisk { path } => {
+            let mut opts = rocksdb::Options::default();
+            opts.create_if_missing(true);
+            opts.create_missing_column_families(true);
+            let txn_opts = rocksdb::TransactionDBOptions::default();
+            // Every column family must be opened, including those created for namespaces. The
+            // listing fails if the database does not exist yet, in which case there are none.
+            let mut fields = TransactionDB::list_cf(&opts, path).unwrap_or_default();
+            for field in [
+                "data",
+                "metadata",
+                "blob_attribute",
+                "blob_digest",
+                "blob_content",
+                "blob_chunk",
+                "blob_idempotency",
+                "blob_idempotency_key",
+                "blob_schema",
+                "kv_expiry",
+                "kv_history",
+                "kv_version",
+            ] {
+                if !fields.iter().any(|existing| existing == field) {
+                    fields.push(field.to_owned());
+                }
+            }
+            TransactionDB::open_cf(&opts, &txn_opts, path, fields)
+        }
+    }
+}
+
+#[async_trait]
+impl KvBackend for RocksDb {
+    type GetStream = DynStream<Result<kv::GetResponse, Status>>;
+    type SetStream = DynStream<Result<kv::SetResponse, Status>>;
+    type DeleteStream = DynStream<Result<kv::DeleteResponse, Status>>;
+    type ScanStream = DynStream<Result<kv::ScanResponse, Status>>;
+    type CompareAndSwapStream = DynStream<Result<kv::CompareAndSwapResponse, Status>>;
+    type TransactionStream = DynStream<Result<kv::TransactionResponse, Status>>;
+    type WatchStream = DynStream<Result<kv::WatchResponse, Status>>;
+    type IncrementStream = DynStream<Result<kv::IncrementResponse, Status>>;
+    type HistoryStream = DynStream<Result<kv::HistoryResponse, Status>>;
+
+    #[cfg_attr(feature = "tracing", tracing::instrument)]
+    fn connect_kv(&self) -> Result<Self::Connection, Self::Error> {
+        let db = self.connect()?;
+        let now = helpers::now_millis();
+        if self.sweeper.is_due(now) {
+            // Unlike other backends, expired pairs are removed before the connection is returned.
+            // Only one handle to the database can be open at a time, so the sweep cannot open one
+            // of its own in the background. Expired pairs remain invisible if the sweep fails, and
+            // are removed by a later sweep.
+            let _res = remove_expired(&db, now);
+        }
+        Ok(db)
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument)]
+    async fn get(&self, request: StreamingRequest<kv::GetRequest>) -> RpcResponse<Self::GetStream> {
+        let namespace = Namespace::from_metadata(request.metadata())?;
+        let mut stream = request.into_inner();
+        let db = self.connect_kv().map_err(into_tonic_status)?;
+        let stream = stream!({
+            let columns = kv_columns(&db, &namespace)?;
+
+            while let Some(kv::GetRequest {
+                key,
+                allow_missing,
+                version,
+            }) = stream.message().await?
+            {
+                let value = get_versioned(&db, &columns, &key, version)?;
+                yield helpers::get_response(&key, value, allow_missing);
+            }
+        })
+        .instrument(trace_span!("RocksDB kv get query"));
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument)]
+    async fn set(&self, request: StreamingRequest<kv::SetRequest>) -> RpcResponse<Self::SetStream> {
+        let namespace = Namespace::from_metadata(request.metadata())?;
+        let mut stream = request.into_inner();
+        let db = self.connect_kv().map_err(into_tonic_status)?;
+        let watchers = self.watchers.in_namespace(&namespace);
+        let stream = stream!({
+            let columns = kv_columns(&db, &namespace)?;
+
+            while let Some(kv::SetRequest {
+                key,
+                value,
+                expiration,
+            }) = stream.message().await?
+            {
+                let expires_at = helpers::expires_at(expiration, helpers::now_millis())?;
+                let txn = db.transaction();
+                let watched = watchers.is_watched();
+                let old_value = if watched {
+                    get_unexpired_for_update(&txn, &columns, &key)?
+                } else {
+                    None
+                };
+                let version = next_version(&db, &txn)?;
+                put(&txn, &columns, &key, version, &value, expires_at)?;
+                txn.commit().map_err(into_tonic_status)?;
+                if watched {
+                    watchers.notify([helpers::put_change(key.clone(), old_value, value)]);
+                }
+                yield Ok(kv::SetResponse { key, version });
+            }
+        })
+        .instrument(trace_span!("RocksDB kv set query"));
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument)]
+    async fn delete(
+        &self,
+        request: StreamingRequest<kv::DeleteRequest>,
+    ) -> RpcResponse<Self::DeleteStream> {
+        let namespace = Namespace::from_metadata(request.metadata())?;
+        let mut stream = request.into_inner();
+        let db = self.connect_kv().map_err(into_tonic_status)?;
+        let watchers = self.watchers.in_namespace(&namespace);
+        let stream = stream!({
+            let columns = kv_columns(&db, &namespace)?;
+
+            while let Some(kv::DeleteRequest { key }) = stream.message().await? {
+                let txn = db.transaction();
+                let old_value = get_unexpired_for_update(&txn, &columns, &key)?;
+                // Deleting a key that does not exist is not a write.
+                if old_value.is_some() {
+                    let version = next_version(&db, &txn)?;
+                    record_write(&txn, &columns, &key, version, None)?;
+                }
+                txn.delete_cf(columns.data, &key)
+                    .and_then(|_| txn.delete_cf(columns.expiry, &key))
+                    .and_then(|_| txn.commit())
+                    .map_err(into_tonic_status)?;
+                // Nothing has changed if the key did not exist.
+                watchers.notify(
+                    old_value.map(|old_value| helpers::delete_change(key.clone(), old_value)),
+                );
+                yield Ok(kv::DeleteResponse { key });
+            }
+        })
+        .instrument(trace_span!("RocksDB kv delete query"));
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument)]
+    async fn eq(&self, request: StreamingRequest<kv::EqRequest>) -> RpcResponse<bool> {
+        let namespace = Namespace::from_metadata(request.metadata())?;
+        let mut stream = request.into_inner();
+        let db = self.connect_kv().map_err(into_tonic_status)?;
+        let stream = Box::pin(stream!({
+            let columns = kv_columns(&db, &namespace)?;
+
+            while let Some(kv::EqRequest { key }) = stream.message().await? {
+                let Some(value) = get_unexpired(&db, &columns, &key)? else {
+                    return Err(Status::not_found(format!("key {key} not found")))?;
+                };
+                yield Ok::<_, Status>(value);
+            }
+        }))
+        .instrument(trace_span!("RocksDB kv eq query"));
+        Ok(Response::new(helpers::all_eq(stream).await?))
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument)]
+    async fn not_eq(&self, request: StreamingRequest<kv::NotEqRequest>) -> RpcResponse<bool> {
+        let namespace = Namespace::from_metadata(request.metadata())?;
+        let mut stream = request.into_inner();
+        let db = self.connect_kv().map_err(into_tonic_status)?;
+        let stream = Box::pin(stream!({
+            let columns = kv_columns(&db, &namespace)?;
+
+            while let Some(kv::NotEqRequest { key }) = stream.message().await? {
+                let Some(value) = get_unexpired(&db, &columns, &key)? else {
+                    return Err(Status::not_found(format!("key {key} not found")))?;
+                };
+                yield Ok::<_, Status>(value);
+            }
+        }))
+        .instrument(trace_span!("RocksDB kv not_eq query"));
+        Ok(Response::new(helpers::all_not_eq(stream).await?))
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument)]
+    async fn scan(
+        &self,
+        request: StreamingRequest<kv::ScanRequest>,
+    ) -> RpcResponse<Self::ScanStream> {
+        let namespace = Namespace::from_metadata(request.metadata())?;
+        let mut stream = request.into_inner();
+        let db = self.connect_kv().map_err(into_tonic_status)?;
+        let stream = stream!({
+            while let Some(kv::ScanRequest {
+                prefix,
+                start,
+                end,
+                limit,
+                reverse,
+            }) = stream.message().await?
+            {
+                let range = KeyRange::new(prefix, start, end);
+                let limit = limit.map_or(usize::MAX, |limit| {
+                    usize::try_from(limit).unwrap_or(usize::MAX)
+                });
+                let pairs = scan_range(&db, &namespace, range, limit, reverse)?;
+                for (key, value) in pairs {
+                    yield Ok(kv::ScanResponse {
+                        key: helpers::stored_string(key.into_vec(), "key")?,
+                        value: value.into_vec(),
+                    });
+                }
+            }
+        })
+        .instrument(trace_span!("RocksDB kv scan query"));
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument)]
+    async fn compare_and_swap(
+        &self,
+        request: StreamingRequest<kv::CompareAndSwapRequest>,
+    ) -> RpcResponse<Self::CompareAndSwapStream> {
+        let namespace = Namespace::from_metadata(request.metadata())?;
+        let mut stream = request.into_inner();
+        let db = self.connect_kv().map_err(into_tonic_status)?;
+        let watchers = self.watchers.in_namespace(&namespace);
+        let stream = stream!({
+            let columns = kv_columns(&db, &namespace)?;
+
+            while let Some(kv::CompareAndSwapRequest {
+                key,
+                expected_value,
+                new_value,
+                expiration,
+            }) = stream.message().await?
+            {
+                let expires_at =
+                    helpers::expires_at(expiration.map(Into::into), helpers::now_millis())?;
+                let txn = db.transaction();
+                let current_value = get_unexpired_for_update(&txn, &columns, &key)?;
+                if current_value != expected_value {
+                    Err(helpers::compare_and_swap_failure(&key, current_value))?;
+                }
+                let version = next_version(&db, &txn)?;
+                put(&txn, &columns, &key, version, &new_value, expires_at)?;
+                txn.commit().map_err(into_tonic_status)?;
+                watchers.notify([helpers::put_change(key.clone(), expected_value, new_value)]);
+                yield Ok(kv::CompareAndSwapResponse { key });
             }
-        }
+        })
+        .instrument(trace_span!("RocksDB kv compare_and_swap query"));
+        Ok(Response::new(Box::pin(stream)))
     }
-}
-
-#[async_trait]
-impl KvBackend for RocksDb {
-    type GetStream = DynStream<Result<kv::GetResponse, Status>>;
-    type SetStream = DynStream<Result<kv::SetResponse, Status>>;
-    type DeleteStream = DynStream<Result<kv::DeleteResponse, Status>>;
 
     #[cfg_attr(feature = "tracing", tracing::instrument)]
-    async fn get(&self, request: StreamingRequest<kv::GetRequest>) -> RpcResponse<Self::GetStream> {
+    async fn transaction(
+        &self,
+        request: StreamingRequest<kv::TransactionRequest>,
+    ) -> RpcResponse<Self::TransactionStream> {
+        let namespace = Namespace::from_metadata(request.metadata())?;
         let mut stream = request.into_inner();
         let db = self.connect_kv().map_err(into_tonic_status)?;
+        let watchers = self.watchers.in_namespace(&namespace);
         let stream = stream!({
-            while let Some(kv::GetRequest { key }) = stream.message().await? {
-                let Some(value) = db.get(&key).map_err(into_tonic_status)? else {
-                    return Err(Status::not_found(format!("key {key} not found")))?;
+            let columns = kv_columns(&db, &namespace)?;
+            // If the stream ends before the transaction is committed, its writes are discarded.
+            let mut txn = Some(BufferedTransaction::default());
+
+            while let Some(kv::TransactionRequest { operation }) = stream.message().await? {
+                let active_txn = txn
+                    .as_mut()
+                    .ok_or_else(|| Status::failed_precondition("transaction has already ended"))?;
+                let outcome = match operation {
+                    Some(Operation::Get(kv::GetRequest {
+                        key,
+                        allow_missing,
+                        version,
+                    })) => {
+                        let value = active_txn.get(&key, version, || {
+                            get_versioned(&db, &columns, &key, version)
+                        })?;
+                        Outcome::Get(helpers::get_response(&key, value, allow_missing)?)
+                    }
+                    Some(Operation::Set(kv::SetRequest {
+                        key,
+                        value,
+                        expiration,
+                    })) => {
+                        let expires_at = helpers::expires_at(expiration, helpers::now_millis())?;
+                        active_txn.set(key.clone(), value, expires_at);
+                        Outcome::Set(kv::SetResponse { key, version: 0 })
+                    }
+                    Some(Operation::Delete(kv::DeleteRequest { key })) => {
+                        active_txn.delete(key.clone());
+                        Outcome::Delete(kv::DeleteResponse { key })
+                    }
+                    Some(Operation::Commit(_)) => {
+                        let db_txn = db.transaction();
+                        let (versions, changes) = active_txn.apply(
+                            &mut TransactionColumns {
+                                db: &db,
+                                txn: &db_txn,
+                                columns: &columns,
+                            },
+                            watchers.is_watched(),
+                        )?;
+                        db_txn.commit().map_err(into_tonic_status)?;
+                        txn = None;
+                        watchers.notify(changes);
+                        Outcome::Commit(kv::Commit { versions })
+                    }
+                    Some(Operation::Rollback(rollback)) => {
+                        txn = None;
+                        Outcome::Rollback(rollback)
+                    }
+                    None => Err(Status::invalid_argument("no operation was provided"))?,
                 };
-                yield Ok(kv::GetResponse {
-                    value: String::from_utf8(value)
-                        .expect("protobuf requires strings be valid UTF-8"),
+                yield Ok(kv::TransactionResponse {
+                    outcome: Some(outcome),
                 });
             }
         })
-        .instrument(trace_span!("RocksDB kv get query"));
+        .instrument(trace_span!("RocksDB kv transaction query"));
         Ok(Response::new(Box::pin(stream)))
     }
 
     #[cfg_attr(feature = "tracing", tracing::instrument)]
-    async fn set(&self, request: StreamingRequest<kv::SetRequest>) -> RpcResponse<Self::SetStream> {
-        let mut stream = request.into_inner();
+    async fn watch(
+        &self,
+        request: tonic::Request<kv::WatchRequest>,
+    ) -> RpcResponse<Self::WatchStream> {
+        let namespace = Namespace::from_metadata(request.metadata())?;
         let db = self.connect_kv().map_err(into_tonic_status)?;
-        let stream = stream!({
-            while let Some(kv::SetRequest { key, value }) = stream.message().await? {
-                db.put(&key, value.as_bytes()).map_err(into_tonic_status)?;
-                yield Ok(kv::SetResponse { key });
-            }
-        })
-        .instrument(trace_span!("RocksDB kv set query"));
+        let _columns = kv_columns(&db, &namespace)?;
+        let stream = self
+            .watchers
+            .in_namespace(&namespace)
+            .watch(request.into_inner())?
+            .instrument(trace_span!("RocksDB kv watch query"));
         Ok(Response::new(Box::pin(stream)))
     }
 
     #[cfg_attr(feature = "tracing", tracing::instrument)]
-    async fn delete(
+    async fn increment(
         &self,
-        request: StreamingRequest<kv::DeleteRequest>,
-    ) -> RpcResponse<Self::DeleteStream> {
+        request: StreamingRequest<kv::IncrementRequest>,
+    ) -> RpcResponse<Self::IncrementStream> {
+        let namespace = Namespace::from_metadata(request.metadata())?;
         let mut stream = request.into_inner();
         let db = self.connect_kv().map_err(into_tonic_status)?;
+        let watchers = self.watchers.in_namespace(&namespace);
         let stream = stream!({
-            while let Some(kv::DeleteRequest { key }) = stream.message().await? {
-                db.delete(&key).map_err(into_tonic_status)?;
-                yield Ok(kv::DeleteResponse { key });
+            let columns = kv_columns(&db, &namespace)?;
+
+            while let Some(kv::IncrementRequest {
+                key,
+                delta,
+                expiration,
+            }) = stream.message().await?
+            {
+                let txn = db.transaction();
+                let old_value = get_unexpired_for_update(&txn, &columns, &key)?;
+                let value = helpers::increment(&key, old_value.as_deref(), delta)?;
+                let new_value = value.to_string().into_bytes();
+                let version = next_version(&db, &txn)?;
+                if old_value.is_none() {
+                    let expires_at =
+                        helpers::expires_at(expiration.map(Into::into), helpers::now_millis())?;
+                    put(&txn, &columns, &key, version, &new_value, expires_at)?;
+                } else {
+                    // Pairs that already exist keep their expiration.
+                    record_write(&txn, &columns, &key, version, Some(new_value.as_slice()))?;
+                    txn.put_cf(columns.data, &key, &new_value)
+                        .map_err(into_tonic_status)?;
+                }
+                txn.commit().map_err(into_tonic_status)?;
+                watchers.notify([helpers::put_change(key.clone(), old_value, new_value)]);
+                yield Ok(kv::IncrementResponse { key, value });
             }
         })
-        .instrument(trace_span!("RocksDB kv delete query"));
+        .instrument(trace_span!("RocksDB kv increment query"));
         Ok(Response::new(Box::pin(stream)))
     }
 
     #[cfg_attr(feature = "tracing", tracing::instrument)]
-    async fn eq(&self, request: StreamingRequest<kv::EqRequest>) -> RpcResponse<bool> {
-        let mut stream = request.into_inner();
+    async fn create_namespace(
+        &self,
+        request: tonic::Request<kv::CreateNamespaceRequest>,
+    ) -> RpcResponse<kv::CreateNamespaceResponse> {
+        let kv::CreateNamespaceRequest { name } = request.into_inner();
+        let namespace = Namespace::named(name.clone())?;
+        let mut db = self.connect_kv().map_err(into_tonic_status)?;
+        let [data_name, expiry_name, history_name] = column_family_names(&namespace);
+        if db.cf_handle(&data_name).is_some() {
+            return Err(namespace.already_exists());
+        }
+        let opts = rocksdb::Options::default();
+        // The namespace exists once its data column family does, so that is created last. Any
+        // companion left behind by an earlier creation that failed part way through is reused.
+        for column in [expiry_name, history_name] {
+            if db.cf_handle(&column).is_none() {
+                db.create_cf(&column, &opts).map_err(into_tonic_status)?;
+            }
+        }
+        db.create_cf(&data_name, &opts).map_err(into_tonic_status)?;
+        Ok(Response::new(kv::CreateNamespaceResponse { name }))
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument)]
+    async fn list_namespaces(
+        &self,
+        _request: tonic::Request<kv::ListNamespacesRequest>,
+    ) -> RpcResponse<kv::ListNamespacesResponse> {
         let db = self.connect_kv().map_err(into_tonic_status)?;
-        let stream = Box::pin(stream!({
-            while let Some(kv::EqRequest { key }) = stream.message().await? {
-                let Some(value) = db.get(&key).map_err(into_tonic_status)? else {
-                    return Err(Status::not_found(format!("key {key} not found")))?;
-                };
-                yield Ok::<_, Status>(value);
+        let names = namespaces(&db)
+            .map_err(into_tonic_status)?
+            .into_iter()
+            .filter_map(|namespace| namespace.name().map(ToOwned::to_owned))
+            .collect();
+        Ok(Response::new(kv::ListNamespacesResponse { names }))
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument)]
+    async fn drop_namespace(
+        &self,
+        request: tonic::Request<kv::DropNamespaceRequest>,
+    ) -> RpcResponse<kv::DropNamespaceResponse> {
+        let kv::DropNamespaceRequest { name } = request.into_inner();
+        let namespace = Namespace::named(name.clone())?;
+        let mut db = self.connect_kv().map_err(into_tonic_status)?;
+        let [data_name, expiry_name, history_name] = column_family_names(&namespace);
+        if db.cf_handle(&data_name).is_none() {
+            return Err(namespace.not_found());
+        }
+        db.drop_cf(&data_name).map_err(into_tonic_status)?;
+        for column in [expiry_name, history_name] {
+            if db.cf_handle(&column).is_some() {
+                db.drop_cf(&column).map_err(into_tonic_status)?;
             }
-        }))
-        .instrument(trace_span!("RocksDB kv eq query"));
-        Ok(Response::new(helpers::all_eq(stream).await?))
+        }
+        Ok(Response::new(kv::DropNamespaceResponse { name }))
     }
 
     #[cfg_attr(feature = "tracing", tracing::instrument)]
-    async fn not_eq(&self, request: StreamingRequest<kv::NotEqRequest>) -> RpcResponse<bool> {
+    async fn history(
+        &self,
+        request: StreamingRequest<kv::HistoryRequest>,
+    ) -> RpcResponse<Self::HistoryStream> {
+        let namespace = Namespace::from_metadata(request.metadata())?;
         let mut stream = request.into_inner();
         let db = self.connect_kv().map_err(into_tonic_status)?;
-        let stream = Box::pin(stream!({
-            while let Some(kv::NotEqRequest { key }) = stream.message().await? {
-                let Some(value) = db.get(&key).map_err(into_tonic_status)? else {
-                    return Err(Status::not_found(format!("key {key} not found")))?;
-                };
-                yield Ok::<_, Status>(value);
+        let stream = stream!({
+            let columns = kv_columns(&db, &namespace)?;
+
+            while let Some(kv::HistoryRequest { key }) = stream.message().await? {
+                for write in writes(&db, &columns, &key)? {
+                    yield Ok(write);
+                }
             }
-        }))
-        .instrument(trace_span!("RocksDB kv not_eq query"));
-        Ok(Response::new(helpers::all_not_eq(stream).await?))
+        })
+        .instrument(trace_span!("RocksDB kv history query"));
+        Ok(Response::new(Box::pin(stream)))
     }
 }
 
@@ -150,6 +2393,21 @@ impl BlobBackend for RocksDb {
     type StoreStream = DynStream<Result<blob::StoreResponse, Status>>;
     type UpdateStream = DynStream<Result<blob::UpdateResponse, Status>>;
     type DeleteStream = DynStream<Result<blob::DeleteResponse, Status>>;
+    type UploadStream = DynStream<Result<blob::UploadResponse, Status>>;
+    type DownloadStream = DynStream<Result<blob::DownloadResponse, Status>>;
+    type ListStream = DynStream<Result<blob::ListResponse, Status>>;
+    type CopyStream = DynStream<Result<blob::CopyResponse, Status>>;
+    type AppendStream = DynStream<Result<blob::AppendResponse, Status>>;
+    type VerifyStream = DynStream<Result<blob::VerifyResponse, Status>>;
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(connection)))]
+    fn initialize(&self, connection: &Self::Connection) -> Result<(), Self::Error> {
+        upgrade_blob_store(connection)
+    }
+
+    fn set_external_threshold(&self, threshold: Option<u64>) {
+        self.external.set_threshold(threshold);
+    }
 
     #[cfg_attr(feature = "tracing", tracing::instrument)]
     async fn get(
@@ -158,27 +2416,31 @@ impl BlobBackend for RocksDb {
     ) -> RpcResponse<Self::GetStream> {
         let mut stream = request.into_inner();
         let db = self.connect_blob().map_err(into_tonic_status)?;
+        let external = self.external.clone();
 
         let stream = stream!({
-            let data_col = cf_handle!(db, "data")?;
-            let metadata_col = cf_handle!(db, "metadata")?;
-
-            while let Some(blob::GetRequest { id }) = stream.message().await? {
-                let Some(data) = db
-                    .get_cf(data_col, id.to_le_bytes())
-                    .map_err(into_tonic_status)?
-                else {
-                    return Err(Status::not_found(format!("id {id} not found")))?;
+            let columns = blob_columns(&db)?;
+
+            while let Some(blob::GetRequest { id, offset, length }) = stream.message().await? {
+                // The BLOB is read from a snapshot, so that its size, data, and attributes are all
+                // read from the same state of the database.
+                let snapshot = db.snapshot();
+                let content = blob_content(&snapshot, &columns, id)?;
+                let (offset, len) = helpers::byte_range(content.size, offset, length)?;
+                let data = if content.is_uncompressed() && len as u64 != content.size {
+                    read_content_range(&snapshot, columns.chunk, &external, &content, offset, len)?
+                } else {
+                    let data = read_content(&snapshot, columns.chunk, &external, &content)?;
+                    data[offset as usize..][..len].to_vec()
                 };
-                let metadata = db
-                    .get_cf(metadata_col, id.to_le_bytes())
-                    .map_err(into_tonic_status)?
-                    .map(|value| {
-                        String::from_utf8(value).expect("protobuf requires strings be valid UTF-8")
-                    });
+                let metadata = read_metadata(&snapshot, &columns, id)?;
+                let attributes = read_attributes(&snapshot, &columns, id)?;
+                drop(snapshot);
                 yield Ok(blob::GetResponse {
                     bytes: data,
                     metadata,
+                    attributes,
+                    digest: content.digest,
                 });
             }
         })
@@ -193,45 +2455,17 @@ impl BlobBackend for RocksDb {
     ) -> RpcResponse<Self::StoreStream> {
         let mut stream = request.into_inner();
         let db = self.connect_blob().map_err(into_tonic_status)?;
+        let external = self.external.clone();
 
         let stream = stream!({
-            let data_col = cf_handle!(db, "data")?;
-            let metadata_col = cf_handle!(db, "metadata")?;
-
-            while let Some(blob::StoreRequest { bytes, metadata }) = stream.message().await? {
-                let mut id = generate_id();
-                let mut id_bytes = id.to_le_bytes();
-
-                // Check for a collision of the generated identifier. If there is one, try once more
-                // before erroring. Note that there is a TOCTOU issue here, but the odds of any
-                // collision at all is so low that it is hardly worth worrying about that. If this
-                // somehow becomes a plausible issue, the ID can be extended to 128 bits from the
-                // current 64, rendering a collision all but impossible.
-                if matches!(db.get_cf(data_col, id_bytes), Ok(Some(_))) {
-                    id = generate_id();
-                    id_bytes = id.to_le_bytes();
-
-                    if matches!(db.get_cf(data_col, id_bytes), Ok(Some(_))) {
-                        return Err(Status::internal("failed to generate unique id"))?;
-                    }
-                };
-
-                let txn = db.transaction();
-                txn.put_cf(data_col, id_bytes, bytes)
-                    .map_err(into_tonic_status)?;
-
-                if let Some(metadata) = metadata {
-                    txn.put_cf(metadata_col, id_bytes, metadata)
-                } else {
-                    Ok(())
-                }
-                .map_err(into_tonic_status)?;
+            let columns = blob_columns(&db)?;
 
-                txn.commit().map_err(into_tonic_status)?;
-                yield Ok(blob::StoreResponse { id });
+            while let Some(request) = stream.message().await? {
+                let response = store_blob(&db, &columns, &external, request)?;
+                yield Ok(response);
             }
         })
-        .instrument(trace_span!("RocksDB blob get query"));
+        .instrument(trace_span!("RocksDB blob store query"));
         Ok(Response::new(Box::pin(stream)))
     }
 
@@ -242,33 +2476,26 @@ impl BlobBackend for RocksDb {
     ) -> RpcResponse<Self::UpdateStream> {
         let mut stream = request.into_inner();
         let db = self.connect_blob().map_err(into_tonic_status)?;
+        let external = self.external.clone();
 
         let stream = stream!({
-            let data_col = cf_handle!(db, "data")?;
-            let metadata_col = cf_handle!(db, "metadata")?;
+            let columns = blob_columns(&db)?;
 
             while let Some(blob::UpdateRequest {
                 id,
                 bytes,
                 should_update_metadata,
                 metadata,
+                attributes,
+                remove_attributes,
+                compression,
             }) = stream.message().await?
             {
-                let txn = db.transaction();
-                if let Some(bytes) = bytes {
-                    txn.put_cf(data_col, id.to_le_bytes(), &bytes)
-                        .map_err(into_tonic_status)?;
-                }
-
-                if should_update_metadata {
-                    if let Some(metadata) = metadata {
-                        txn.put_cf(metadata_col, id.to_le_bytes(), metadata)
-                    } else {
-                        txn.delete_cf(metadata_col, id.to_le_bytes())
-                    }
-                    .map_err(into_tonic_status)?;
-                }
-                txn.commit().map_err(into_tonic_status)?;
+                let compression = Compression::try_from(compression).map_err(into_tonic_status)?;
+                let data = bytes.as_deref().map(|bytes| (bytes, compression));
+                let metadata = should_update_metadata.then_some(metadata);
+                let attributes = AttributeChanges::new(remove_attributes, attributes);
+                update_blob(&db, &columns, &external, id, data, metadata, &attributes)?;
                 yield Ok(blob::UpdateResponse { id });
             }
         })
@@ -283,14 +2510,19 @@ impl BlobBackend for RocksDb {
     ) -> RpcResponse<Self::DeleteStream> {
         let mut stream = request.into_inner();
         let db = self.connect_blob().map_err(into_tonic_status)?;
+        let external = self.external.clone();
+
         let stream = stream!({
-            let data_col = cf_handle!(db, "data")?;
-            let metadata_col = cf_handle!(db, "metadata")?;
+            let columns = blob_columns(&db)?;
 
             while let Some(blob::DeleteRequest { id }) = stream.message().await? {
-                db.delete_cf(data_col, id.to_le_bytes())
-                    .and_then(|_| db.delete_cf(metadata_col, id.to_le_bytes()))
+                let txn = db.transaction();
+                let released = delete_blob(&db, &txn, &columns, id)
+                    .and_then(|released| txn.commit().map(|()| released))
                     .map_err(into_tonic_status)?;
+                if let Some(path) = released {
+                    external.remove(&path);
+                }
                 yield Ok(blob::DeleteResponse { id });
             }
         })
@@ -304,16 +2536,11 @@ impl BlobBackend for RocksDb {
         let db = self.connect_blob().map_err(into_tonic_status)?;
 
         let stream = Box::pin(stream!({
-            let data_col = cf_handle!(db, "data")?;
+            let columns = blob_columns(&db)?;
 
             while let Some(blob::EqDataRequest { id }) = stream.message().await? {
-                let Some(value) = db
-                    .get_cf(data_col, id.to_le_bytes())
-                    .map_err(into_tonic_status)?
-                else {
-                    return Err(Status::not_found(format!("id {id} not found")))?;
-                };
-                yield Ok::<_, Status>(value);
+                // BLOBs with identical data have identical digests, so the data need not be read.
+                yield blob_digest(&db, &columns, id);
             }
         }))
         .instrument(trace_span!("RocksDB blob eq_data query"));
@@ -329,19 +2556,459 @@ impl BlobBackend for RocksDb {
         let db = self.connect_blob().map_err(into_tonic_status)?;
 
         let stream = Box::pin(stream!({
-            let data_col = cf_handle!(db, "data")?;
+            let columns = blob_columns(&db)?;
 
             while let Some(blob::NotEqDataRequest { id }) = stream.message().await? {
-                let Some(value) = db
-                    .get_cf(data_col, id.to_le_bytes())
-                    .map_err(into_tonic_status)?
-                else {
-                    return Err(Status::not_found(format!("id {id} not found")))?;
-                };
-                yield Ok::<_, Status>(value);
+                // BLOBs with identical data have identical digests, so the data need not be read.
+                yield blob_digest(&db, &columns, id);
             }
         }))
         .instrument(trace_span!("RocksDB blob not_eq_data query"));
         Ok(Response::new(helpers::all_not_eq(stream).await?))
     }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument)]
+    async fn upload(
+        &self,
+        request: StreamingRequest<blob::UploadRequest>,
+    ) -> RpcResponse<Self::UploadStream> {
+        let mut stream = request.into_inner();
+        let db = self.connect_blob().map_err(into_tonic_status)?;
+        let external = self.external.clone();
+
+        let stream = stream!({
+            let columns = blob_columns(&db)?;
+            let mut upload = PendingUpload::new(&db, &columns, &external)?;
+
+            while let Some(blob::UploadRequest { part }) = stream.message().await? {
+                match part {
+                    Some(upload_request::Part::Chunk(chunk)) => upload.write(chunk)?,
+                    Some(upload_request::Part::Finalize(blob::Finalize {
+                        metadata,
+                        attributes,
+                        compression,
+                    })) => {
+                        let compression =
+                            Compression::try_from(compression).map_err(into_tonic_status)?;
+                        let next_upload = PendingUpload::new(&db, &columns, &external)?;
+                        let response = std::mem::replace(&mut upload, next_upload).finalize(
+                            compression,
+                            metadata,
+                            &helpers::encode_attributes(attributes),
+                        )?;
+                        yield Ok(response);
+                    }
+                    None => Err(Status::invalid_argument(
+                        "no chunk or finalization was provided",
+                    ))?,
+                }
+            }
+        })
+        .instrument(trace_span!("RocksDB blob upload query"));
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument)]
+    async fn download(
+        &self,
+        request: StreamingRequest<blob::DownloadRequest>,
+    ) -> RpcResponse<Self::DownloadStream> {
+        let mut stream = request.into_inner();
+        let db = self.connect_blob().map_err(into_tonic_status)?;
+        let external = self.external.clone();
+
+        let stream = stream!({
+            let columns = blob_columns(&db)?;
+
+            while let Some(blob::DownloadRequest { id, chunk_size }) = stream.message().await? {
+                // Each BLOB is read from a snapshot, so that its chunks are all read from the same
+                // state of the database.
+                let snapshot = db.snapshot();
+                let content = blob_content(&snapshot, &columns, id)?;
+                let metadata = read_metadata(&snapshot, &columns, id)?;
+                let attributes = read_attributes(&snapshot, &columns, id)?;
+                let chunks = helpers::chunks(content.size, chunk_size)?;
+                // Compressed content is decompressed in full before being split into chunks.
+                // Uncompressed content is verified as its chunks are read.
+                let (data, mut verifier) = if content.is_uncompressed() {
+                    (None, Some(helpers::Verifier::default()))
+                } else {
+                    (
+                        Some(read_content(&snapshot, columns.chunk, &external, &content)?),
+                        None,
+                    )
+                };
+
+                yield Ok(helpers::download_info(
+                    id,
+                    content.size,
+                    content.digest.clone(),
+                    metadata,
+                    attributes,
+                ));
+                for (offset, len) in chunks {
+                    let bytes = match &data {
+                        Some(data) => data[offset as usize..][..len].to_vec(),
+                        None => read_content_range(
+                            &snapshot,
+                            columns.chunk,
+                            &external,
+                            &content,
+                            offset,
+                            len,
+                        )?,
+                    };
+                    if let Some(verifier) = &mut verifier {
+                        verifier.update(&bytes);
+                    }
+                    yield Ok(helpers::download_chunk(id, offset, bytes));
+                }
+                if let Some(verifier) = verifier {
+                    verifier.finish(&content.digest)?;
+                }
+            }
+        })
+        .instrument(trace_span!("RocksDB blob download query"));
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument)]
+    async fn list(
+        &self,
+        request: tonic::Request<blob::ListRequest>,
+    ) -> RpcResponse<Self::ListStream> {
+        let request = request.into_inner();
+        let db = self.connect_blob().map_err(into_tonic_status)?;
+
+        let stream = stream!({
+            let columns = blob_columns(&db)?;
+            // Every page is collected from the same snapshot, so the listing is consistent.
+            let snapshot = db.snapshot();
+            let mut start_id = request.start_id.unwrap_or(0);
+            let mut remaining = request.limit.map_or(usize::MAX, |limit| {
+                usize::try_from(limit).unwrap_or(usize::MAX)
+            });
+            while remaining > 0 {
+                let page_size = remaining.min(helpers::LIST_PAGE_SIZE);
+                let (blobs, next_id) =
+                    list_blobs(&snapshot, &columns, &request, start_id, page_size)?;
+                remaining -= blobs.len();
+                for blob in blobs {
+                    yield Ok(blob);
+                }
+                let Some(next_id) = next_id else {
+                    break;
+                };
+                start_id = next_id;
+            }
+            drop(snapshot);
+        })
+        .instrument(trace_span!("RocksDB blob list query"));
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument)]
+    async fn copy(
+        &self,
+        request: StreamingRequest<blob::CopyRequest>,
+    ) -> RpcResponse<Self::CopyStream> {
+        let mut stream = request.into_inner();
+        let db = self.connect_blob().map_err(into_tonic_status)?;
+
+        let stream = stream!({
+            let columns = blob_columns(&db)?;
+
+            while let Some(blob::CopyRequest {
+                id,
+                should_update_metadata,
+                metadata,
+            }) = stream.message().await?
+            {
+                let metadata = should_update_metadata.then_some(metadata);
+                let id = copy_blob(&db, &columns, id, metadata)?;
+                yield Ok(blob::CopyResponse { id });
+            }
+        })
+        .instrument(trace_span!("RocksDB blob copy query"));
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument)]
+    async fn append(
+        &self,
+        request: StreamingRequest<blob::AppendRequest>,
+    ) -> RpcResponse<Self::AppendStream> {
+        let mut stream = request.into_inner();
+        let db = self.connect_blob().map_err(into_tonic_status)?;
+        let external = self.external.clone();
+
+        let stream = stream!({
+            let columns = blob_columns(&db)?;
+
+            while let Some(blob::AppendRequest {
+                id,
+                bytes,
+                compression,
+            }) = stream.message().await?
+            {
+                let compression = Compression::try_from(compression).map_err(into_tonic_status)?;
+                let size = append_blob(&db, &columns, &external, id, &bytes, compression)?;
+                yield Ok(blob::AppendResponse { id, size });
+            }
+        })
+        .instrument(trace_span!("RocksDB blob append query"));
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument)]
+    async fn verify(
+        &self,
+        _request: tonic::Request<blob::VerifyRequest>,
+    ) -> RpcResponse<Self::VerifyStream> {
+        let db = self.connect_blob().map_err(into_tonic_status)?;
+        let external = self.external.clone();
+
+        let stream = stream!({
+            let columns = blob_columns(&db)?;
+            // The result of verifying each content, by its digest, so that content shared by
+            // several BLOBs is verified once.
+            let mut verified = HashMap::<Vec<u8>, Option<String>>::new();
+            // Every page is collected from the same snapshot, so content is not deleted while it
+            // is read.
+            let snapshot = db.snapshot();
+            let mut start_id = 0;
+            loop {
+                let blobs = blob_digests(&snapshot, &columns, start_id, helpers::LIST_PAGE_SIZE)?;
+                let next_id = blobs
+                    .last()
+                    .filter(|_| blobs.len() == helpers::LIST_PAGE_SIZE)
+                    .and_then(|&(id, _)| id.checked_add(1));
+                for (id, digest) in blobs {
+                    let message = match verified.get(&digest) {
+                        Some(message) => message.clone(),
+                        None => {
+                            let message = content_corruption(&snapshot, &columns, &external, id)?;
+                            let _previous = verified.insert(digest, message.clone());
+                            message
+                        }
+                    };
+                    if let Some(message) = message {
+                        yield Ok(blob::VerifyResponse { id, message });
+                    }
+                }
+                let Some(next_id) = next_id else {
+                    break;
+                };
+                start_id = next_id;
+            }
+            drop(snapshot);
+        })
+        .instrument(trace_span!("RocksDB blob verify query"));
+        Ok(Response::new(Box::pin(stream)))
+    }
+}
+
+/// The most keys a `SCAN` returns, which is also the number it returns without a `LIMIT`. Rows are
+/// collected before being returned, so every scan must be bounded.
+const MAX_SCAN_LIMIT: u64 = 1_000;
+
+/// The most keys a `COUNT` visits, which is also the number it visits without a `LIMIT`. Counting
+/// blocks the thread running the stream, so every count must be bounded.
+const MAX_COUNT_LIMIT: u64 = 100_000;
+
+/// Whether a column family holds data stored by users, rather than data used internally by the
+/// store, such as versions and idempotency keys. Only these column families can be queried.
+fn is_queryable(name: &str) -> bool {
+    matches!(name, "default" | "metadata" | "blob_attribute") || name.starts_with(Namespace::PREFIX)
+}
+
+/// The column family with the given name, provided it can be queried.
+fn column_family<'a>(db: &'a TransactionDB, name: &str) -> Result<&'a ColumnFamily, Status> {
+    if !is_queryable(name) {
+        return Err(Status::invalid_argument(format!(
+            "column family {name} cannot be queried"
+        )));
+    }
+    db.cf_handle(name)
+        .ok_or_else(|| Status::not_found(format!("column family {name} does not exist")))
+}
+
+/// The column family holding the expirations of the pairs in the queryable column family with the
+/// given name, if it holds the pairs of a KV namespace.
+fn expiry_family<'a>(db: &'a TransactionDB, name: &str) -> Option<&'a ColumnFamily> {
+    let expiry = match name.strip_prefix(Namespace::PREFIX) {
+        Some(namespace) => format!("kv_expiry:{namespace}"),
+        None if name == "default" => "kv_expiry".to_owned(),
+        None => return None,
+    };
+    db.cf_handle(&expiry)
+}
+
+/// The header of a column of a query result. Values returned by RocksDB are never null.
+fn column(name: &str, declared_type: &str) -> query::Column {
+    query::Column {
+        name: name.to_owned(),
+        declared_type: Some(declared_type.to_owned()),
+        nullable: Some(false),
+    }
+}
+
+/// Visit each key-value pair within the range, in order of the keys. Expired pairs are skipped.
+fn visit_range(
+    db: &TransactionDB,
+    range: &Range,
+    mut visit: impl FnMut(Box<[u8]>, Box<[u8]>),
+) -> Result<(), Status> {
+    let family = column_family(db, &range.family)?;
+    let expiry = expiry_family(db, &range.family);
+    let now = helpers::now_millis();
+    let limit = range.limit.unwrap_or(u64::MAX);
+    let mut visited = 0;
+    for pair in db.iterator_cf(
+        family,
+        IteratorMode::From(range.start(), Direction::Forward),
+    ) {
+        if visited >= limit {
+            break;
+        }
+        let (key, value) = pair.map_err(into_tonic_status)?;
+        if !key.starts_with(&range.prefix) {
+            break;
+        }
+        if let Some(expiry) = expiry {
+            if is_expired(db, expiry, &key, now)? {
+                continue;
+            }
+        }
+        visit(key, value);
+        visited += 1;
+    }
+    Ok(())
+}
+
+/// Run a query, returning the header followed by each row.
+fn run_query(db: &TransactionDB, query: Query) -> Result<Vec<query::QueryResult>, Status> {
+    let key_value_columns = || vec![column("key", "BLOB"), column("value", "BLOB")];
+    let (columns, rows) = match query {
+        Query::Families => {
+            let mut names = TransactionDB::list_cf(&rocksdb::Options::default(), db.path())
+                .map_err(into_tonic_status)?;
+            names.retain(|name| is_queryable(name));
+            names.sort_unstable();
+            let rows = names
+                .into_iter()
+                .map(|name| vec![RocksDbValue::String(name)])
+                .collect();
+            (vec![column("name", "TEXT")], rows)
+        }
+        Query::Get { family, key } => {
+            let value = db
+                .get_cf(column_family(db, &family)?, &key)
+                .map_err(into_tonic_status)?;
+            let value = match expiry_family(db, &family) {
+                Some(expiry) if is_expired(db, expiry, &key, helpers::now_millis())? => None,
+                _ => value,
+            };
+            let rows = value
+                .map(|value| vec![RocksDbValue::Bytes(key), RocksDbValue::Bytes(value)])
+                .into_iter()
+                .collect();
+            (key_value_columns(), rows)
+        }
+        Query::Scan(mut range) => {
+            let limit = range.limit.unwrap_or(MAX_SCAN_LIMIT);
+            if limit > MAX_SCAN_LIMIT {
+                return Err(Status::invalid_argument(format!(
+                    "LIMIT cannot exceed {MAX_SCAN_LIMIT}"
+                )));
+            }
+            range.limit = Some(limit);
+            let mut rows = Vec::new();
+            visit_range(db, &range, |key, value| {
+                rows.push(vec![
+                    RocksDbValue::Bytes(key.into()),
+                    RocksDbValue::Bytes(value.into()),
+                ]);
+            })?;
+            (key_value_columns(), rows)
+        }
+        Query::Count(mut range) => {
+            let limit = range.limit.unwrap_or(MAX_COUNT_LIMIT);
+            if limit > MAX_COUNT_LIMIT {
+                return Err(Status::invalid_argument(format!(
+                    "LIMIT cannot exceed {MAX_COUNT_LIMIT}"
+                )));
+            }
+            range.limit = Some(limit);
+            let mut count = 0;
+            visit_range(db, &range, |_, _| count += 1)?;
+            (
+                vec![column("count", "UBIGINT")],
+                vec![vec![RocksDbValue::UInt64(count)]],
+            )
+        }
+    };
+
+    let mut results = vec![query::QueryResult {
+        fields: Vec::new(),
+        columns,
+    }];
+    for row in rows {
+        let fields = row
+            .into_iter()
+            .map(try_into_protobuf_any)
+            .collect::<Result<_, _>>()
+            .map_err(into_tonic_status)?;
+        results.push(query::QueryResult {
+            fields,
+            columns: Vec::new(),
+        });
+    }
+    Ok(results)
+}
+
+impl Queryable for RocksDb {
+    type Connection = TransactionDB;
+    type QueryStream = DynStream<Result<query::QueryResult, Status>>;
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(connection)))]
+    async fn query(
+        query: String,
+        params: QueryParams,
+        connection: TransactionDB,
+    ) -> (Self::QueryStream, TransactionDB) {
+        let results =
+            language::parse(&query, params).and_then(|query| run_query(&connection, query));
+
+        let stream = stream!({
+            for result in results? {
+                yield Ok(result);
+            }
+        })
+        .instrument(trace_span!("RocksDB raw query"));
+        (Box::pin(stream), connection)
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(connection)))]
+    async fn execute(
+        _query: String,
+        _params: QueryParams,
+        connection: TransactionDB,
+    ) -> (Result<query::RowsChanged, Status>, TransactionDB) {
+        (
+            Err(Status::unimplemented("RocksDB queries are read-only")),
+            connection,
+        )
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(_connection)))]
+    fn attach(
+        _connection: &TransactionDB,
+        _location: &Location,
+        _name: &str,
+    ) -> Result<(), Status> {
+        Err(Status::unimplemented(
+            "RocksDB does not support queries across databases",
+        ))
+    }
 }
@@ -0,0 +1,282 @@
+//! A minimal query language for inspecting the column families of a RocksDB database.
+//!
+//! RocksDB has no query language of its own. Each query is one of
+//!
+//! - `FAMILIES`, returning the name of every column family that can be queried;
+//! - `GET <family> <key>`, returning the key and its value if the key exists;
+//! - `SCAN <family> [PREFIX <prefix>] [FROM <key>] [LIMIT <n>]`, returning each key and its value in
+//!   order of the keys, up to a maximum number of keys; and
+//! - `COUNT <family> [PREFIX <prefix>] [FROM <key>] [LIMIT <n>]`, returning the number of keys that
+//!   would be returned by the equivalent `SCAN`, up to a larger maximum number of keys.
+//!
+//! Keys can only be filtered by prefix, and counting is the only aggregate. As with the KV store,
+//! pairs in the column family of a namespace that have expired are not visible, even before they
+//! are removed.
+//!
+//! Keywords are case-insensitive. Keys, prefixes, and limits may be bare words, quoted strings
+//! (`'a key'`, where `''` is a quote), hexadecimal literals (`x'00ff'`), or parameters (`?`, `?N`,
+//! or a named parameter such as `:name`).
+
+use crate::backend::helpers;
+use crate::queryable::QueryParams;
+use prost_types::value::Kind;
+use prost_types::Value;
+use std::collections::HashSet;
+use std::iter::Peekable;
+use std::str::Chars;
+use tonic::Status;
+
+/// A parsed query.
+#[derive(Debug, PartialEq, Eq)]
+pub(super) enum Query {
+    /// Return the name of every column family.
+    Families,
+    /// Return the value of a key.
+    Get {
+        /// The column family containing the key.
+        family: String,
+        /// The key to look up.
+        key: Vec<u8>,
+    },
+    /// Return the keys in a range and their values.
+    Scan(Range),
+    /// Return the number of keys in a range.
+    Count(Range),
+}
+
+/// The keys of a column family visited by a scan or count, in order.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(super) struct Range {
+    /// The column family containing the keys.
+    pub(super) family: String,
+    /// The prefix every key must begin with.
+    pub(super) prefix: Vec<u8>,
+    /// The key to begin at, if any.
+    pub(super) from: Option<Vec<u8>>,
+    /// The maximum number of keys to visit, if any.
+    pub(super) limit: Option<u64>,
+}
+
+impl Range {
+    /// The key the range begins at: the later of its start and its prefix.
+    pub(super) fn start(&self) -> &[u8] {
+        match &self.from {
+            Some(from) if from[..] > self.prefix[..] => from,
+            _ => &self.prefix,
+        }
+    }
+}
+
+/// A token of a query.
+#[derive(Debug)]
+enum Token {
+    /// A bare word, which may be a keyword.
+    Word(String),
+    /// A quoted string, hexadecimal literal, or parameter.
+    Literal(Vec<u8>),
+}
+
+impl Token {
+    /// The bytes the token stands for when used as an operand.
+    fn into_bytes(self) -> Vec<u8> {
+        match self {
+            Self::Word(word) => word.into_bytes(),
+            Self::Literal(bytes) => bytes,
+        }
+    }
+
+    /// Whether the token is the given keyword.
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self, Self::Word(word) if word.eq_ignore_ascii_case(keyword))
+    }
+}
+
+/// The status returned when a query cannot be parsed.
+fn syntax_error(message: &str) -> Status {
+    Status::invalid_argument(format!("invalid query: {message}"))
+}
+
+/// Parse a query, binding the values of its parameters.
+pub(super) fn parse(query: &str, params: QueryParams) -> Result<Query, Status> {
+    let mut tokens = tokenize(query, params)?.into_iter();
+    let Some(command) = tokens.next() else {
+        return Err(syntax_error("the query is empty"));
+    };
+    let query = if command.is_keyword("FAMILIES") {
+        Query::Families
+    } else if command.is_keyword("GET") {
+        Query::Get {
+            family: family(&mut tokens)?,
+            key: operand(&mut tokens, "key")?,
+        }
+    } else if command.is_keyword("SCAN") {
+        Query::Scan(range(&mut tokens)?)
+    } else if command.is_keyword("COUNT") {
+        Query::Count(range(&mut tokens)?)
+    } else {
+        return Err(syntax_error("expected FAMILIES, GET, SCAN, or COUNT"));
+    };
+    match tokens.next() {
+        Some(_) => Err(syntax_error("unexpected input after the end of the query")),
+        None => Ok(query),
+    }
+}
+
+/// Parse the column family and clauses of a scan or count.
+fn range(tokens: &mut impl Iterator<Item = Token>) -> Result<Range, Status> {
+    let mut range = Range {
+        family: family(tokens)?,
+        ..Range::default()
+    };
+    while let Some(clause) = tokens.next() {
+        if clause.is_keyword("PREFIX") {
+            range.prefix = operand(tokens, "prefix")?;
+        } else if clause.is_keyword("FROM") {
+            range.from = Some(operand(tokens, "key")?);
+        } else if clause.is_keyword("LIMIT") {
+            let limit = operand(tokens, "limit")?;
+            range.limit = Some(
+                std::str::from_utf8(&limit)
+                    .ok()
+                    .and_then(|limit| limit.parse().ok())
+                    .ok_or_else(|| syntax_error("LIMIT must be a non-negative integer"))?,
+            );
+        } else {
+            return Err(syntax_error("expected PREFIX, FROM, or LIMIT"));
+        }
+    }
+    Ok(range)
+}
+
+/// Parse the name of a column family.
+fn family(tokens: &mut impl Iterator<Item = Token>) -> Result<String, Status> {
+    String::from_utf8(operand(tokens, "column family")?)
+        .map_err(|_| syntax_error("column family names must be UTF-8"))
+}
+
+/// Parse an operand, such as a key or prefix.
+fn operand(tokens: &mut impl Iterator<Item = Token>, what: &str) -> Result<Vec<u8>, Status> {
+    tokens
+        .next()
+        .map(Token::into_bytes)
+        .ok_or_else(|| syntax_error(&format!("expected a {what}")))
+}
+
+/// Split a query into tokens, replacing each parameter with its value.
+///
+/// A value must be provided for every positional parameter, and every named value must be used.
+fn tokenize(query: &str, params: QueryParams) -> Result<Vec<Token>, Status> {
+    let QueryParams { positional, named } = params;
+    let mut tokens = Vec::new();
+    let mut next_position = 0;
+    let mut positions_used = 0;
+    let mut names_used = HashSet::new();
+    let mut chars = query.chars().peekable();
+    while let Some(char) = chars.next() {
+        let token = match char {
+            char if char.is_whitespace() => continue,
+            '\'' => Token::Literal(quoted(&mut chars)?.into_bytes()),
+            'x' | 'X' if chars.next_if_eq(&'\'').is_some() => {
+                Token::Literal(decode_hex(&quoted(&mut chars)?)?)
+            }
+            '?' => {
+                let digits = take_while(&mut chars, |char| char.is_ascii_digit());
+                let position: usize = if digits.is_empty() {
+                    next_position += 1;
+                    next_position
+                } else {
+                    digits
+                        .parse()
+                        .map_err(|_| syntax_error("invalid parameter index"))?
+                };
+                positions_used = positions_used.max(position);
+                let value = position
+                    .checked_sub(1)
+                    .and_then(|index| positional.get(index))
+                    .ok_or_else(|| {
+                        helpers::param_count_mismatch(positions_used, positional.len())
+                    })?;
+                Token::Literal(param_bytes(value)?)
+            }
+            ':' | '@' | '$' => {
+                let name = take_while(&mut chars, |char| char.is_alphanumeric() || char == '_');
+                let name = format!("{char}{name}");
+                let value = named.get(&name).ok_or_else(|| {
+                    Status::invalid_argument(format!("no value provided for parameter {name}"))
+                })?;
+                let token = Token::Literal(param_bytes(value)?);
+                let _newly_used = names_used.insert(name);
+                token
+            }
+            char => {
+                let rest = take_while(&mut chars, |char| !char.is_whitespace() && char != '\'');
+                Token::Word(format!("{char}{rest}"))
+            }
+        };
+        tokens.push(token);
+    }
+    if positions_used != positional.len() {
+        return Err(helpers::param_count_mismatch(
+            positions_used,
+            positional.len(),
+        ));
+    }
+    if let Some(name) = named.keys().find(|name| !names_used.contains(*name)) {
+        return Err(Status::invalid_argument(format!(
+            "query has no parameter {name}"
+        )));
+    }
+    Ok(tokens)
+}
+
+/// Take characters for as long as they match the predicate.
+fn take_while(chars: &mut Peekable<Chars<'_>>, predicate: impl Fn(char) -> bool) -> String {
+    let mut taken = String::new();
+    while let Some(char) = chars.next_if(|&char| predicate(char)) {
+        taken.push(char);
+    }
+    taken
+}
+
+/// Read the remainder of a quoted string, whose opening quote has already been read.
+fn quoted(chars: &mut Peekable<Chars<'_>>) -> Result<String, Status> {
+    let mut string = String::new();
+    loop {
+        match chars.next() {
+            // A doubled quote stands for a single quote.
+            Some('\'') if chars.next_if_eq(&'\'').is_some() => string.push('\''),
+            Some('\'') => return Ok(string),
+            Some(char) => string.push(char),
+            None => return Err(syntax_error("unterminated string")),
+        }
+    }
+}
+
+/// Decode the contents of a hexadecimal literal.
+fn decode_hex(hex: &str) -> Result<Vec<u8>, Status> {
+    // A literal with an odd number of digits ends in an incomplete byte, which cannot be sliced.
+    (0..hex.len())
+        .step_by(2)
+        .map(|index| {
+            hex.get(index..index + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                .ok_or_else(|| syntax_error("invalid hexadecimal literal"))
+        })
+        .collect()
+}
+
+/// The bytes a parameter stands for. Strings stand for their UTF-8 encoding, and non-negative
+/// integers for their decimal representation.
+fn param_bytes(value: &Value) -> Result<Vec<u8>, Status> {
+    match &value.kind {
+        Some(Kind::StringValue(string)) => Ok(string.as_bytes().to_vec()),
+        Some(Kind::NumberValue(number))
+            if number.fract() == 0. && (0. ..u64::MAX as f64).contains(number) =>
+        {
+            Ok((*number as u64).to_string().into_bytes())
+        }
+        _ => Err(Status::invalid_argument(
+            "only strings and non-negative integers can be bound to RocksDB query parameters",
+        )),
+    }
+}
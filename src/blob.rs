@@ -3,7 +3,8 @@
 use crate::backend::{BlobBackend, DatabaseBackend};
 use crate::interop::IntoTonicStatus;
 use crate::proto::blob::{
-    DeleteRequest, EqDataRequest, GetRequest, NotEqDataRequest, StoreRequest, UpdateRequest,
+    AppendRequest, CopyRequest, DeleteRequest, DownloadRequest, EqDataRequest, GetRequest,
+    ListRequest, NotEqDataRequest, StoreRequest, UpdateRequest, UploadRequest, VerifyRequest,
 };
 use crate::service::blob::BlobRpc;
 use crate::{Location, RpcResponse, StreamingRequest};
@@ -53,6 +54,23 @@ where
     }
 }
 
+impl<Backend> BlobStore<Backend>
+where
+    Backend: BlobBackend,
+{
+    /// Store the data of BLOBs larger than `threshold` bytes in files in a directory alongside the
+    /// database, rather than in the database itself. The directory is named after the database,
+    /// with `-blobs` appended.
+    ///
+    /// This is transparent to clients, and data already stored is unaffected. In-memory stores
+    /// always keep data in the database.
+    #[inline]
+    pub fn with_external_threshold(self, threshold: u64) -> Self {
+        self.backend.set_external_threshold(Some(threshold));
+        self
+    }
+}
+
 #[tonic::async_trait]
 impl<Backend> BlobRpc for BlobStore<Backend>
 where
@@ -62,12 +80,24 @@ where
             StoreStream: Send,
             UpdateStream: Send,
             DeleteStream: Send,
+            UploadStream: Send,
+            DownloadStream: Send,
+            ListStream: Send,
+            CopyStream: Send,
+            AppendStream: Send,
+            VerifyStream: Send,
         > + 'static,
 {
     type GetStream = Backend::GetStream;
     type StoreStream = Backend::StoreStream;
     type UpdateStream = Backend::UpdateStream;
     type DeleteStream = Backend::DeleteStream;
+    type UploadStream = Backend::UploadStream;
+    type DownloadStream = Backend::DownloadStream;
+    type ListStream = Backend::ListStream;
+    type CopyStream = Backend::CopyStream;
+    type AppendStream = Backend::AppendStream;
+    type VerifyStream = Backend::VerifyStream;
 
     async fn get(&self, request: StreamingRequest<GetRequest>) -> RpcResponse<Self::GetStream> {
         self.backend.get(request).await
@@ -101,4 +131,40 @@ where
     async fn not_eq_data(&self, request: StreamingRequest<NotEqDataRequest>) -> RpcResponse<bool> {
         self.backend.not_eq_data(request).await
     }
+
+    async fn upload(
+        &self,
+        request: StreamingRequest<UploadRequest>,
+    ) -> RpcResponse<Self::UploadStream> {
+        self.backend.upload(request).await
+    }
+
+    async fn download(
+        &self,
+        request: StreamingRequest<DownloadRequest>,
+    ) -> RpcResponse<Self::DownloadStream> {
+        self.backend.download(request).await
+    }
+
+    async fn list(&self, request: tonic::Request<ListRequest>) -> RpcResponse<Self::ListStream> {
+        self.backend.list(request).await
+    }
+
+    async fn copy(&self, request: StreamingRequest<CopyRequest>) -> RpcResponse<Self::CopyStream> {
+        self.backend.copy(request).await
+    }
+
+    async fn append(
+        &self,
+        request: StreamingRequest<AppendRequest>,
+    ) -> RpcResponse<Self::AppendStream> {
+        self.backend.append(request).await
+    }
+
+    async fn verify(
+        &self,
+        request: tonic::Request<VerifyRequest>,
+    ) -> RpcResponse<Self::VerifyStream> {
+        self.backend.verify(request).await
+    }
 }
@@ -76,6 +76,11 @@ pub(crate) struct RunArgs {
     /// The location of the BLOB store.
     #[clap(long, default_value = "blob_store.db")]
     pub(crate) blob_store: PathBuf,
+    /// Store the data of BLOBs larger than this many bytes in files alongside the BLOB store.
+    ///
+    /// If omitted, all data is stored in the BLOB store itself.
+    #[clap(long, value_name = "BYTES")]
+    pub(crate) external_threshold: Option<u64>,
     /// The address to listen on.
     #[clap(default_value = "[::1]:50051")]
     pub(crate) addr: SocketAddr,
@@ -99,7 +104,8 @@ pub(crate) enum KvCommand {
     /// Get the value(s) associated with the given key(s).
     ///
     /// If multiple keys are provided, the values associated with each key will be written to stdout
-    /// in the order they were provided, separated by a null byte (`\0`).
+    /// in the order they were provided, separated by a null byte (`\0`). Nothing is written for a
+    /// key that does not exist, and the command exits with an error once all values are written.
     #[clap(alias = "fetch")]
     Get {
         /// The key(s) to get the value(s) for.
@@ -117,6 +123,11 @@ pub(crate) enum KvCommand {
         key: String,
         /// The value to be associated with the key.
         value: String,
+        /// How long the pair should exist, in seconds.
+        ///
+        /// If omitted, the pair never expires.
+        #[arg(long)]
+        ttl: Option<u32>,
     },
     /// Delete a value for a given key.
     #[clap(aliases = ["remove", "rm"])]
@@ -162,6 +173,9 @@ pub(crate) struct BlobArgs {
 #[derive(Debug, Subcommand)]
 pub(crate) enum BlobCommand {
     /// Get the BLOB associated with the given ID.
+    ///
+    /// The data is written as it is received and verified once all of it has been written, so the
+    /// command exits with an error after writing data that is corrupted.
     #[clap(alias = "fetch")]
     Get {
         /// The ID of the BLOB to get.
@@ -1,12 +1,12 @@
-#[cfg(any(feature = "duckdb", feature = "sqlite"))]
+#[cfg(any(feature = "duckdb", feature = "rocksdb", feature = "sqlite"))]
 use prost::{Message, Name};
-#[cfg(feature = "duckdb")]
+#[cfg(any(feature = "duckdb", feature = "rocksdb", feature = "sqlite"))]
 use prost_types::value::Kind;
 use prost_types::*;
 #[cfg(feature = "duckdb")]
 use std::collections::BTreeMap;
 
-#[cfg(any(feature = "duckdb", feature = "sqlite"))]
+#[cfg(any(feature = "duckdb", feature = "rocksdb", feature = "sqlite"))]
 pub(crate) fn try_into_protobuf_any<T>(value: T) -> Result<Any, Unsupported>
 where
     T: TryIntoProtobufAny,
@@ -14,11 +14,40 @@ where
     value.try_into_protobuf_any()
 }
 
-#[cfg(any(feature = "duckdb", feature = "sqlite"))]
+#[cfg(any(feature = "duckdb", feature = "rocksdb", feature = "sqlite"))]
 pub(crate) trait TryIntoProtobufAny {
     fn try_into_protobuf_any(self) -> Result<Any, Unsupported>;
 }
 
+/// Convert a `google.protobuf.Value` into a value that can be bound to a query parameter.
+#[cfg(any(feature = "duckdb", feature = "sqlite"))]
+pub(crate) fn try_from_protobuf_value<T>(value: Value) -> Result<T, Unsupported>
+where
+    T: TryFromProtobufValue,
+{
+    T::try_from_protobuf_value(value)
+}
+
+#[cfg(any(feature = "duckdb", feature = "sqlite"))]
+pub(crate) trait TryFromProtobufValue: Sized {
+    fn try_from_protobuf_value(value: Value) -> Result<Self, Unsupported>;
+}
+
+/// Whether a number is an integer that can be represented as an `i64`.
+///
+/// All numbers in `google.protobuf.Value` are doubles, so integers are bound as such to compare
+/// equal to integer columns and to be accepted where only integers are, such as `LIMIT`.
+#[cfg(any(feature = "duckdb", feature = "sqlite"))]
+fn is_integer(val: f64) -> bool {
+    (i64::MIN as f64..i64::MAX as f64).contains(&val) && val.fract() == 0.
+}
+
+/// The error returned when a list or struct is bound to a query parameter.
+#[cfg(any(feature = "duckdb", feature = "sqlite"))]
+const UNSUPPORTED_PARAM: Unsupported = Unsupported {
+    message: "lists and structs cannot be bound to query parameters",
+};
+
 #[derive(Debug)]
 pub(crate) struct Unsupported {
     pub(crate) message: &'static str,
@@ -49,35 +78,35 @@ enum ConcreteValue {
 }
 
 impl ConcreteValue {
-    #[cfg(any(feature = "duckdb", feature = "sqlite"))]
+    #[cfg(any(feature = "duckdb", feature = "rocksdb", feature = "sqlite"))]
     fn into_any(self) -> Any {
-        macro_rules! google_proto {
-            ($file:literal) => {
-                concat!(
-                    "https://github.com/protocolbuffers/protobuf/raw/",
-                    "db48abbef49312d76d442c0cde6a551961726b28/src/google/protobuf/",
-                    $file
-                )
-                .to_owned()
+        macro_rules! google_type {
+            ($name:literal) => {
+                concat!("type.googleapis.com/google.protobuf.", $name).to_owned()
             };
         }
 
         let (type_url, value) = match self {
-            // TODO What's the correct encoding for NullValue?
-            Self::NullValue => (google_proto!("struct.proto"), vec![]),
-            Self::BoolValue(val) => (google_proto!("wrappers.proto"), val.encode_to_vec()),
-            Self::Int32Value(val) => (google_proto!("wrappers.proto"), val.encode_to_vec()),
-            Self::Int64Value(val) => (google_proto!("wrappers.proto"), val.encode_to_vec()),
-            Self::UInt32Value(val) => (google_proto!("wrappers.proto"), val.encode_to_vec()),
-            Self::UInt64Value(val) => (google_proto!("wrappers.proto"), val.encode_to_vec()),
-            Self::FloatValue(val) => (google_proto!("wrappers.proto"), val.encode_to_vec()),
-            Self::DoubleValue(val) => (google_proto!("wrappers.proto"), val.encode_to_vec()),
-            Self::StringValue(val) => (google_proto!("wrappers.proto"), val.encode_to_vec()),
-            Self::BytesValue(val) => (google_proto!("wrappers.proto"), val.encode_to_vec()),
+            Self::NullValue => (
+                google_type!("Value"),
+                Value {
+                    kind: Some(Kind::NullValue(NullValue::NullValue.into())),
+                }
+                .encode_to_vec(),
+            ),
+            Self::BoolValue(val) => (google_type!("BoolValue"), val.encode_to_vec()),
+            Self::Int32Value(val) => (google_type!("Int32Value"), val.encode_to_vec()),
+            Self::Int64Value(val) => (google_type!("Int64Value"), val.encode_to_vec()),
+            Self::UInt32Value(val) => (google_type!("UInt32Value"), val.encode_to_vec()),
+            Self::UInt64Value(val) => (google_type!("UInt64Value"), val.encode_to_vec()),
+            Self::FloatValue(val) => (google_type!("FloatValue"), val.encode_to_vec()),
+            Self::DoubleValue(val) => (google_type!("DoubleValue"), val.encode_to_vec()),
+            Self::StringValue(val) => (google_type!("StringValue"), val.encode_to_vec()),
+            Self::BytesValue(val) => (google_type!("BytesValue"), val.encode_to_vec()),
             Self::Timestamp(val) => (Timestamp::type_url(), val.encode_to_vec()),
-            Self::ListValue(val) => (google_proto!("struct.proto"), val.encode_to_vec()),
-            Self::Struct(val) => (google_proto!("struct.proto"), val.encode_to_vec()),
-            Self::Value(val) => (google_proto!("struct.proto"), val.encode_to_vec()),
+            Self::ListValue(val) => (google_type!("ListValue"), val.encode_to_vec()),
+            Self::Struct(val) => (google_type!("Struct"), val.encode_to_vec()),
+            Self::Value(val) => (google_type!("Value"), val.encode_to_vec()),
         };
 
         Any { type_url, value }
@@ -181,6 +210,20 @@ impl TryIntoProtobufAny for duckdb::types::Value {
     }
 }
 
+#[cfg(feature = "duckdb")]
+impl TryFromProtobufValue for duckdb::types::Value {
+    fn try_from_protobuf_value(value: Value) -> Result<Self, Unsupported> {
+        Ok(match value.kind {
+            None | Some(Kind::NullValue(_)) => Self::Null,
+            Some(Kind::BoolValue(val)) => Self::Boolean(val),
+            Some(Kind::NumberValue(val)) if is_integer(val) => Self::BigInt(val as i64),
+            Some(Kind::NumberValue(val)) => Self::Double(val),
+            Some(Kind::StringValue(val)) => Self::Text(val),
+            Some(Kind::ListValue(_) | Kind::StructValue(_)) => return Err(UNSUPPORTED_PARAM),
+        })
+    }
+}
+
 #[cfg(feature = "duckdb")]
 fn duckdb_value_to_protobuf_value(value: &duckdb::types::Value) -> Result<Value, Unsupported> {
     use duckdb::types::Value as DuckdbValue;
@@ -279,6 +322,27 @@ fn duckdb_value_to_protobuf_value(value: &duckdb::types::Value) -> Result<Value,
     }
 }
 
+/// A value returned by a RocksDB query.
+#[cfg(feature = "rocksdb")]
+#[derive(Debug)]
+pub(crate) enum RocksDbValue {
+    Bytes(Vec<u8>),
+    String(String),
+    UInt64(u64),
+}
+
+#[cfg(feature = "rocksdb")]
+impl TryIntoProtobufAny for RocksDbValue {
+    fn try_into_protobuf_any(self) -> Result<Any, Unsupported> {
+        Ok(match self {
+            Self::Bytes(val) => ConcreteValue::BytesValue(val),
+            Self::String(val) => ConcreteValue::StringValue(val),
+            Self::UInt64(val) => ConcreteValue::UInt64Value(val),
+        }
+        .into_any())
+    }
+}
+
 #[cfg(feature = "sqlite")]
 impl TryIntoProtobufAny for rusqlite::types::Value {
     fn try_into_protobuf_any(self) -> Result<Any, Unsupported> {
@@ -292,3 +356,60 @@ impl TryIntoProtobufAny for rusqlite::types::Value {
         .into_any())
     }
 }
+
+#[cfg(feature = "sqlite")]
+impl TryFromProtobufValue for rusqlite::types::Value {
+    fn try_from_protobuf_value(value: Value) -> Result<Self, Unsupported> {
+        Ok(match value.kind {
+            None | Some(Kind::NullValue(_)) => Self::Null,
+            Some(Kind::BoolValue(val)) => Self::Integer(val.into()),
+            Some(Kind::NumberValue(val)) if is_integer(val) => Self::Integer(val as i64),
+            Some(Kind::NumberValue(val)) => Self::Real(val),
+            Some(Kind::StringValue(val)) => Self::Text(val),
+            Some(Kind::ListValue(_) | Kind::StructValue(_)) => return Err(UNSUPPORTED_PARAM),
+        })
+    }
+}
+
+/// The name of the DuckDB type corresponding to the Arrow type a column is returned as.
+#[cfg(feature = "duckdb")]
+pub(crate) fn duckdb_type_name(data_type: &duckdb::arrow::datatypes::DataType) -> String {
+    use duckdb::arrow::datatypes::DataType;
+    match data_type {
+        DataType::Null => "NULL".to_owned(),
+        DataType::Boolean => "BOOLEAN".to_owned(),
+        DataType::Int8 => "TINYINT".to_owned(),
+        DataType::Int16 => "SMALLINT".to_owned(),
+        DataType::Int32 => "INTEGER".to_owned(),
+        DataType::Int64 => "BIGINT".to_owned(),
+        DataType::UInt8 => "UTINYINT".to_owned(),
+        DataType::UInt16 => "USMALLINT".to_owned(),
+        DataType::UInt32 => "UINTEGER".to_owned(),
+        DataType::UInt64 => "UBIGINT".to_owned(),
+        DataType::Float16 | DataType::Float32 => "FLOAT".to_owned(),
+        DataType::Float64 => "DOUBLE".to_owned(),
+        DataType::Decimal128(precision, scale) | DataType::Decimal256(precision, scale) => {
+            format!("DECIMAL({precision}, {scale})")
+        }
+        DataType::Utf8 | DataType::LargeUtf8 => "VARCHAR".to_owned(),
+        DataType::Binary | DataType::LargeBinary | DataType::FixedSizeBinary(_) => {
+            "BLOB".to_owned()
+        }
+        DataType::Date32 | DataType::Date64 => "DATE".to_owned(),
+        DataType::Time32(_) | DataType::Time64(_) => "TIME".to_owned(),
+        DataType::Timestamp(_, _) => "TIMESTAMP".to_owned(),
+        DataType::Interval(_) => "INTERVAL".to_owned(),
+        DataType::List(field) | DataType::LargeList(field) => {
+            format!("{}[]", duckdb_type_name(field.data_type()))
+        }
+        DataType::FixedSizeList(field, len) => {
+            format!("{}[{len}]", duckdb_type_name(field.data_type()))
+        }
+        DataType::Struct(_) => "STRUCT".to_owned(),
+        DataType::Map(_, _) => "MAP".to_owned(),
+        DataType::Union(_, _) => "UNION".to_owned(),
+        // Enums are returned as dictionaries.
+        DataType::Dictionary(_, _) => "ENUM".to_owned(),
+        other => other.to_string(),
+    }
+}
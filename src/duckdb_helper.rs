@@ -20,6 +20,21 @@ where
     params_from_iter([TripleToSql::A(a), TripleToSql::B(b), TripleToSql::C(c)])
 }
 
+pub(crate) fn params4<A, B, C, D>(a: A, b: B, c: C, d: D) -> impl Params
+where
+    A: ToSql,
+    B: ToSql,
+    C: ToSql,
+    D: ToSql,
+{
+    params_from_iter([
+        QuadToSql::A(a),
+        QuadToSql::B(b),
+        QuadToSql::C(c),
+        QuadToSql::D(d),
+    ])
+}
+
 enum PairToSql<A, B> {
     A(A),
     B(B),
@@ -58,3 +73,27 @@ where
         }
     }
 }
+
+enum QuadToSql<A, B, C, D> {
+    A(A),
+    B(B),
+    C(C),
+    D(D),
+}
+
+impl<A, B, C, D> ToSql for QuadToSql<A, B, C, D>
+where
+    A: ToSql,
+    B: ToSql,
+    C: ToSql,
+    D: ToSql,
+{
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>> {
+        match self {
+            Self::A(a) => a.to_sql(),
+            Self::B(b) => b.to_sql(),
+            Self::C(c) => c.to_sql(),
+            Self::D(d) => d.to_sql(),
+        }
+    }
+}
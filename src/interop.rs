@@ -17,10 +17,23 @@ pub trait IntoTonicStatus {
     fn into_tonic_status(self) -> Status;
 }
 
+/// Whether a DuckDB error was caused by a write conflicting with that of another transaction.
+#[cfg(feature = "duckdb")]
+fn is_duckdb_conflict(message: &str) -> bool {
+    message
+        .strip_prefix("TransactionContext Error: ")
+        .is_some_and(|message| {
+            message.contains("onflict") || message.starts_with("Failed to commit: ")
+        })
+}
+
 #[cfg(feature = "duckdb")]
 impl IntoTonicStatus for duckdb::Error {
     fn into_tonic_status(self) -> Status {
         let mut tonic_err = match &self {
+            Self::DuckDBFailure(_, Some(message)) if is_duckdb_conflict(message) => {
+                Status::aborted(format!("DuckDB transaction conflict: {message}"))
+            }
             Self::DuckDBFailure(a, b) => Status::internal(format!("DuckDB failure: {a} {b:?}")),
             Self::FromSqlConversionFailure(_, ty, _) => {
                 Status::internal(format!("could not convert {ty} to Rust type"))
@@ -2,16 +2,26 @@
 
 use crate::backend::{DatabaseBackend, KvBackend};
 use crate::interop::IntoTonicStatus;
-use crate::proto::kv::{DeleteRequest, EqRequest, GetRequest, NotEqRequest, SetRequest};
+use crate::proto::kv::{
+    CompareAndSwapRequest, CreateNamespaceRequest, CreateNamespaceResponse, DeleteRequest,
+    DropNamespaceRequest, DropNamespaceResponse, EqRequest, GetRequest, HistoryRequest,
+    IncrementRequest, ListNamespacesRequest, ListNamespacesResponse, NotEqRequest, ScanRequest,
+    SetRequest, TransactionRequest, WatchRequest,
+};
 use crate::service::kv::KvRpc;
 use crate::{Location, RpcResponse, StreamingRequest};
 use std::path::PathBuf;
 
+/// The metadata key naming the namespace a request operates on.
+///
+/// If not present, the request operates on the default namespace.
+pub const NAMESPACE_METADATA_KEY: &str = "buffdb-namespace";
+
 /// A key-value store.
 ///
-/// This is a key-value store where both the key and value are strings. There are no restrictions on
-/// the length or contents of either the key or value beyond restrictions implemented by the
-/// protobuf server.
+/// This is a key-value store where the key is a string and the value is arbitrary bytes. There are
+/// no restrictions on the length or contents of either the key or value beyond restrictions
+/// implemented by the protobuf server.
 #[must_use]
 #[derive(Debug)]
 pub struct KvStore<Backend> {
@@ -54,12 +64,28 @@ where
 #[tonic::async_trait]
 impl<Backend> KvRpc for KvStore<Backend>
 where
-    Backend: KvBackend<Error: IntoTonicStatus, GetStream: Send, SetStream: Send, DeleteStream: Send>
-        + 'static,
+    Backend: KvBackend<
+            Error: IntoTonicStatus,
+            GetStream: Send,
+            SetStream: Send,
+            DeleteStream: Send,
+            ScanStream: Send,
+            CompareAndSwapStream: Send,
+            TransactionStream: Send,
+            WatchStream: Send,
+            IncrementStream: Send,
+            HistoryStream: Send,
+        > + 'static,
 {
     type GetStream = Backend::GetStream;
     type SetStream = Backend::SetStream;
     type DeleteStream = Backend::DeleteStream;
+    type ScanStream = Backend::ScanStream;
+    type CompareAndSwapStream = Backend::CompareAndSwapStream;
+    type TransactionStream = Backend::TransactionStream;
+    type WatchStream = Backend::WatchStream;
+    type IncrementStream = Backend::IncrementStream;
+    type HistoryStream = Backend::HistoryStream;
 
     async fn get(&self, request: StreamingRequest<GetRequest>) -> RpcResponse<Self::GetStream> {
         self.backend.get(request).await
@@ -83,4 +109,61 @@ where
     async fn not_eq(&self, request: StreamingRequest<NotEqRequest>) -> RpcResponse<bool> {
         self.backend.not_eq(request).await
     }
+
+    async fn scan(&self, request: StreamingRequest<ScanRequest>) -> RpcResponse<Self::ScanStream> {
+        self.backend.scan(request).await
+    }
+
+    async fn compare_and_swap(
+        &self,
+        request: StreamingRequest<CompareAndSwapRequest>,
+    ) -> RpcResponse<Self::CompareAndSwapStream> {
+        self.backend.compare_and_swap(request).await
+    }
+
+    async fn transaction(
+        &self,
+        request: StreamingRequest<TransactionRequest>,
+    ) -> RpcResponse<Self::TransactionStream> {
+        self.backend.transaction(request).await
+    }
+
+    async fn watch(&self, request: tonic::Request<WatchRequest>) -> RpcResponse<Self::WatchStream> {
+        self.backend.watch(request).await
+    }
+
+    async fn increment(
+        &self,
+        request: StreamingRequest<IncrementRequest>,
+    ) -> RpcResponse<Self::IncrementStream> {
+        self.backend.increment(request).await
+    }
+
+    async fn create_namespace(
+        &self,
+        request: tonic::Request<CreateNamespaceRequest>,
+    ) -> RpcResponse<CreateNamespaceResponse> {
+        self.backend.create_namespace(request).await
+    }
+
+    async fn list_namespaces(
+        &self,
+        request: tonic::Request<ListNamespacesRequest>,
+    ) -> RpcResponse<ListNamespacesResponse> {
+        self.backend.list_namespaces(request).await
+    }
+
+    async fn drop_namespace(
+        &self,
+        request: tonic::Request<DropNamespaceRequest>,
+    ) -> RpcResponse<DropNamespaceResponse> {
+        self.backend.drop_namespace(request).await
+    }
+
+    async fn history(
+        &self,
+        request: StreamingRequest<HistoryRequest>,
+    ) -> RpcResponse<Self::HistoryStream> {
+        self.backend.history(request).await
+    }
 }
@@ -57,20 +57,62 @@ pub mod proto {
     /// Protobuf types needed to interact with the BLOB store.
     pub mod blob {
         pub use crate::bindings::buffdb::blob::{
-            DeleteRequest, DeleteResponse, EqDataRequest, GetRequest, GetResponse,
+            AppendRequest, AppendResponse, BlobChunk, BlobInfo, Compression, CopyRequest,
+            CopyResponse, DeleteRequest, DeleteResponse, DownloadRequest, DownloadResponse,
+            EqDataRequest, Finalize, GetRequest, GetResponse, ListRequest, ListResponse,
             NotEqDataRequest, StoreRequest, StoreResponse, UpdateRequest, UpdateResponse,
+            UploadRequest, UploadResponse, VerifyRequest, VerifyResponse,
         };
+        /// Types used by [`UploadRequest`].
+        pub mod upload_request {
+            pub use crate::bindings::buffdb::blob::upload_request::Part;
+        }
+        /// Types used by [`DownloadResponse`].
+        pub mod download_response {
+            pub use crate::bindings::buffdb::blob::download_response::Part;
+        }
     }
     /// Protobuf types needed to interact with the KV store.
     pub mod kv {
         pub use crate::bindings::buffdb::kv::{
-            DeleteRequest, DeleteResponse, EqRequest, GetRequest, GetResponse, NotEqRequest,
-            SetRequest, SetResponse,
+            ChangeKind, Commit, CompareAndSwapFailure, CompareAndSwapRequest,
+            CompareAndSwapResponse, CreateNamespaceRequest, CreateNamespaceResponse, DeleteRequest,
+            DeleteResponse, DropNamespaceRequest, DropNamespaceResponse, EqRequest, GetRequest,
+            GetResponse, HistoryRequest, HistoryResponse, IncrementRequest, IncrementResponse,
+            ListNamespacesRequest, ListNamespacesResponse, NotEqRequest, Rollback, ScanRequest,
+            ScanResponse, SetRequest, SetResponse, TransactionRequest, TransactionResponse,
+            WatchRequest, WatchResponse,
         };
+        /// Types used by [`CompareAndSwapRequest`].
+        pub mod compare_and_swap_request {
+            pub use crate::bindings::buffdb::kv::compare_and_swap_request::Expiration;
+        }
+        /// Types used by [`TransactionRequest`].
+        pub mod transaction_request {
+            pub use crate::bindings::buffdb::kv::transaction_request::Operation;
+        }
+        /// Types used by [`TransactionResponse`].
+        pub mod transaction_response {
+            pub use crate::bindings::buffdb::kv::transaction_response::Outcome;
+        }
+        /// Types used by [`WatchRequest`].
+        pub mod watch_request {
+            pub use crate::bindings::buffdb::kv::watch_request::Target;
+        }
+        /// Types used by [`IncrementRequest`].
+        pub mod increment_request {
+            pub use crate::bindings::buffdb::kv::increment_request::Expiration;
+        }
+        /// Types used by [`SetRequest`].
+        pub mod set_request {
+            pub use crate::bindings::buffdb::kv::set_request::Expiration;
+        }
     }
     /// Protobuf types needed to send raw queries to a given store.
     pub mod query {
-        pub use crate::bindings::buffdb::query::{QueryResult, RawQuery, RowsChanged, TargetStore};
+        pub use crate::bindings::buffdb::query::{
+            Column, QueryResult, RawQuery, RowsChanged, TargetStore,
+        };
     }
 }
 
@@ -99,6 +141,7 @@ pub mod client {
     /// gRPC client for the KV store.
     pub mod kv {
         pub use crate::bindings::buffdb::kv::kv_client::KvClient;
+        pub use crate::kv::NAMESPACE_METADATA_KEY;
     }
     /// gRPC client for the raw query execution.
     pub mod query {
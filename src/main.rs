@@ -20,6 +20,7 @@ use buffdb::backend::RocksDb;
 use buffdb::backend::Sqlite;
 use buffdb::backend::{BlobBackend, DatabaseBackend, KvBackend};
 use buffdb::interop::IntoTonicStatus;
+use buffdb::proto::blob::{download_response, upload_request};
 use buffdb::proto::{blob, kv};
 use buffdb::server::blob::BlobServer;
 use buffdb::server::kv::KvServer;
@@ -30,9 +31,14 @@ use futures::{stream, StreamExt};
 use std::path::PathBuf;
 use std::process::ExitCode;
 use tokio::fs;
-use tokio::io::{self, AsyncReadExt as _, AsyncWriteExt as _};
+use tokio::io::{self, AsyncRead, AsyncReadExt as _, AsyncWriteExt as _};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
 use tonic::transport::Server;
 
+/// The size of each chunk of a BLOB uploaded from the command line, in bytes.
+const UPLOAD_CHUNK_SIZE: usize = 1 << 20;
+
 /// A custom error message.
 #[derive(Debug)]
 struct ErrStr(&'static str);
@@ -87,6 +93,7 @@ fn main() -> Result<ExitCode, Box<dyn std::error::Error>> {
 ///
 /// - `kv_store`: The location to store key-value pairs.
 /// - `blob_store`: The location to store BLOBs.
+/// - `external_threshold`: The size above which BLOB data is stored in files, if any.
 /// - `addr`: The address to bind the server to.
 ///
 /// `kv_store` and `blob_store` cannot be the same location. This is enforced at runtime to a
@@ -96,14 +103,34 @@ async fn run<Backend>(
     RunArgs {
         kv_store,
         blob_store,
+        external_threshold,
         addr,
     }: RunArgs,
 ) -> Result<ExitCode, Box<dyn std::error::Error>>
 where
     Backend: DatabaseBackend<Error: IntoTonicStatus + std::error::Error>
-        + KvBackend<GetStream: Send, SetStream: Send, DeleteStream: Send>
-        + BlobBackend<GetStream: Send, StoreStream: Send, UpdateStream: Send, DeleteStream: Send>
-        + 'static,
+        + KvBackend<
+            GetStream: Send,
+            SetStream: Send,
+            DeleteStream: Send,
+            ScanStream: Send,
+            CompareAndSwapStream: Send,
+            TransactionStream: Send,
+            WatchStream: Send,
+            IncrementStream: Send,
+            HistoryStream: Send,
+        > + BlobBackend<
+            GetStream: Send,
+            StoreStream: Send,
+            UpdateStream: Send,
+            DeleteStream: Send,
+            UploadStream: Send,
+            DownloadStream: Send,
+            ListStream: Send,
+            CopyStream: Send,
+            AppendStream: Send,
+            VerifyStream: Send,
+        > + 'static,
 {
     if kv_store == blob_store {
         return Err(Box::new(ErrStr(
@@ -132,7 +159,10 @@ where
 
     debug!(?kv_store, ?blob_store, "creating stores");
     let kv_store = KvStore::<Backend>::at_path(kv_store)?;
-    let blob_store = BlobStore::<Backend>::at_path(blob_store)?;
+    let mut blob_store = BlobStore::<Backend>::at_path(blob_store)?;
+    if let Some(threshold) = external_threshold {
+        blob_store = blob_store.with_external_threshold(threshold);
+    }
 
     debug!("starting server");
     Server::builder()
@@ -154,38 +184,80 @@ where
 /// # stdout
 ///
 /// When obtaining a value for a key, the value is written to stdout. Multiple values are separated
-/// by a null byte (`\0`).
+/// by a null byte (`\0`). Nothing is written for keys that do not exist.
+///
+/// # stderr
+///
+/// Keys that do not exist are written to stderr, one per line.
 #[cfg_attr(feature = "tracing", tracing::instrument)]
 async fn kv<Backend>(
     KvArgs { store, command }: KvArgs,
 ) -> Result<ExitCode, Box<dyn std::error::Error>>
 where
-    Backend: KvBackend<GetStream: Send, SetStream: Send, DeleteStream: Send, Error: IntoTonicStatus>
-        + 'static,
+    Backend: KvBackend<
+            GetStream: Send,
+            SetStream: Send,
+            DeleteStream: Send,
+            ScanStream: Send,
+            CompareAndSwapStream: Send,
+            TransactionStream: Send,
+            WatchStream: Send,
+            IncrementStream: Send,
+            HistoryStream: Send,
+            Error: IntoTonicStatus,
+        > + 'static,
 {
     let mut client = transitive::kv_client::<_, Backend>(store).await?;
     match command {
         cli::KvCommand::Get { keys } => {
             let mut values = client
-                .get(stream::iter(
-                    keys.into_iter().map(|key| kv::GetRequest { key }),
-                ))
+                .get(stream::iter(keys.clone().into_iter().map(|key| {
+                    kv::GetRequest {
+                        key,
+                        allow_missing: true,
+                        version: None,
+                    }
+                })))
                 .await?
                 .into_inner();
 
             let mut stdout = io::stdout();
-            let Some(kv::GetResponse { value }) = values.message().await? else {
-                return Err(Box::new(ErrStr("expected at least one value")));
-            };
-            stdout.write_all(value.as_bytes()).await?;
-            while let Some(kv::GetResponse { value }) = values.message().await? {
-                stdout.write_all(&[0]).await?;
-                stdout.write_all(value.as_bytes()).await?;
+            let mut missing = Vec::new();
+            for (idx, key) in keys.iter().enumerate() {
+                let Some(kv::GetResponse { value, .. }) = values.message().await? else {
+                    return Err(Box::new(ErrStr("expected a value for each key")));
+                };
+                if idx != 0 {
+                    stdout.write_all(&[0]).await?;
+                }
+                match value {
+                    Some(value) => stdout.write_all(&value).await?,
+                    None => missing.push(key.as_str()),
+                }
+            }
+            stdout.flush().await?;
+            if !missing.is_empty() {
+                let mut stderr = io::stderr();
+                for key in missing {
+                    stderr.write_all(key.as_bytes()).await?;
+                    stderr.write_all(b"\n").await?;
+                }
+                return Err(Box::new(ErrStr("the above key(s) were not found")));
             }
         }
-        cli::KvCommand::Set { key, value } => {
+        cli::KvCommand::Set { key, value, ttl } => {
+            let expiration = ttl.map(|ttl| {
+                kv::set_request::Expiration::Ttl(prost_types::Duration {
+                    seconds: ttl.into(),
+                    nanos: 0,
+                })
+            });
             let _response = client
-                .set(stream::iter([kv::SetRequest { key, value }]))
+                .set(stream::iter([kv::SetRequest {
+                    key,
+                    value: value.into_bytes(),
+                    expiration,
+                }]))
                 .await?;
         }
         cli::KvCommand::Delete { key } => {
@@ -240,60 +312,70 @@ where
             StoreStream: Send,
             UpdateStream: Send,
             DeleteStream: Send,
+            UploadStream: Send,
+            DownloadStream: Send,
+            ListStream: Send,
+            CopyStream: Send,
+            AppendStream: Send,
+            VerifyStream: Send,
             Error: IntoTonicStatus,
         > + 'static,
 {
     let mut client = transitive::blob_client::<_, Backend>(store.clone()).await?;
     match command {
         cli::BlobCommand::Get { id, mode } => {
-            let blob: Vec<_> = client
-                .get(stream::iter([blob::GetRequest { id }]))
+            let mut responses = client
+                .download(stream::iter([blob::DownloadRequest {
+                    id,
+                    chunk_size: None,
+                }]))
                 .await?
-                .into_inner()
-                .collect()
-                .await;
+                .into_inner();
             drop(client);
 
-            let (bytes, metadata) = match blob.as_slice() {
-                [Ok(blob::GetResponse { bytes, metadata })] => (bytes, metadata),
-                [Err(err)] => return Err(err.clone().into()),
-                _ => return Err(Box::new(ErrStr("expected exactly one BlobId"))),
-            };
-
-            match mode {
-                cli::BlobGetMode::Data => io::stdout().write_all(bytes).await?,
-                cli::BlobGetMode::Metadata => {
-                    if let Some(metadata) = metadata {
-                        io::stdout().write_all(metadata.as_bytes()).await?
-                    }
-                }
-                cli::BlobGetMode::All => {
-                    let mut stdout = io::stdout();
-                    if let Some(metadata) = metadata {
-                        stdout.write_all(metadata.as_bytes()).await?;
+            // The size and metadata precede the data, so each part can be written as it arrives.
+            let mut stdout = io::stdout();
+            while let Some(response) = responses.next().await {
+                match (response?.part, mode) {
+                    (
+                        Some(download_response::Part::Info(blob::BlobInfo { metadata, .. })),
+                        cli::BlobGetMode::Metadata | cli::BlobGetMode::All,
+                    ) => {
+                        if let Some(metadata) = metadata {
+                            stdout.write_all(metadata.as_bytes()).await?;
+                        }
+                        if matches!(mode, cli::BlobGetMode::All) {
+                            stdout.write_all(&[0]).await?;
+                        }
                     }
-                    stdout.write_all(&[0]).await?;
-                    stdout.write_all(bytes).await?;
+                    (
+                        Some(download_response::Part::Chunk(blob::BlobChunk { bytes, .. })),
+                        cli::BlobGetMode::Data | cli::BlobGetMode::All,
+                    ) => stdout.write_all(&bytes).await?,
+                    _ => {}
                 }
             }
+            stdout.flush().await?;
         }
         cli::BlobCommand::Store {
             file_path,
             metadata,
         } => {
+            let (sender, receiver) = mpsc::channel(1);
+            let reader = tokio::spawn(upload_file_or_stdin(file_path, metadata, sender));
             let id: Vec<_> = client
-                .store(stream::iter([blob::StoreRequest {
-                    bytes: read_file_or_stdin(file_path).await?,
-                    metadata,
-                }]))
+                .upload(ReceiverStream::new(receiver))
                 .await?
                 .into_inner()
                 .collect()
                 .await;
             drop(client);
+            // A failure to read the input takes precedence, as it prevents the upload from being
+            // finalized.
+            reader.await??;
             match id.as_slice() {
                 #[allow(clippy::print_stdout)]
-                [Ok(blob::StoreResponse { id })] => println!("{id}"),
+                [Ok(blob::UploadResponse { id, .. })] => println!("{id}"),
                 [Err(err)] => return Err(err.clone().into()),
                 _ => return Err(Box::new(ErrStr("expected exactly one BlobId"))),
             }
@@ -308,6 +390,9 @@ where
                     bytes: Some(read_file_or_stdin(file_path).await?),
                     should_update_metadata: false,
                     metadata: None,
+                    attributes: None,
+                    remove_attributes: Vec::new(),
+                    compression: blob::Compression::None.into(),
                 }]))
                 .await?;
         }
@@ -321,6 +406,9 @@ where
                     bytes: None,
                     should_update_metadata: true,
                     metadata,
+                    attributes: None,
+                    remove_attributes: Vec::new(),
+                    compression: blob::Compression::None.into(),
                 }]))
                 .await?;
         }
@@ -338,6 +426,9 @@ where
                     bytes: Some(read_file_or_stdin(file_path).await?),
                     should_update_metadata: true,
                     metadata,
+                    attributes: None,
+                    remove_attributes: Vec::new(),
+                    compression: blob::Compression::None.into(),
                 }]))
                 .await?;
         }
@@ -377,7 +468,7 @@ where
 /// Given a path, read from stdin if the path is "-". Otherwise, read the file at that path.
 #[cfg_attr(feature = "tracing", tracing::instrument)]
 async fn read_file_or_stdin(file_path: PathBuf) -> io::Result<Vec<u8>> {
-    if file_path == PathBuf::from("-") {
+    if file_path.as_os_str() == "-" {
         let mut bytes = Vec::new();
         let _num_bytes = io::stdin().read_to_end(&mut bytes).await?;
         Ok(bytes)
@@ -385,3 +476,53 @@ async fn read_file_or_stdin(file_path: PathBuf) -> io::Result<Vec<u8>> {
         Ok(fs::read(file_path).await?)
     }
 }
+
+/// Given a path, upload the contents of stdin if the path is "-". Otherwise, upload the contents of
+/// the file at that path.
+///
+/// The contents are sent in chunks as they are read, and the upload is only finalized once all of
+/// them have been sent.
+#[cfg_attr(feature = "tracing", tracing::instrument)]
+async fn upload_file_or_stdin(
+    file_path: PathBuf,
+    metadata: Option<String>,
+    sender: mpsc::Sender<blob::UploadRequest>,
+) -> io::Result<()> {
+    let mut reader: Box<dyn AsyncRead + Send + Unpin> = if file_path.as_os_str() == "-" {
+        Box::new(io::stdin())
+    } else {
+        Box::new(fs::File::open(file_path).await?)
+    };
+
+    let mut offset = 0;
+    loop {
+        let mut bytes = Vec::with_capacity(UPLOAD_CHUNK_SIZE);
+        let len = (&mut reader)
+            .take(UPLOAD_CHUNK_SIZE as u64)
+            .read_to_end(&mut bytes)
+            .await?;
+        if len == 0 {
+            break;
+        }
+        let chunk = blob::BlobChunk { offset, bytes };
+        offset += len as u64;
+        let request = blob::UploadRequest {
+            part: Some(upload_request::Part::Chunk(chunk)),
+        };
+        // The upload only ends early if it has failed, which is reported by the caller.
+        if sender.send(request).await.is_err() {
+            return Ok(());
+        }
+    }
+
+    let _res = sender
+        .send(blob::UploadRequest {
+            part: Some(upload_request::Part::Finalize(blob::Finalize {
+                metadata,
+                attributes: None,
+                compression: blob::Compression::None.into(),
+            })),
+        })
+        .await;
+    Ok(())
+}
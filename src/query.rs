@@ -1,13 +1,17 @@
 use crate::backend::DatabaseBackend;
 use crate::interop::{into_tonic_status, IntoTonicStatus};
 use crate::proto::query::{QueryResult, RawQuery, RowsChanged, TargetStore};
-use crate::queryable::Queryable;
+use crate::queryable::{QueryParams, Queryable};
 use crate::service::query::QueryRpc;
 use crate::{DynStream, Location, RpcResponse, StreamingRequest};
 use async_stream::stream;
 use futures::StreamExt as _;
 use tonic::{Response, Status};
 
+/// The name under which the BLOB store is attached to the key-value store for queries targeting
+/// both.
+const BLOB_SCHEMA: &str = "blob";
+
 /// The handler for raw queries. Supports both key-value and blob stores.
 #[must_use]
 #[derive(Debug)]
@@ -65,26 +69,57 @@ where
     async fn query(&self, request: StreamingRequest<RawQuery>) -> RpcResponse<Self::QueryStream> {
         let mut request = request.into_inner();
 
-        let mut kv_conn = self.kv_backend.connect().map_err(into_tonic_status)?;
-        let mut blob_conn = self.blob_backend.connect().map_err(into_tonic_status)?;
+        let kv_conn = self.kv_backend.connect().map_err(into_tonic_status)?;
+        let blob_conn = self.blob_backend.connect().map_err(into_tonic_status)?;
+        // The BLOB store is only attached once a query targets both stores, as DuckDB does not
+        // see changes made to a database through other connections after attaching it. This means
+        // older versions of SQLite refuse to attach it if that query is run within a transaction.
+        let blob_location = self.blob_backend.location().clone();
+        let mut blob_attached = false;
 
         let stream = stream!({
-            while let Some(RawQuery { query, target }) = request.message().await? {
+            // A transaction left open on a connection is rolled back when the connection is
+            // closed. Moving the connections into the body closes them as soon as the stream ends
+            // or fails, rather than whenever the stream itself is dropped.
+            let (mut kv_conn, mut blob_conn) = (kv_conn, blob_conn);
+
+            while let Some(RawQuery {
+                query,
+                target,
+                params,
+                named_params,
+            }) = request.message().await?
+            {
+                let params = QueryParams {
+                    positional: params,
+                    named: named_params,
+                };
                 match target.try_into().map_err(into_tonic_status)? {
                     TargetStore::Kv => {
-                        let (mut items, conn) = Backend::query(query, kv_conn).await;
+                        let (mut items, conn) = Backend::query(query, params, kv_conn).await;
                         kv_conn = conn;
                         while let Some(item) = items.next().await {
                             yield item;
                         }
                     }
                     TargetStore::Blob => {
-                        let (mut items, conn) = Backend::query(query, blob_conn).await;
+                        let (mut items, conn) = Backend::query(query, params, blob_conn).await;
                         blob_conn = conn;
                         while let Some(item) = items.next().await {
                             yield item;
                         }
                     }
+                    TargetStore::Both => {
+                        if !blob_attached {
+                            Backend::attach(&kv_conn, &blob_location, BLOB_SCHEMA)?;
+                            blob_attached = true;
+                        }
+                        let (mut items, conn) = Backend::query(query, params, kv_conn).await;
+                        kv_conn = conn;
+                        while let Some(item) = items.next().await {
+                            yield item;
+                        }
+                    }
                 }
             }
         });
@@ -99,22 +134,47 @@ where
     ) -> RpcResponse<Self::ExecuteStream> {
         let mut request = request.into_inner();
 
-        let mut kv_conn = self.kv_backend.connect().map_err(into_tonic_status)?;
-        let mut blob_conn = self.blob_backend.connect().map_err(into_tonic_status)?;
+        let kv_conn = self.kv_backend.connect().map_err(into_tonic_status)?;
+        let blob_conn = self.blob_backend.connect().map_err(into_tonic_status)?;
+        // The BLOB store is only attached once a query targets both stores, as in `query`.
+        let blob_location = self.blob_backend.location().clone();
+        let mut blob_attached = false;
 
         let stream = stream!({
-            while let Some(RawQuery { query, target }) = request.message().await? {
+            // Roll back any open transaction as soon as the stream ends or fails, as in `query`.
+            let (mut kv_conn, mut blob_conn) = (kv_conn, blob_conn);
+
+            while let Some(RawQuery {
+                query,
+                target,
+                params,
+                named_params,
+            }) = request.message().await?
+            {
+                let params = QueryParams {
+                    positional: params,
+                    named: named_params,
+                };
                 match target.try_into().map_err(into_tonic_status)? {
                     TargetStore::Kv => {
-                        let (res, conn) = Backend::execute(query, kv_conn).await;
+                        let (res, conn) = Backend::execute(query, params, kv_conn).await;
                         kv_conn = conn;
                         yield res;
                     }
                     TargetStore::Blob => {
-                        let (res, conn) = Backend::execute(query, blob_conn).await;
+                        let (res, conn) = Backend::execute(query, params, blob_conn).await;
                         blob_conn = conn;
                         yield res;
                     }
+                    TargetStore::Both => {
+                        if !blob_attached {
+                            Backend::attach(&kv_conn, &blob_location, BLOB_SCHEMA)?;
+                            blob_attached = true;
+                        }
+                        let (res, conn) = Backend::execute(query, params, kv_conn).await;
+                        kv_conn = conn;
+                        yield res;
+                    }
                 }
             }
         });
@@ -1,10 +1,22 @@
 //! A database that supports raw query execution.
 
 use crate::proto::query::{QueryResult, RowsChanged};
+use crate::Location;
 use futures::Stream;
+use prost_types::Value;
+use std::collections::HashMap;
 use std::future::Future;
 use tonic::Status;
 
+/// The values bound to the parameters of a query.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct QueryParams {
+    /// The values bound to positional parameters, in order.
+    pub positional: Vec<Value>,
+    /// The values bound to named parameters, keyed by the parameter as it appears in the query.
+    pub named: HashMap<String, Value>,
+}
+
 /// A trait for types that can execute raw queries.
 pub trait Queryable {
     /// The type of a connection to the database.
@@ -13,11 +25,12 @@ pub trait Queryable {
     /// The type of a stream containing the query results.
     type QueryStream: Stream<Item = Result<QueryResult, Status>> + Unpin;
 
-    /// Execute a query and return a stream of results.
+    /// Execute a query with the given parameters and return a stream of results.
     ///
     /// The connection is passed by ownership, but must be returned in the output tuple.
     fn query(
         query: String,
+        params: QueryParams,
         conn: Self::Connection,
     ) -> impl Future<Output = (Self::QueryStream, Self::Connection)> + Send;
 
@@ -26,6 +39,12 @@ pub trait Queryable {
     /// This is used for queries that modify the database.
     fn execute(
         query: String,
+        params: QueryParams,
         conn: Self::Connection,
     ) -> impl Future<Output = (Result<RowsChanged, Status>, Self::Connection)> + Send;
+
+    /// Attach the database at the given location to the connection under the given name, so that
+    /// a single query can refer to tables in both databases.
+    #[allow(clippy::result_large_err)] // `Status` is the error type of every RPC
+    fn attach(conn: &Self::Connection, location: &Location, name: &str) -> Result<(), Status>;
 }
@@ -7,7 +7,12 @@
 //!
 //! Note that this module is not exhaustive. The API can be expanded as needed.
 
-#![allow(unused_imports, unused_macros, dead_code)]
+#![allow(
+    unused_imports,
+    unused_macros,
+    dead_code,
+    clippy::missing_docs_in_private_items
+)]
 
 #[cfg(feature = "tracing")]
 pub(crate) use tracing::{
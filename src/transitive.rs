@@ -86,7 +86,7 @@ impl<T> DerefMut for Transitive<T> {
 }
 
 macro_rules! declare_clients {
-    ($(fn $fn_name:ident<$client:ident, $server:ident, $store:ident, $backend:ident>[$($bounds:tt)*];)*) => {$(
+    ($(fn $fn_name:ident, $store_fn_name:ident<$client:ident, $server:ident, $store:ident, $backend:ident>[$($bounds:tt)*];)*) => {$(
         #[doc = concat!("Create a new client for the `", stringify!($client), "` service.")]
         ///
         /// For in-memory connections, it is highly recommended to only call this function once. All
@@ -106,14 +106,33 @@ macro_rules! declare_clients {
                 + 'static
         {
             let location = location.into();
+            info!(?location, "creating store for transitive {}", stringify!($server));
+            let store = $store::<Backend>::at_location(location).map_err(into_tonic_status)?;
+            $store_fn_name(store).await
+        }
+
+        #[doc = concat!(
+            "Create a new client for the `", stringify!($client), "` service, served by an ",
+            "existing store."
+        )]
+        ///
+        #[doc = concat!("This behaves as [`", stringify!($fn_name), "`], but permits the store ")]
+        /// to be configured before it is served.
+        #[cfg_attr(feature = "tracing", tracing::instrument(skip(store)))]
+        pub async fn $store_fn_name<Backend>(
+            store: $store<Backend>,
+        ) -> Result<Transitive<$client<Channel>>, TransitiveError>
+        where
+            Backend: DatabaseBackend<Error: IntoTonicStatus>
+                + $backend<$($bounds)*>
+                + 'static
+        {
             let (client, server) = tokio::io::duplex(DUPLEX_SIZE);
 
-            info!(?location, "spawning transitive {}", stringify!($server));
+            info!("spawning transitive {}", stringify!($server));
             let _join_handle = tokio::spawn(async move {
                 Server::builder()
-                    .add_service($server::new(
-                        $store::<Backend>::at_location(location).map_err(into_tonic_status)?
-                    ))
+                    .add_service($server::new(store))
                     .serve_with_incoming(
                         tokio_stream::once(Ok::<_, std::io::Error>(server)),
                     )
@@ -130,10 +149,7 @@ macro_rules! declare_clients {
                             info!("client is connected to transitive {}", stringify!($server));
                             Ok(TokioIo::new(client))
                         } else {
-                            Err(std::io::Error::new(
-                                std::io::ErrorKind::Other,
-                                "Client already taken",
-                            ))
+                            Err(std::io::Error::other("Client already taken"))
                         }
                     }
                 }))
@@ -147,16 +163,28 @@ macro_rules! declare_clients {
 }
 
 declare_clients! {
-    fn kv_client<KvClient, KvServer, KvStore, KvBackend>[
+    fn kv_client, kv_client_for_store<KvClient, KvServer, KvStore, KvBackend>[
         GetStream: Send,
         SetStream: Send,
         DeleteStream: Send,
+        ScanStream: Send,
+        CompareAndSwapStream: Send,
+        TransactionStream: Send,
+        WatchStream: Send,
+        IncrementStream: Send,
+        HistoryStream: Send,
     ];
-    fn blob_client<BlobClient, BlobServer, BlobStore, BlobBackend>[
+    fn blob_client, blob_client_for_store<BlobClient, BlobServer, BlobStore, BlobBackend>[
         GetStream: Send,
         StoreStream: Send,
         UpdateStream: Send,
         DeleteStream: Send,
+        UploadStream: Send,
+        DownloadStream: Send,
+        ListStream: Send,
+        CopyStream: Send,
+        AppendStream: Send,
+        VerifyStream: Send,
     ];
 }
 
@@ -212,10 +240,7 @@ where
                     info!("client is connected to transitive QueryServer");
                     Ok(TokioIo::new(client))
                 } else {
-                    Err(std::io::Error::new(
-                        std::io::ErrorKind::Other,
-                        "Client already taken",
-                    ))
+                    Err(std::io::Error::other("Client already taken"))
                 }
             }
         }))
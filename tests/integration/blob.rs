@@ -1,14 +1,23 @@
 use crate::helpers::assert_stream_eq;
 use anyhow::{bail, Result};
 use buffdb::client::blob::BlobClient;
+use buffdb::prost_types::value::Kind;
+use buffdb::prost_types::{ListValue, Struct, Value};
+use buffdb::proto::blob::download_response::Part as DownloadPart;
+use buffdb::proto::blob::upload_request::Part as UploadPart;
 use buffdb::proto::blob::{
-    DeleteRequest, DeleteResponse, EqDataRequest, GetRequest, GetResponse, NotEqDataRequest,
-    StoreRequest, StoreResponse, UpdateRequest, UpdateResponse,
+    AppendRequest, AppendResponse, BlobChunk, BlobInfo, Compression, CopyRequest, DeleteRequest,
+    DeleteResponse, DownloadRequest, EqDataRequest, Finalize, GetRequest, GetResponse, ListRequest,
+    ListResponse, NotEqDataRequest, StoreRequest, StoreResponse, UpdateRequest, UpdateResponse,
+    UploadRequest, UploadResponse, VerifyRequest,
 };
-use buffdb::transitive::blob_client;
+use buffdb::store::BlobStore;
+use buffdb::transitive::{blob_client, blob_client_for_store};
 use buffdb::Location;
-use futures::{stream, StreamExt as _};
+use futures::{stream, StreamExt as _, TryStreamExt as _};
 use serial_test::serial;
+use sha2::{Digest as _, Sha256};
+use std::collections::BTreeMap;
 use std::sync::LazyLock;
 use tonic::transport::Channel;
 
@@ -24,12 +33,76 @@ async fn insert_one(client: &mut BlobClient<Channel>, value: StoreRequest) -> Re
         .collect::<Vec<_>>()
         .await;
     match id.as_slice() {
-        [Ok(StoreResponse { id })] => Ok(*id),
+        [Ok(StoreResponse { id, .. })] => Ok(*id),
         [Err(e)] => Err(e.clone().into()),
         _ => bail!("expected exactly one BlobId"),
     }
 }
 
+/// The requests uploading `bytes` in chunks of at most `chunk_size` bytes.
+fn upload_requests(
+    bytes: &[u8],
+    chunk_size: usize,
+    metadata: Option<String>,
+) -> Vec<UploadRequest> {
+    bytes
+        .chunks(chunk_size)
+        .zip((0..).step_by(chunk_size))
+        .map(|(chunk, offset)| UploadRequest {
+            part: Some(UploadPart::Chunk(BlobChunk {
+                offset,
+                bytes: chunk.to_vec(),
+            })),
+        })
+        .chain([UploadRequest {
+            part: Some(UploadPart::Finalize(Finalize {
+                metadata,
+                attributes: None,
+                compression: Compression::None.into(),
+            })),
+        }])
+        .collect()
+}
+
+/// A `Struct` with the given fields.
+fn attributes<const N: usize>(fields: [(&str, Kind); N]) -> Struct {
+    Struct {
+        fields: fields
+            .into_iter()
+            .map(|(name, kind)| (name.to_owned(), Value { kind: Some(kind) }))
+            .collect::<BTreeMap<_, _>>(),
+    }
+}
+
+/// Download a BLOB in chunks of `chunk_size` bytes, reassembling its data.
+async fn download(
+    client: &mut BlobClient<Channel>,
+    id: u64,
+    chunk_size: u32,
+) -> Result<(BlobInfo, Vec<u8>)> {
+    let mut responses = client
+        .download(stream::iter([DownloadRequest {
+            id,
+            chunk_size: Some(chunk_size),
+        }]))
+        .await?
+        .into_inner();
+    let Some(DownloadPart::Info(info)) = responses.try_next().await?.and_then(|r| r.part) else {
+        bail!("expected the size and metadata to be sent first");
+    };
+    let mut bytes = Vec::new();
+    while let Some(response) = responses.try_next().await? {
+        assert_eq!(response.id, id);
+        let Some(DownloadPart::Chunk(chunk)) = response.part else {
+            bail!("expected a chunk");
+        };
+        assert_eq!(chunk.offset, bytes.len() as u64);
+        assert!(chunk.bytes.len() <= chunk_size as usize);
+        bytes.extend_from_slice(&chunk.bytes);
+    }
+    Ok((info, bytes))
+}
+
 #[tokio::test]
 #[serial]
 async fn test_get() -> Result<()> {
@@ -40,12 +113,20 @@ async fn test_get() -> Result<()> {
         StoreRequest {
             bytes: b"abcdef".to_vec(),
             metadata: None,
+            attributes: None,
+            compression: Compression::None.into(),
+            id: None,
+            idempotency_key: None,
         },
     )
     .await?;
 
     let response = client
-        .get(stream::iter([GetRequest { id }]))
+        .get(stream::iter([GetRequest {
+            id,
+            offset: None,
+            length: None,
+        }]))
         .await?
         .into_inner();
     drop(client);
@@ -54,6 +135,8 @@ async fn test_get() -> Result<()> {
         [GetResponse {
             bytes: b"abcdef".to_vec(),
             metadata: None,
+            attributes: None,
+            digest: Sha256::digest(b"abcdef").to_vec(),
         }],
     )
     .await;
@@ -61,6 +144,153 @@ async fn test_get() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+#[serial]
+async fn test_get_range() -> Result<()> {
+    let mut client = blob_client::<_, super::Backend>(BLOB_STORE_LOC.clone()).await?;
+
+    let stored_id = insert_one(
+        &mut client,
+        StoreRequest {
+            bytes: b"0123456789".to_vec(),
+            metadata: Some("{}".to_owned()),
+            attributes: None,
+            compression: Compression::None.into(),
+            id: None,
+            idempotency_key: None,
+        },
+    )
+    .await?;
+    let responses = client
+        .upload(stream::iter(upload_requests(b"0123456789", 4, None)))
+        .await?
+        .into_inner()
+        .try_collect::<Vec<_>>()
+        .await?;
+    let [UploadResponse {
+        id: uploaded_id, ..
+    }] = responses[..]
+    else {
+        bail!("unexpected responses {responses:?}");
+    };
+
+    for id in [stored_id, uploaded_id] {
+        let range = |offset, length| GetRequest { id, offset, length };
+        let response = client
+            .get(stream::iter([
+                range(Some(3), Some(5)),
+                range(Some(7), None),
+                range(None, Some(2)),
+                range(Some(8), Some(100)),
+                range(Some(10), None),
+            ]))
+            .await?
+            .into_inner();
+        assert_stream_eq(
+            response.map_ok(|response| response.bytes),
+            [
+                b"34567".to_vec(),
+                b"789".to_vec(),
+                b"01".to_vec(),
+                b"89".to_vec(),
+                Vec::new(),
+            ],
+        )
+        .await;
+
+        let response = client
+            .get(stream::iter([range(Some(11), None)]))
+            .await?
+            .into_inner()
+            .try_collect::<Vec<_>>()
+            .await;
+        assert_eq!(
+            response.map_err(|status| status.code()),
+            Err(tonic::Code::OutOfRange)
+        );
+    }
+
+    let _response = client
+        .delete(stream::iter([
+            DeleteRequest { id: stored_id },
+            DeleteRequest { id: uploaded_id },
+        ]))
+        .await?
+        .into_inner()
+        .try_collect::<Vec<_>>()
+        .await?;
+    drop(client);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn test_get_range_across_chunks() -> Result<()> {
+    let mut client = blob_client::<_, super::Backend>(BLOB_STORE_LOC.clone()).await?;
+
+    // Large content is stored in chunks, which ranges can span.
+    let uploaded = (0..5 << 19).map(|i: u32| i as u8).collect::<Vec<_>>();
+    let stored = uploaded.iter().map(|byte| !byte).collect::<Vec<_>>();
+    let responses = client
+        .upload(stream::iter(upload_requests(&uploaded, 700_000, None)))
+        .await?
+        .into_inner()
+        .try_collect::<Vec<_>>()
+        .await?;
+    let [UploadResponse {
+        id: uploaded_id, ..
+    }] = responses[..]
+    else {
+        bail!("unexpected responses {responses:?}");
+    };
+    let stored_id = insert_one(
+        &mut client,
+        StoreRequest {
+            bytes: stored.clone(),
+            metadata: None,
+            attributes: None,
+            compression: Compression::None.into(),
+            id: None,
+            idempotency_key: None,
+        },
+    )
+    .await?;
+
+    for (id, data) in [(uploaded_id, &uploaded), (stored_id, &stored)] {
+        let ranges = [(699_995, 10), ((1 << 20) - 3, 1_400_000), (0, data.len())];
+        let response = client
+            .get(stream::iter(ranges.map(|(offset, length)| GetRequest {
+                id,
+                offset: Some(offset as u64),
+                length: Some(length as u64),
+            })))
+            .await?
+            .into_inner();
+        assert_stream_eq(
+            response.map_ok(|response| response.bytes),
+            ranges.map(|(offset, length)| data[offset..][..length].to_vec()),
+        )
+        .await;
+
+        let (_, bytes) = download(&mut client, id, 999_999).await?;
+        assert!(bytes == *data);
+    }
+
+    let _response = client
+        .delete(stream::iter([
+            DeleteRequest { id: uploaded_id },
+            DeleteRequest { id: stored_id },
+        ]))
+        .await?
+        .into_inner()
+        .try_collect::<Vec<_>>()
+        .await?;
+    drop(client);
+
+    Ok(())
+}
+
 #[tokio::test]
 #[serial]
 async fn test_store() -> Result<()> {
@@ -71,12 +301,20 @@ async fn test_store() -> Result<()> {
         StoreRequest {
             bytes: b"abcdef".to_vec(),
             metadata: Some("{}".to_owned()),
+            attributes: None,
+            compression: Compression::None.into(),
+            id: None,
+            idempotency_key: None,
         },
     )
     .await?;
 
     let response = client
-        .get(stream::iter([GetRequest { id }]))
+        .get(stream::iter([GetRequest {
+            id,
+            offset: None,
+            length: None,
+        }]))
         .await?
         .into_inner();
     drop(client);
@@ -85,6 +323,8 @@ async fn test_store() -> Result<()> {
         [GetResponse {
             bytes: b"abcdef".to_vec(),
             metadata: Some("{}".to_owned()),
+            attributes: None,
+            digest: Sha256::digest(b"abcdef").to_vec(),
         }],
     )
     .await;
@@ -92,6 +332,543 @@ async fn test_store() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+#[serial]
+async fn test_store_deduplicated() -> Result<()> {
+    let mut client = blob_client::<_, super::Backend>(BLOB_STORE_LOC.clone()).await?;
+
+    let store = |metadata: &str| StoreRequest {
+        bytes: b"duplicate".to_vec(),
+        metadata: Some(metadata.to_owned()),
+        attributes: None,
+        compression: Compression::None.into(),
+        id: None,
+        idempotency_key: None,
+    };
+    let responses = client
+        .store(stream::iter([store("first"), store("second")]))
+        .await?
+        .into_inner()
+        .try_collect::<Vec<_>>()
+        .await?;
+    let [StoreResponse {
+        id: first_id,
+        digest: ref first_digest,
+        ..
+    }, StoreResponse {
+        id: second_id,
+        digest: ref second_digest,
+        ..
+    }] = responses[..]
+    else {
+        bail!("unexpected responses {responses:?}");
+    };
+    assert_ne!(first_id, second_id);
+    assert_eq!(first_digest[..], Sha256::digest(b"duplicate")[..]);
+    assert_eq!(first_digest, second_digest);
+
+    // BLOBs uploaded in chunks share content with those stored whole.
+    let responses = client
+        .upload(stream::iter(upload_requests(b"duplicate", 4, None)))
+        .await?
+        .into_inner()
+        .try_collect::<Vec<_>>()
+        .await?;
+    let [UploadResponse {
+        id: uploaded_id,
+        ref digest,
+        ..
+    }] = responses[..]
+    else {
+        bail!("unexpected responses {responses:?}");
+    };
+    assert_eq!(digest, first_digest);
+
+    // Changing or deleting one BLOB does not affect the others sharing its content.
+    let _response = client
+        .update(stream::iter([UpdateRequest {
+            id: first_id,
+            bytes: Some(b"changed".to_vec()),
+            should_update_metadata: false,
+            metadata: None,
+            attributes: None,
+            remove_attributes: Vec::new(),
+            compression: Compression::None.into(),
+        }]))
+        .await?
+        .into_inner()
+        .try_collect::<Vec<_>>()
+        .await?;
+    let _response = client
+        .delete(stream::iter([DeleteRequest { id: second_id }]))
+        .await?
+        .into_inner()
+        .try_collect::<Vec<_>>()
+        .await?;
+    let get = |id| GetRequest {
+        id,
+        offset: None,
+        length: None,
+    };
+    let response = client
+        .get(stream::iter([get(first_id), get(uploaded_id)]))
+        .await?
+        .into_inner();
+    assert_stream_eq(
+        response,
+        [
+            GetResponse {
+                bytes: b"changed".to_vec(),
+                metadata: Some("first".to_owned()),
+                attributes: None,
+                digest: Sha256::digest(b"changed").to_vec(),
+            },
+            GetResponse {
+                bytes: b"duplicate".to_vec(),
+                metadata: None,
+                attributes: None,
+                digest: Sha256::digest(b"duplicate").to_vec(),
+            },
+        ],
+    )
+    .await;
+
+    let _response = client
+        .delete(stream::iter([
+            DeleteRequest { id: first_id },
+            DeleteRequest { id: uploaded_id },
+        ]))
+        .await?
+        .into_inner()
+        .try_collect::<Vec<_>>()
+        .await?;
+    let response = client
+        .get(stream::iter([get(uploaded_id)]))
+        .await?
+        .into_inner()
+        .try_collect::<Vec<_>>()
+        .await;
+    drop(client);
+    assert_eq!(
+        response.map_err(|status| status.code()),
+        Err(tonic::Code::NotFound)
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+#[serial]
+async fn test_store_deduplicated_concurrent() -> Result<()> {
+    let mut client = blob_client::<_, super::Backend>(BLOB_STORE_LOC.clone()).await?;
+
+    let contents = (0..10)
+        .map(|round| format!("stored concurrently {round}").into_bytes())
+        .collect::<Vec<_>>();
+    let tasks = (0..4).map(|_| {
+        let mut client = client.clone();
+        let requests = contents
+            .iter()
+            .map(|bytes| StoreRequest {
+                bytes: bytes.clone(),
+                metadata: None,
+                attributes: None,
+                compression: Compression::None.into(),
+                id: None,
+                idempotency_key: None,
+            })
+            .collect::<Vec<_>>();
+        tokio::spawn(async move {
+            client
+                .store(stream::iter(requests))
+                .await?
+                .into_inner()
+                .try_collect::<Vec<_>>()
+                .await
+        })
+    });
+    let mut ids = Vec::new();
+    for task in futures::future::join_all(tasks).await {
+        let responses = task??;
+        assert_eq!(responses.len(), contents.len());
+        for (response, bytes) in responses.iter().zip(&contents) {
+            assert_eq!(response.digest[..], Sha256::digest(bytes)[..]);
+            ids.push((response.id, bytes.clone()));
+        }
+    }
+
+    // Every BLOB can be read back, even though all but one of those with the same content were
+    // stored while another transaction was storing it.
+    for (id, bytes) in &ids {
+        let id = *id;
+        let response = client
+            .get(stream::iter([GetRequest {
+                id,
+                offset: None,
+                length: None,
+            }]))
+            .await?
+            .into_inner()
+            .try_collect::<Vec<_>>()
+            .await?;
+        assert_eq!(response.len(), 1);
+        assert_eq!(&response[0].bytes, bytes);
+    }
+    let _response = client
+        .delete(stream::iter(
+            ids.into_iter().map(|(id, _)| DeleteRequest { id }),
+        ))
+        .await?
+        .into_inner()
+        .try_collect::<Vec<_>>()
+        .await?;
+    drop(client);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn test_store_idempotent() -> Result<()> {
+    let mut client = blob_client::<_, super::Backend>(BLOB_STORE_LOC.clone()).await?;
+
+    let store = |bytes: &[u8], id, idempotency_key: Option<&str>| StoreRequest {
+        bytes: bytes.to_vec(),
+        metadata: None,
+        attributes: None,
+        compression: Compression::None.into(),
+        id,
+        idempotency_key: idempotency_key.map(str::to_owned),
+    };
+    // A retried request stores nothing, regardless of the data sent.
+    let responses = client
+        .store(stream::iter([
+            store(b"first", None, Some("test_store_idempotent")),
+            store(b"retried", None, Some("test_store_idempotent")),
+            store(b"chosen", Some(5_000_000_000), None),
+            store(b"retried", Some(5_000_000_000), None),
+        ]))
+        .await?
+        .into_inner()
+        .map_ok(|response| (response.id, response.already_exists))
+        .try_collect::<Vec<_>>()
+        .await?;
+    let [(keyed_id, false), (retried_id, true), (5_000_000_000, false), (5_000_000_000, true)] =
+        responses[..]
+    else {
+        bail!("unexpected responses {responses:?}");
+    };
+    assert_eq!(keyed_id, retried_id);
+
+    let response = client
+        .get(stream::iter([keyed_id, 5_000_000_000].map(|id| {
+            GetRequest {
+                id,
+                offset: None,
+                length: None,
+            }
+        })))
+        .await?
+        .into_inner();
+    assert_stream_eq(
+        response.map_ok(|response| response.bytes),
+        [b"first".to_vec(), b"chosen".to_vec()],
+    )
+    .await;
+
+    // The key is forgotten once the BLOB is deleted.
+    let _response = client
+        .delete(stream::iter(
+            [keyed_id, 5_000_000_000].map(|id| DeleteRequest { id }),
+        ))
+        .await?
+        .into_inner()
+        .try_collect::<Vec<_>>()
+        .await?;
+    let responses = client
+        .store(stream::iter([store(
+            b"second",
+            None,
+            Some("test_store_idempotent"),
+        )]))
+        .await?
+        .into_inner()
+        .try_collect::<Vec<_>>()
+        .await?;
+    let [StoreResponse {
+        id,
+        already_exists: false,
+        ..
+    }] = responses[..]
+    else {
+        bail!("unexpected responses {responses:?}");
+    };
+    let _response = client
+        .delete(stream::iter([DeleteRequest { id }]))
+        .await?
+        .into_inner()
+        .try_collect::<Vec<_>>()
+        .await?;
+    drop(client);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn test_store_compressed() -> Result<()> {
+    let mut client = blob_client::<_, super::Backend>(BLOB_STORE_LOC.clone()).await?;
+
+    for (compression, line) in [
+        (Compression::Zstd, "INFO zstd: sensor reading nominal\n"),
+        (Compression::Lz4, "INFO lz4: sensor reading nominal\n"),
+    ] {
+        let data = line.repeat(500).into_bytes();
+        let stored_id = insert_one(
+            &mut client,
+            StoreRequest {
+                bytes: data.clone(),
+                metadata: None,
+                attributes: None,
+                compression: compression.into(),
+                id: None,
+                idempotency_key: None,
+            },
+        )
+        .await?;
+        let mut requests = upload_requests(&data, 1000, None);
+        if let Some(UploadRequest {
+            part: Some(UploadPart::Finalize(finalize)),
+        }) = requests.last_mut()
+        {
+            finalize.compression = compression.into();
+        }
+        let responses = client
+            .upload(stream::iter(requests))
+            .await?
+            .into_inner()
+            .try_collect::<Vec<_>>()
+            .await?;
+        let [UploadResponse {
+            id: uploaded_id, ..
+        }] = responses[..]
+        else {
+            bail!("unexpected responses {responses:?}");
+        };
+
+        for id in [stored_id, uploaded_id] {
+            let range = |offset, length| GetRequest { id, offset, length };
+            let response = client
+                .get(stream::iter([
+                    range(None, None),
+                    range(Some(100), Some(50)),
+                ]))
+                .await?
+                .into_inner();
+            assert_stream_eq(
+                response.map_ok(|response| response.bytes),
+                [data.clone(), data[100..150].to_vec()],
+            )
+            .await;
+
+            let (info, bytes) = download(&mut client, id, 1000).await?;
+            assert_eq!(info.size, data.len() as u64);
+            assert_eq!(bytes, data);
+        }
+
+        // Data is compared by its logical bytes, regardless of how it is stored.
+        let plain_id = insert_one(
+            &mut client,
+            StoreRequest {
+                bytes: data.clone(),
+                metadata: None,
+                attributes: None,
+                compression: Compression::None.into(),
+                id: None,
+                idempotency_key: None,
+            },
+        )
+        .await?;
+        let eq = client
+            .eq_data(stream::iter(
+                [stored_id, uploaded_id, plain_id].map(|id| EqDataRequest { id }),
+            ))
+            .await?
+            .into_inner();
+        assert!(eq);
+
+        let _response = client
+            .delete(stream::iter(
+                [stored_id, uploaded_id, plain_id].map(|id| DeleteRequest { id }),
+            ))
+            .await?
+            .into_inner()
+            .try_collect::<Vec<_>>()
+            .await?;
+    }
+
+    // Data that does not compress is stored as is.
+    let id = insert_one(
+        &mut client,
+        StoreRequest {
+            bytes: b"x".to_vec(),
+            metadata: None,
+            attributes: None,
+            compression: Compression::Zstd.into(),
+            id: None,
+            idempotency_key: None,
+        },
+    )
+    .await?;
+    let response = client
+        .get(stream::iter([GetRequest {
+            id,
+            offset: None,
+            length: None,
+        }]))
+        .await?
+        .into_inner();
+    assert_stream_eq(response.map_ok(|response| response.bytes), [b"x".to_vec()]).await;
+
+    let _response = client
+        .delete(stream::iter([DeleteRequest { id }]))
+        .await?
+        .into_inner()
+        .try_collect::<Vec<_>>()
+        .await?;
+    drop(client);
+
+    Ok(())
+}
+
+/// The number of files in the directory storing large BLOB data.
+fn external_file_count() -> Result<usize> {
+    match std::fs::read_dir(format!("{}-blobs", super::BLOB_PATH)) {
+        Ok(entries) => Ok(entries.count()),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(0),
+        Err(err) => Err(err.into()),
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn test_external_files() -> Result<()> {
+    let store = BlobStore::<super::Backend>::at_location(BLOB_STORE_LOC.clone())?
+        .with_external_threshold(64);
+    let mut client = blob_client_for_store(store).await?;
+    let file_count = external_file_count()?;
+
+    let data = (0..1000_u32).flat_map(u32::to_le_bytes).collect::<Vec<_>>();
+    let stored_id = insert_one(
+        &mut client,
+        StoreRequest {
+            bytes: data.clone(),
+            metadata: Some("large".to_owned()),
+            attributes: None,
+            compression: Compression::None.into(),
+            id: None,
+            idempotency_key: None,
+        },
+    )
+    .await?;
+    let responses = client
+        .upload(stream::iter(upload_requests(&data[..3000], 700, None)))
+        .await?
+        .into_inner()
+        .try_collect::<Vec<_>>()
+        .await?;
+    let [UploadResponse {
+        id: uploaded_id, ..
+    }] = responses[..]
+    else {
+        bail!("unexpected responses {responses:?}");
+    };
+    // Data at or below the threshold is stored in the database.
+    let small_id = insert_one(
+        &mut client,
+        StoreRequest {
+            bytes: data[..64].to_vec(),
+            metadata: None,
+            attributes: None,
+            compression: Compression::None.into(),
+            id: None,
+            idempotency_key: None,
+        },
+    )
+    .await?;
+    assert_eq!(external_file_count()?, file_count + 2);
+
+    let response = client
+        .get(stream::iter([
+            GetRequest {
+                id: stored_id,
+                offset: None,
+                length: None,
+            },
+            GetRequest {
+                id: uploaded_id,
+                offset: Some(1000),
+                length: Some(100),
+            },
+            GetRequest {
+                id: small_id,
+                offset: None,
+                length: None,
+            },
+        ]))
+        .await?
+        .into_inner();
+    assert_stream_eq(
+        response.map_ok(|response| (response.bytes, response.metadata)),
+        [
+            (data.clone(), Some("large".to_owned())),
+            (data[1000..1100].to_vec(), None),
+            (data[..64].to_vec(), None),
+        ],
+    )
+    .await;
+    let (info, bytes) = download(&mut client, uploaded_id, 256).await?;
+    assert_eq!(info.size, 3000);
+    assert_eq!(bytes, data[..3000]);
+
+    // Replacing the data of a BLOB removes the file storing its old data.
+    let _response = client
+        .update(stream::iter([UpdateRequest {
+            id: uploaded_id,
+            bytes: Some(data.clone()),
+            should_update_metadata: false,
+            metadata: None,
+            attributes: None,
+            remove_attributes: Vec::new(),
+            compression: Compression::None.into(),
+        }]))
+        .await?
+        .into_inner()
+        .try_collect::<Vec<_>>()
+        .await?;
+    assert_eq!(external_file_count()?, file_count + 1);
+    let eq = client
+        .eq_data(stream::iter(
+            [stored_id, uploaded_id].map(|id| EqDataRequest { id }),
+        ))
+        .await?
+        .into_inner();
+    assert!(eq);
+
+    let _response = client
+        .delete(stream::iter(
+            [stored_id, uploaded_id, small_id].map(|id| DeleteRequest { id }),
+        ))
+        .await?
+        .into_inner()
+        .try_collect::<Vec<_>>()
+        .await?;
+    assert_eq!(external_file_count()?, file_count);
+    drop(client);
+
+    Ok(())
+}
+
 #[tokio::test]
 #[serial]
 async fn test_update_both() -> Result<()> {
@@ -102,6 +879,10 @@ async fn test_update_both() -> Result<()> {
         StoreRequest {
             bytes: b"abcdef".to_vec(),
             metadata: None,
+            attributes: None,
+            compression: Compression::None.into(),
+            id: None,
+            idempotency_key: None,
         },
     )
     .await?;
@@ -112,13 +893,20 @@ async fn test_update_both() -> Result<()> {
             bytes: Some(b"def".to_vec()),
             should_update_metadata: true,
             metadata: Some("{}".to_owned()),
+            attributes: None,
+            remove_attributes: Vec::new(),
+            compression: Compression::None.into(),
         }]))
         .await?
         .into_inner();
     assert_stream_eq(stream, [UpdateResponse { id }]).await;
 
     let response = client
-        .get(stream::iter([GetRequest { id }]))
+        .get(stream::iter([GetRequest {
+            id,
+            offset: None,
+            length: None,
+        }]))
         .await?
         .into_inner();
     drop(client);
@@ -127,6 +915,8 @@ async fn test_update_both() -> Result<()> {
         [GetResponse {
             bytes: b"def".to_vec(),
             metadata: Some("{}".to_owned()),
+            attributes: None,
+            digest: Sha256::digest(b"def").to_vec(),
         }],
     )
     .await;
@@ -144,6 +934,10 @@ async fn test_update_bytes() -> Result<()> {
         StoreRequest {
             bytes: b"abcdef".to_vec(),
             metadata: None,
+            attributes: None,
+            compression: Compression::None.into(),
+            id: None,
+            idempotency_key: None,
         },
     )
     .await?;
@@ -154,18 +948,29 @@ async fn test_update_bytes() -> Result<()> {
             bytes: Some(b"def".to_vec()),
             should_update_metadata: false,
             metadata: Some("{}".to_owned()),
+            attributes: None,
+            remove_attributes: Vec::new(),
+            compression: Compression::None.into(),
         }]))
         .await?
         .into_inner();
     assert_stream_eq(stream, [UpdateResponse { id }]).await;
 
-    let response = client.get(stream::iter([GetRequest { id }])).await?;
+    let response = client
+        .get(stream::iter([GetRequest {
+            id,
+            offset: None,
+            length: None,
+        }]))
+        .await?;
     drop(client);
     assert_stream_eq(
         response.into_inner(),
         [GetResponse {
             bytes: b"def".to_vec(),
             metadata: None,
+            attributes: None,
+            digest: Sha256::digest(b"def").to_vec(),
         }],
     )
     .await;
@@ -183,6 +988,10 @@ async fn test_update_metadata() -> Result<()> {
         StoreRequest {
             bytes: b"def".to_vec(),
             metadata: Some("{}".to_owned()),
+            attributes: None,
+            compression: Compression::None.into(),
+            id: None,
+            idempotency_key: None,
         },
     )
     .await?;
@@ -193,18 +1002,29 @@ async fn test_update_metadata() -> Result<()> {
             bytes: None,
             should_update_metadata: true,
             metadata: Some("{}".to_owned()),
+            attributes: None,
+            remove_attributes: Vec::new(),
+            compression: Compression::None.into(),
         }]))
         .await?
         .into_inner();
     assert_stream_eq(stream, [UpdateResponse { id }]).await;
 
-    let response = client.get(stream::iter([GetRequest { id }])).await?;
+    let response = client
+        .get(stream::iter([GetRequest {
+            id,
+            offset: None,
+            length: None,
+        }]))
+        .await?;
     drop(client);
     assert_stream_eq(
         response.into_inner(),
         [GetResponse {
             bytes: b"def".to_vec(),
             metadata: Some("{}".to_owned()),
+            attributes: None,
+            digest: Sha256::digest(b"def").to_vec(),
         }],
     )
     .await;
@@ -222,6 +1042,10 @@ async fn test_delete_with_metadata() -> Result<()> {
         StoreRequest {
             bytes: b"abcdef".to_vec(),
             metadata: Some("{}".to_owned()),
+            attributes: None,
+            compression: Compression::None.into(),
+            id: None,
+            idempotency_key: None,
         },
     )
     .await?;
@@ -233,7 +1057,11 @@ async fn test_delete_with_metadata() -> Result<()> {
     assert_stream_eq(response, [DeleteResponse { id }]).await;
 
     let mut response = client
-        .get(stream::iter([GetRequest { id }]))
+        .get(stream::iter([GetRequest {
+            id,
+            offset: None,
+            length: None,
+        }]))
         .await?
         .into_inner();
     drop(client);
@@ -253,6 +1081,10 @@ async fn test_delete_no_metadata() -> Result<()> {
         StoreRequest {
             bytes: b"abcdef".to_vec(),
             metadata: None,
+            attributes: None,
+            compression: Compression::None.into(),
+            id: None,
+            idempotency_key: None,
         },
     )
     .await?;
@@ -264,7 +1096,11 @@ async fn test_delete_no_metadata() -> Result<()> {
     assert_stream_eq(response, [DeleteResponse { id }]).await;
 
     let mut response = client
-        .get(stream::iter([GetRequest { id }]))
+        .get(stream::iter([GetRequest {
+            id,
+            offset: None,
+            length: None,
+        }]))
         .await?
         .into_inner();
     drop(client);
@@ -284,6 +1120,10 @@ async fn test_eq_data() -> Result<()> {
         StoreRequest {
             bytes: b"abcdef".to_vec(),
             metadata: None,
+            attributes: None,
+            compression: Compression::None.into(),
+            id: None,
+            idempotency_key: None,
         },
     )
     .await?;
@@ -292,6 +1132,10 @@ async fn test_eq_data() -> Result<()> {
         StoreRequest {
             bytes: b"abcdef".to_vec(),
             metadata: None,
+            attributes: None,
+            compression: Compression::None.into(),
+            id: None,
+            idempotency_key: None,
         },
     )
     .await?;
@@ -300,6 +1144,10 @@ async fn test_eq_data() -> Result<()> {
         StoreRequest {
             bytes: b"ghijkl".to_vec(),
             metadata: None,
+            attributes: None,
+            compression: Compression::None.into(),
+            id: None,
+            idempotency_key: None,
         },
     )
     .await?;
@@ -336,6 +1184,10 @@ async fn test_not_eq_data() -> Result<()> {
         StoreRequest {
             bytes: b"abcdef".to_vec(),
             metadata: None,
+            attributes: None,
+            compression: Compression::None.into(),
+            id: None,
+            idempotency_key: None,
         },
     )
     .await?;
@@ -344,6 +1196,10 @@ async fn test_not_eq_data() -> Result<()> {
         StoreRequest {
             bytes: b"abcdef".to_vec(),
             metadata: None,
+            attributes: None,
+            compression: Compression::None.into(),
+            id: None,
+            idempotency_key: None,
         },
     )
     .await?;
@@ -352,6 +1208,10 @@ async fn test_not_eq_data() -> Result<()> {
         StoreRequest {
             bytes: b"ghijkl".to_vec(),
             metadata: None,
+            attributes: None,
+            compression: Compression::None.into(),
+            id: None,
+            idempotency_key: None,
         },
     )
     .await?;
@@ -413,3 +1273,864 @@ async fn test_not_eq_data_not_found() -> Result<()> {
     assert!(res.is_err());
     Ok(())
 }
+
+#[tokio::test]
+#[serial]
+async fn test_upload_download() -> Result<()> {
+    let mut client = blob_client::<_, super::Backend>(BLOB_STORE_LOC.clone()).await?;
+
+    let requests = upload_requests(b"abcdefghij", 4, Some("chunked".to_owned()))
+        .into_iter()
+        .chain(upload_requests(b"", 4, None));
+    let responses = client
+        .upload(stream::iter(requests))
+        .await?
+        .into_inner()
+        .try_collect::<Vec<_>>()
+        .await?;
+    let [UploadResponse { id, size: 10, .. }, UploadResponse {
+        id: empty_id,
+        size: 0,
+        ..
+    }] = responses[..]
+    else {
+        bail!("unexpected responses {responses:?}");
+    };
+
+    // BLOBs uploaded in chunks can be retrieved as a whole.
+    let stream = client
+        .get(stream::iter([GetRequest {
+            id,
+            offset: None,
+            length: None,
+        }]))
+        .await?
+        .into_inner();
+    assert_stream_eq(
+        stream,
+        [GetResponse {
+            bytes: b"abcdefghij".to_vec(),
+            metadata: Some("chunked".to_owned()),
+            attributes: None,
+            digest: Sha256::digest(b"abcdefghij").to_vec(),
+        }],
+    )
+    .await;
+
+    let (info, bytes) = download(&mut client, id, 3).await?;
+    assert_eq!(
+        info,
+        BlobInfo {
+            size: 10,
+            metadata: Some("chunked".to_owned()),
+            attributes: None,
+            digest: Sha256::digest(b"abcdefghij").to_vec(),
+        }
+    );
+    assert_eq!(bytes, b"abcdefghij");
+
+    let (info, bytes) = download(&mut client, empty_id, 3).await?;
+    assert_eq!(
+        info,
+        BlobInfo {
+            size: 0,
+            metadata: None,
+            attributes: None,
+            digest: Sha256::digest(b"").to_vec(),
+        }
+    );
+    assert!(bytes.is_empty());
+
+    // BLOBs stored as a whole can be downloaded in chunks.
+    let stored_id = insert_one(
+        &mut client,
+        StoreRequest {
+            bytes: b"stored whole".to_vec(),
+            metadata: None,
+            attributes: None,
+            compression: Compression::None.into(),
+            id: None,
+            idempotency_key: None,
+        },
+    )
+    .await?;
+    let (_, bytes) = download(&mut client, stored_id, 5).await?;
+    assert_eq!(bytes, b"stored whole");
+
+    let _response = client
+        .delete(stream::iter([
+            DeleteRequest { id },
+            DeleteRequest { id: empty_id },
+            DeleteRequest { id: stored_id },
+        ]))
+        .await?
+        .into_inner()
+        .try_collect::<Vec<_>>()
+        .await?;
+    let response = client
+        .download(stream::iter([DownloadRequest {
+            id,
+            chunk_size: None,
+        }]))
+        .await?
+        .into_inner()
+        .try_collect::<Vec<_>>()
+        .await;
+    drop(client);
+    assert!(response.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn test_upload_larger_than_message_limit() -> Result<()> {
+    let mut client = blob_client::<_, super::Backend>(BLOB_STORE_LOC.clone()).await?;
+
+    // tonic limits messages to 4 MiB by default.
+    let data = (0..5 << 20).map(|i: u32| i as u8).collect::<Vec<_>>();
+    let responses = client
+        .upload(stream::iter(upload_requests(&data, 1 << 20, None)))
+        .await?
+        .into_inner()
+        .try_collect::<Vec<_>>()
+        .await?;
+    let [UploadResponse { id, size, .. }] = responses[..] else {
+        bail!("unexpected responses {responses:?}");
+    };
+    assert_eq!(size, data.len() as u64);
+
+    let (info, bytes) = download(&mut client, id, 1 << 20).await?;
+    let _response = client
+        .delete(stream::iter([DeleteRequest { id }]))
+        .await?
+        .into_inner()
+        .try_collect::<Vec<_>>()
+        .await?;
+    drop(client);
+    assert_eq!(info.size, data.len() as u64);
+    assert!(bytes == data);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn test_upload_out_of_order() -> Result<()> {
+    let mut client = blob_client::<_, super::Backend>(BLOB_STORE_LOC.clone()).await?;
+
+    let chunk = |offset: u64| UploadRequest {
+        part: Some(UploadPart::Chunk(BlobChunk {
+            offset,
+            bytes: b"abc".to_vec(),
+        })),
+    };
+    let response = client
+        .upload(stream::iter([
+            chunk(0),
+            chunk(4),
+            UploadRequest {
+                part: Some(UploadPart::Finalize(Finalize {
+                    metadata: None,
+                    attributes: None,
+                    compression: Compression::None.into(),
+                })),
+            },
+        ]))
+        .await?
+        .into_inner()
+        .try_collect::<Vec<_>>()
+        .await;
+    drop(client);
+    assert_eq!(
+        response.map_err(|status| status.code()),
+        Err(tonic::Code::InvalidArgument)
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn test_list() -> Result<()> {
+    let mut client = blob_client::<_, super::Backend>(BLOB_STORE_LOC.clone()).await?;
+
+    let mut ids = Vec::new();
+    for (bytes, metadata) in [
+        (&b"abc"[..], "test_list:thumbnail"),
+        (b"abcdef", "test_list:image"),
+        (b"a", "test_list:thumbnail"),
+    ] {
+        let id = insert_one(
+            &mut client,
+            StoreRequest {
+                bytes: bytes.to_vec(),
+                metadata: Some(metadata.to_owned()),
+                attributes: None,
+                compression: Compression::None.into(),
+                id: None,
+                idempotency_key: None,
+            },
+        )
+        .await?;
+        ids.push((id, metadata, bytes.len() as u64));
+    }
+    ids.sort_unstable();
+    let expected = |filter: &dyn Fn(u64, &str) -> bool| {
+        ids.iter()
+            .filter(|(id, metadata, _)| filter(*id, metadata))
+            .map(|&(id, metadata, size)| ListResponse {
+                id,
+                metadata: Some(metadata.to_owned()),
+                size,
+                attributes: None,
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let mut list = async |request| -> Result<Vec<ListResponse>> {
+        Ok(client
+            .list(request)
+            .await?
+            .into_inner()
+            .try_collect::<Vec<_>>()
+            .await?)
+    };
+    let by_prefix = list(ListRequest {
+        metadata_prefix: Some("test_list:".to_owned()),
+        ..Default::default()
+    })
+    .await?;
+    let by_metadata = list(ListRequest {
+        metadata: Some("test_list:thumbnail".to_owned()),
+        ..Default::default()
+    })
+    .await?;
+    let by_range = list(ListRequest {
+        metadata_prefix: Some("test_list:".to_owned()),
+        start_id: Some(ids[1].0),
+        end_id: Some(ids[2].0),
+        ..Default::default()
+    })
+    .await?;
+    let limited = list(ListRequest {
+        metadata_prefix: Some("test_list:".to_owned()),
+        limit: Some(2),
+        ..Default::default()
+    })
+    .await?;
+
+    let _response = client
+        .delete(stream::iter(
+            ids.clone()
+                .into_iter()
+                .map(|(id, _, _)| DeleteRequest { id }),
+        ))
+        .await?
+        .into_inner()
+        .try_collect::<Vec<_>>()
+        .await?;
+    drop(client);
+
+    assert_eq!(by_prefix, expected(&|_, _| true));
+    assert_eq!(
+        by_metadata,
+        expected(&|_, metadata| metadata == "test_list:thumbnail")
+    );
+    assert_eq!(by_range, expected(&|id, _| id == ids[1].0));
+    assert_eq!(limited, expected(&|id, _| id != ids[2].0));
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn test_list_across_pages() -> Result<()> {
+    let mut client = blob_client::<_, super::Backend>(BLOB_STORE_LOC.clone()).await?;
+
+    // Listings are collected in pages of a few hundred BLOBs.
+    let requests = (0..600).map(|index| StoreRequest {
+        bytes: vec![0; index % 3],
+        metadata: Some(format!("test_list_across_pages:{}", index % 2)),
+        attributes: None,
+        compression: Compression::None.into(),
+        id: None,
+        idempotency_key: None,
+    });
+    let mut ids = client
+        .store(stream::iter(requests))
+        .await?
+        .into_inner()
+        .map_ok(|response| response.id)
+        .try_collect::<Vec<_>>()
+        .await?;
+    ids.sort_unstable();
+
+    let mut list = async |request| -> Result<Vec<u64>> {
+        Ok(client
+            .list(request)
+            .await?
+            .into_inner()
+            .map_ok(|response| response.id)
+            .try_collect::<Vec<_>>()
+            .await?)
+    };
+    let all = list(ListRequest {
+        metadata_prefix: Some("test_list_across_pages:".to_owned()),
+        ..Default::default()
+    })
+    .await?;
+    let limited = list(ListRequest {
+        metadata_prefix: Some("test_list_across_pages:".to_owned()),
+        limit: Some(300),
+        ..Default::default()
+    })
+    .await?;
+    let filtered = list(ListRequest {
+        metadata: Some("test_list_across_pages:1".to_owned()),
+        ..Default::default()
+    })
+    .await?;
+
+    let _response = client
+        .delete(stream::iter(
+            ids.clone().into_iter().map(|id| DeleteRequest { id }),
+        ))
+        .await?
+        .into_inner()
+        .try_collect::<Vec<_>>()
+        .await?;
+    drop(client);
+
+    assert_eq!(all, ids);
+    assert_eq!(limited, ids[..300]);
+    assert_eq!(filtered.len(), 300);
+    assert!(filtered.windows(2).all(|pair| pair[0] < pair[1]));
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn test_attributes() -> Result<()> {
+    let mut client = blob_client::<_, super::Backend>(BLOB_STORE_LOC.clone()).await?;
+
+    let tags = Kind::ListValue(ListValue {
+        values: vec![Value {
+            kind: Some(Kind::StringValue("cat".to_owned())),
+        }],
+    });
+    let id = insert_one(
+        &mut client,
+        StoreRequest {
+            bytes: b"abcdef".to_vec(),
+            metadata: None,
+            attributes: Some(attributes([
+                ("kind", Kind::StringValue("test_attributes".to_owned())),
+                ("width", Kind::NumberValue(64.)),
+                ("tags", tags.clone()),
+            ])),
+            compression: Compression::None.into(),
+            id: None,
+            idempotency_key: None,
+        },
+    )
+    .await?;
+
+    let get = |client: &mut BlobClient<Channel>| {
+        let mut client = client.clone();
+        async move {
+            let response = client
+                .get(stream::iter([GetRequest {
+                    id,
+                    offset: None,
+                    length: None,
+                }]))
+                .await?
+                .into_inner()
+                .try_collect::<Vec<_>>()
+                .await?;
+            Ok::<_, anyhow::Error>(response)
+        }
+    };
+    let stored = get(&mut client).await?;
+
+    let _response = client
+        .update(stream::iter([UpdateRequest {
+            id,
+            bytes: None,
+            should_update_metadata: false,
+            metadata: None,
+            attributes: Some(attributes([("width", Kind::NumberValue(128.))])),
+            remove_attributes: vec!["tags".to_owned(), "height".to_owned()],
+            compression: Compression::None.into(),
+        }]))
+        .await?
+        .into_inner()
+        .try_collect::<Vec<_>>()
+        .await?;
+    let updated = get(&mut client).await?;
+
+    let mut list = async |filter| -> Result<Vec<u64>> {
+        Ok(client
+            .list(ListRequest {
+                attributes: Some(filter),
+                ..Default::default()
+            })
+            .await?
+            .into_inner()
+            .map_ok(|response| response.id)
+            .try_collect::<Vec<_>>()
+            .await?)
+    };
+    let by_new_width = list(attributes([
+        ("kind", Kind::StringValue("test_attributes".to_owned())),
+        ("width", Kind::NumberValue(128.)),
+    ]))
+    .await?;
+    let by_old_width = list(attributes([
+        ("kind", Kind::StringValue("test_attributes".to_owned())),
+        ("width", Kind::NumberValue(64.)),
+    ]))
+    .await?;
+
+    let _response = client
+        .delete(stream::iter([DeleteRequest { id }]))
+        .await?
+        .into_inner()
+        .try_collect::<Vec<_>>()
+        .await?;
+    drop(client);
+
+    assert_eq!(
+        stored,
+        [GetResponse {
+            bytes: b"abcdef".to_vec(),
+            metadata: None,
+            attributes: Some(attributes([
+                ("kind", Kind::StringValue("test_attributes".to_owned())),
+                ("width", Kind::NumberValue(64.)),
+                ("tags", tags),
+            ])),
+            digest: Sha256::digest(b"abcdef").to_vec(),
+        }]
+    );
+    assert_eq!(
+        updated,
+        [GetResponse {
+            bytes: b"abcdef".to_vec(),
+            metadata: None,
+            attributes: Some(attributes([
+                ("kind", Kind::StringValue("test_attributes".to_owned())),
+                ("width", Kind::NumberValue(128.)),
+            ])),
+            digest: Sha256::digest(b"abcdef").to_vec(),
+        }]
+    );
+    assert_eq!(by_new_width, [id]);
+    assert_eq!(by_old_width, []);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn test_copy() -> Result<()> {
+    let mut client = blob_client::<_, super::Backend>(BLOB_STORE_LOC.clone()).await?;
+
+    let id = insert_one(
+        &mut client,
+        StoreRequest {
+            bytes: b"original".to_vec(),
+            metadata: Some("test_copy".to_owned()),
+            attributes: Some(attributes([("width", Kind::NumberValue(64.))])),
+            compression: Compression::None.into(),
+            id: None,
+            idempotency_key: None,
+        },
+    )
+    .await?;
+    let copies = client
+        .copy(stream::iter([
+            CopyRequest {
+                id,
+                should_update_metadata: false,
+                metadata: None,
+            },
+            CopyRequest {
+                id,
+                should_update_metadata: true,
+                metadata: Some("renamed".to_owned()),
+            },
+        ]))
+        .await?
+        .into_inner()
+        .map_ok(|response| response.id)
+        .try_collect::<Vec<_>>()
+        .await?;
+    let [same_id, renamed_id] = copies[..] else {
+        bail!("unexpected responses {copies:?}");
+    };
+    assert!(same_id != id && renamed_id != id && same_id != renamed_id);
+
+    // Changing the original does not change its copies.
+    let _response = client
+        .update(stream::iter([UpdateRequest {
+            id,
+            bytes: Some(b"changed".to_vec()),
+            should_update_metadata: false,
+            metadata: None,
+            attributes: None,
+            remove_attributes: vec!["width".to_owned()],
+            compression: Compression::None.into(),
+        }]))
+        .await?
+        .into_inner()
+        .try_collect::<Vec<_>>()
+        .await?;
+    let response = client
+        .get(stream::iter([id, same_id, renamed_id].map(|id| {
+            GetRequest {
+                id,
+                offset: None,
+                length: None,
+            }
+        })))
+        .await?
+        .into_inner();
+    let width = Some(attributes([("width", Kind::NumberValue(64.))]));
+    assert_stream_eq(
+        response,
+        [
+            GetResponse {
+                bytes: b"changed".to_vec(),
+                metadata: Some("test_copy".to_owned()),
+                attributes: None,
+                digest: Sha256::digest(b"changed").to_vec(),
+            },
+            GetResponse {
+                bytes: b"original".to_vec(),
+                metadata: Some("test_copy".to_owned()),
+                attributes: width.clone(),
+                digest: Sha256::digest(b"original").to_vec(),
+            },
+            GetResponse {
+                bytes: b"original".to_vec(),
+                metadata: Some("renamed".to_owned()),
+                attributes: width,
+                digest: Sha256::digest(b"original").to_vec(),
+            },
+        ],
+    )
+    .await;
+
+    let _response = client
+        .delete(stream::iter(
+            [id, same_id, renamed_id].map(|id| DeleteRequest { id }),
+        ))
+        .await?
+        .into_inner()
+        .try_collect::<Vec<_>>()
+        .await?;
+
+    // The BLOB no longer exists.
+    let response = client
+        .copy(stream::iter([CopyRequest {
+            id,
+            should_update_metadata: false,
+            metadata: None,
+        }]))
+        .await?
+        .into_inner()
+        .try_collect::<Vec<_>>()
+        .await;
+    assert_eq!(
+        response.map_err(|status| status.code()),
+        Err(tonic::Code::NotFound)
+    );
+    drop(client);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn test_append() -> Result<()> {
+    let mut client = blob_client::<_, super::Backend>(BLOB_STORE_LOC.clone()).await?;
+
+    let id = insert_one(
+        &mut client,
+        StoreRequest {
+            bytes: b"line 1\n".to_vec(),
+            metadata: Some("test_append".to_owned()),
+            attributes: None,
+            compression: Compression::None.into(),
+            id: None,
+            idempotency_key: None,
+        },
+    )
+    .await?;
+    // Another BLOB sharing the data is not changed by appending.
+    let shared_id = insert_one(
+        &mut client,
+        StoreRequest {
+            bytes: b"line 1\n".to_vec(),
+            metadata: None,
+            attributes: None,
+            compression: Compression::None.into(),
+            id: None,
+            idempotency_key: None,
+        },
+    )
+    .await?;
+    let response = client
+        .append(stream::iter([
+            AppendRequest {
+                id,
+                bytes: b"line 2\n".to_vec(),
+                compression: Compression::None.into(),
+            },
+            AppendRequest {
+                id,
+                bytes: b"line 3\n".to_vec(),
+                compression: Compression::Zstd.into(),
+            },
+        ]))
+        .await?
+        .into_inner();
+    assert_stream_eq(
+        response,
+        [
+            AppendResponse { id, size: 14 },
+            AppendResponse { id, size: 21 },
+        ],
+    )
+    .await;
+
+    let response = client
+        .get(stream::iter([id, shared_id].map(|id| GetRequest {
+            id,
+            offset: None,
+            length: None,
+        })))
+        .await?
+        .into_inner();
+    assert_stream_eq(
+        response,
+        [
+            GetResponse {
+                bytes: b"line 1\nline 2\nline 3\n".to_vec(),
+                metadata: Some("test_append".to_owned()),
+                attributes: None,
+                digest: Sha256::digest(b"line 1\nline 2\nline 3\n").to_vec(),
+            },
+            GetResponse {
+                bytes: b"line 1\n".to_vec(),
+                metadata: None,
+                attributes: None,
+                digest: Sha256::digest(b"line 1\n").to_vec(),
+            },
+        ],
+    )
+    .await;
+
+    let _response = client
+        .delete(stream::iter([id, shared_id].map(|id| DeleteRequest { id })))
+        .await?
+        .into_inner()
+        .try_collect::<Vec<_>>()
+        .await?;
+
+    // The BLOB no longer exists.
+    let response = client
+        .append(stream::iter([AppendRequest {
+            id,
+            bytes: b"lost".to_vec(),
+            compression: Compression::None.into(),
+        }]))
+        .await?
+        .into_inner()
+        .try_collect::<Vec<_>>()
+        .await;
+    assert_eq!(
+        response.map_err(|status| status.code()),
+        Err(tonic::Code::NotFound)
+    );
+    drop(client);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn test_append_in_chunks() -> Result<()> {
+    let store = BlobStore::<super::Backend>::at_location(BLOB_STORE_LOC.clone())?
+        .with_external_threshold(2_000_000);
+    let mut client = blob_client_for_store(store).await?;
+
+    let data = (0..=250).cycle().take(2_300_000).collect::<Vec<u8>>();
+    let id = insert_one(
+        &mut client,
+        StoreRequest {
+            bytes: data[..1_500_000].to_vec(),
+            metadata: None,
+            attributes: None,
+            compression: Compression::None.into(),
+            id: None,
+            idempotency_key: None,
+        },
+    )
+    .await?;
+    // The first append leaves the content shared with the copy unchanged, the next one extends
+    // content no other BLOB refers to, and the last two move the content to a file and extend it.
+    let copies = client
+        .copy(stream::iter([CopyRequest {
+            id,
+            should_update_metadata: false,
+            metadata: None,
+        }]))
+        .await?
+        .into_inner()
+        .map_ok(|response| response.id)
+        .try_collect::<Vec<_>>()
+        .await?;
+    let [copy_id] = copies[..] else {
+        bail!("unexpected responses {copies:?}");
+    };
+    let ends = [1_700_000, 1_900_000, 2_200_000, 2_300_000];
+    let mut start = 1_500_000;
+    let requests = ends.map(|end| {
+        let bytes = data[start..end].to_vec();
+        start = end;
+        AppendRequest {
+            id,
+            bytes,
+            compression: Compression::None.into(),
+        }
+    });
+    let response = client.append(stream::iter(requests)).await?.into_inner();
+    assert_stream_eq(
+        response,
+        ends.map(|end| AppendResponse {
+            id,
+            size: end as u64,
+        }),
+    )
+    .await;
+
+    let response = client
+        .get(stream::iter([id, copy_id].map(|id| GetRequest {
+            id,
+            offset: None,
+            length: None,
+        })))
+        .await?
+        .into_inner();
+    assert_stream_eq(
+        response,
+        [
+            GetResponse {
+                bytes: data.clone(),
+                metadata: None,
+                attributes: None,
+                digest: Sha256::digest(&data).to_vec(),
+            },
+            GetResponse {
+                bytes: data[..1_500_000].to_vec(),
+                metadata: None,
+                attributes: None,
+                digest: Sha256::digest(&data[..1_500_000]).to_vec(),
+            },
+        ],
+    )
+    .await;
+
+    let _response = client
+        .delete(stream::iter([id, copy_id].map(|id| DeleteRequest { id })))
+        .await?
+        .into_inner()
+        .try_collect::<Vec<_>>()
+        .await?;
+    drop(client);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn test_verify() -> Result<()> {
+    let store = BlobStore::<super::Backend>::at_location(BLOB_STORE_LOC.clone())?
+        .with_external_threshold(64);
+    let mut client = blob_client_for_store(store).await?;
+
+    let data = b"test_verify ".repeat(100);
+    let id = insert_one(
+        &mut client,
+        StoreRequest {
+            bytes: data.clone(),
+            metadata: None,
+            attributes: None,
+            compression: Compression::None.into(),
+            id: None,
+            idempotency_key: None,
+        },
+    )
+    .await?;
+    let corrupted = client
+        .verify(VerifyRequest {})
+        .await?
+        .into_inner()
+        .map_ok(|response| response.id)
+        .try_collect::<Vec<_>>()
+        .await?;
+    assert!(!corrupted.contains(&id));
+
+    // Corrupt the file storing the data without changing its size.
+    let mut corrupted_file = false;
+    for entry in std::fs::read_dir(format!("{}-blobs", super::BLOB_PATH))? {
+        let path = entry?.path();
+        if std::fs::read(&path)? == data {
+            std::fs::write(&path, b"corrupted!! ".repeat(100))?;
+            corrupted_file = true;
+        }
+    }
+    assert!(corrupted_file);
+
+    let response = client
+        .get(stream::iter([GetRequest {
+            id,
+            offset: None,
+            length: None,
+        }]))
+        .await?
+        .into_inner()
+        .try_collect::<Vec<_>>()
+        .await;
+    assert_eq!(
+        response.map_err(|status| status.code()),
+        Err(tonic::Code::DataLoss)
+    );
+    // The corruption is only detected once every chunk has been sent.
+    let response = download(&mut client, id, 256).await;
+    assert_eq!(
+        response.map_err(|err| err.downcast_ref::<tonic::Status>().map(tonic::Status::code)),
+        Err(Some(tonic::Code::DataLoss))
+    );
+    let corrupted = client
+        .verify(VerifyRequest {})
+        .await?
+        .into_inner()
+        .try_collect::<Vec<_>>()
+        .await?;
+    assert!(corrupted.iter().any(|response| response.id == id));
+
+    let _response = client
+        .delete(stream::iter([DeleteRequest { id }]))
+        .await?
+        .into_inner()
+        .try_collect::<Vec<_>>()
+        .await?;
+    drop(client);
+
+    Ok(())
+}
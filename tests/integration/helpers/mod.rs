@@ -1,14 +1,17 @@
+use futures::{Stream, StreamExt as _};
 use std::fmt::Debug;
-use tonic::Streaming;
+use tonic::Status;
 
-pub(crate) async fn assert_stream_eq<S, X>(mut stream: Streaming<S>, expected: X)
-where
+pub(crate) async fn assert_stream_eq<S, X>(
+    mut stream: impl Stream<Item = Result<S, Status>> + Unpin,
+    expected: X,
+) where
     S: PartialEq<X::Item> + Debug,
     X: IntoIterator<Item: Debug, IntoIter: Send> + Send,
 {
     let mut expected = expected.into_iter();
 
-    while let Ok(Some(stream_item)) = stream.message().await {
+    while let Some(Ok(stream_item)) = stream.next().await {
         match (stream_item, expected.next()) {
             (stream_item, Some(expected_item)) => {
                 assert!(stream_item == expected_item);
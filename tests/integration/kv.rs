@@ -1,34 +1,65 @@
 use crate::helpers::assert_stream_eq;
 use anyhow::Result;
+use buffdb::client::kv::{KvClient, NAMESPACE_METADATA_KEY};
+use buffdb::proto::kv::set_request::Expiration;
+use buffdb::proto::kv::transaction_request::Operation;
+use buffdb::proto::kv::transaction_response::Outcome;
+use buffdb::proto::kv::watch_request::Target;
+use buffdb::proto::kv::ChangeKind;
 use buffdb::proto::kv::{
-    DeleteRequest, DeleteResponse, EqRequest, GetRequest, GetResponse, NotEqRequest, SetRequest,
-    SetResponse,
+    Commit, CompareAndSwapFailure, CompareAndSwapRequest, CompareAndSwapResponse,
+    CreateNamespaceRequest, DeleteRequest, DeleteResponse, DropNamespaceRequest, EqRequest,
+    GetRequest, GetResponse, HistoryRequest, IncrementRequest, IncrementResponse,
+    ListNamespacesRequest, NotEqRequest, Rollback, ScanRequest, ScanResponse, SetRequest,
+    SetResponse, TransactionRequest, TransactionResponse, WatchRequest, WatchResponse,
 };
 use buffdb::transitive::kv_client;
 use buffdb::Location;
-use futures::{stream, StreamExt as _};
+use futures::{stream, StreamExt as _, TryStreamExt as _};
+use prost::Message as _;
 use serial_test::serial;
 use std::sync::LazyLock;
+use tonic::transport::Channel;
+use tonic::Code;
 
 static KV_STORE_LOC: LazyLock<Location> = LazyLock::new(|| Location::OnDisk {
     path: super::KV_PATH.into(),
 });
 
+/// The version of the most recent write to the key.
+async fn latest_version(client: &mut KvClient<Channel>, key: &str) -> Result<u64> {
+    let writes = client
+        .history(stream::iter([HistoryRequest {
+            key: key.to_owned(),
+        }]))
+        .await?
+        .into_inner()
+        .try_collect::<Vec<_>>()
+        .await?;
+    Ok(writes.last().map_or(0, |write| write.version))
+}
+
 #[tokio::test]
 #[serial]
 async fn test_get() -> Result<()> {
     let mut client = kv_client::<_, super::Backend>(KV_STORE_LOC.clone()).await?;
 
-    let _response = client
+    let set = client
         .set(stream::iter([SetRequest {
             key: "key_get".to_owned(),
-            value: "value_get".to_owned(),
+            value: b"value_get".to_vec(),
+            expiration: None,
         }]))
+        .await?
+        .into_inner()
+        .try_collect::<Vec<_>>()
         .await?;
 
     let stream = client
         .get(stream::iter([GetRequest {
             key: "key_get".to_owned(),
+            allow_missing: false,
+            version: None,
         }]))
         .await?
         .into_inner();
@@ -36,7 +67,8 @@ async fn test_get() -> Result<()> {
     assert_stream_eq(
         stream,
         [GetResponse {
-            value: "value_get".to_owned(),
+            value: Some(b"value_get".to_vec()),
+            version: set[0].version,
         }],
     )
     .await;
@@ -49,21 +81,39 @@ async fn test_get() -> Result<()> {
 async fn test_set() -> Result<()> {
     let mut client = kv_client::<_, super::Backend>(KV_STORE_LOC.clone()).await?;
 
-    let stream = client
-        .set(stream::iter([SetRequest {
-            key: "key_set".to_owned(),
-            value: "value_set".to_owned(),
-        }]))
+    let set_request = |value: &[u8]| SetRequest {
+        key: "key_set".to_owned(),
+        value: value.to_vec(),
+        expiration: None,
+    };
+    let set = client
+        .set(stream::iter([
+            set_request(b"value_set"),
+            set_request(b"value_set"),
+        ]))
         .await?
-        .into_inner();
+        .into_inner()
+        .try_collect::<Vec<_>>()
+        .await?;
     drop(client);
-    assert_stream_eq(
-        stream,
-        [SetResponse {
-            key: "key_set".to_owned(),
-        }],
-    )
-    .await;
+    let [first, second] = &set[..] else {
+        panic!("unexpected responses {set:?}");
+    };
+    // Each write is given a later version than the one before it.
+    assert!(first.version < second.version);
+    assert_eq!(
+        set,
+        [
+            SetResponse {
+                key: "key_set".to_owned(),
+                version: first.version,
+            },
+            SetResponse {
+                key: "key_set".to_owned(),
+                version: second.version,
+            },
+        ]
+    );
 
     Ok(())
 }
@@ -76,7 +126,8 @@ async fn test_delete() -> Result<()> {
     let _response = client
         .set(stream::iter([SetRequest {
             key: "key_delete".to_owned(),
-            value: "value_delete".to_owned(),
+            value: b"value_delete".to_vec(),
+            expiration: None,
         }]))
         .await?;
 
@@ -97,6 +148,8 @@ async fn test_delete() -> Result<()> {
     let mut response = client
         .get(stream::iter([GetRequest {
             key: "key_delete".to_owned(),
+            allow_missing: false,
+            version: None,
         }]))
         .await?
         .into_inner();
@@ -116,7 +169,8 @@ async fn test_eq() -> Result<()> {
         let _response = client
             .set(stream::iter([SetRequest {
                 key: key.to_owned(),
-                value: "value_eq".to_owned(),
+                value: b"value_eq".to_vec(),
+                expiration: None,
             }]))
             .await?;
     }
@@ -143,7 +197,8 @@ async fn test_eq() -> Result<()> {
     let _response = client
         .set(stream::iter([SetRequest {
             key: "key_e_eq".to_owned(),
-            value: "value2_eq".to_owned(),
+            value: b"value2_eq".to_vec(),
+            expiration: None,
         }]))
         .await?;
 
@@ -185,7 +240,8 @@ async fn test_not_eq() -> Result<()> {
         let _response = client
             .set(stream::iter([SetRequest {
                 key: key.to_owned(),
-                value: format!("value{idx}_neq"),
+                value: format!("value{idx}_neq").into_bytes(),
+                expiration: None,
             }]))
             .await?;
     }
@@ -212,7 +268,8 @@ async fn test_not_eq() -> Result<()> {
     let _response = client
         .set(stream::iter([SetRequest {
             key: "key_e_neq".to_owned(),
-            value: "value2_neq".to_owned(),
+            value: b"value2_neq".to_vec(),
+            expiration: None,
         }]))
         .await?;
 
@@ -242,6 +299,80 @@ async fn test_not_eq() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+#[serial]
+async fn test_get_not_found() -> Result<()> {
+    let mut client = kv_client::<_, super::Backend>(KV_STORE_LOC.clone()).await?;
+    let mut stream = client
+        .get(stream::iter([GetRequest {
+            key: "this-key-should-not-exist".to_owned(),
+            allow_missing: false,
+            version: None,
+        }]))
+        .await?
+        .into_inner();
+    drop(client);
+    let status = stream
+        .next()
+        .await
+        .expect("stream should not be empty")
+        .expect_err("key should not be found");
+    assert_eq!(status.code(), Code::NotFound);
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn test_get_allow_missing() -> Result<()> {
+    let mut client = kv_client::<_, super::Backend>(KV_STORE_LOC.clone()).await?;
+
+    let set = client
+        .set(stream::iter([SetRequest {
+            key: "key_get_allow_missing".to_owned(),
+            value: b"value_get_allow_missing".to_vec(),
+            expiration: None,
+        }]))
+        .await?
+        .into_inner()
+        .try_collect::<Vec<_>>()
+        .await?;
+
+    let request = |key: &str| GetRequest {
+        key: key.to_owned(),
+        allow_missing: true,
+        version: None,
+    };
+    let stream = client
+        .get(stream::iter([
+            request("this-key-should-not-exist"),
+            request("key_get_allow_missing"),
+            request("this-key-should-not-exist"),
+        ]))
+        .await?
+        .into_inner();
+    drop(client);
+    assert_stream_eq(
+        stream,
+        [
+            GetResponse {
+                value: None,
+                version: 0,
+            },
+            GetResponse {
+                value: Some(b"value_get_allow_missing".to_vec()),
+                version: set[0].version,
+            },
+            GetResponse {
+                value: None,
+                version: 0,
+            },
+        ],
+    )
+    .await;
+
+    Ok(())
+}
+
 #[tokio::test]
 #[serial]
 async fn test_eq_not_found() -> Result<()> {
@@ -269,3 +400,1001 @@ async fn test_not_eq_not_found() -> Result<()> {
     assert!(res.is_err());
     Ok(())
 }
+
+#[tokio::test]
+#[serial]
+async fn test_scan() -> Result<()> {
+    let mut client = kv_client::<_, super::Backend>(KV_STORE_LOC.clone()).await?;
+
+    let _response = client
+        .set(stream::iter(
+            ["scan:a", "scan:b", "scan:c", "scan;", "scam"].map(|key| SetRequest {
+                key: key.to_owned(),
+                value: format!("value_{key}").into_bytes(),
+                expiration: None,
+            }),
+        ))
+        .await?
+        .into_inner()
+        .collect::<Vec<_>>()
+        .await;
+
+    let pair = |key: &str| ScanResponse {
+        key: key.to_owned(),
+        value: format!("value_{key}").into_bytes(),
+    };
+
+    let stream = client
+        .scan(stream::iter([
+            ScanRequest {
+                prefix: Some("scan:".to_owned()),
+                start: None,
+                end: None,
+                limit: None,
+                reverse: false,
+            },
+            ScanRequest {
+                prefix: Some("scan:".to_owned()),
+                start: None,
+                end: None,
+                limit: Some(2),
+                reverse: true,
+            },
+            ScanRequest {
+                prefix: None,
+                start: Some("scan:b".to_owned()),
+                end: Some("scan;".to_owned()),
+                limit: None,
+                reverse: false,
+            },
+        ]))
+        .await?
+        .into_inner();
+    drop(client);
+    assert_stream_eq(
+        stream,
+        [
+            pair("scan:a"),
+            pair("scan:b"),
+            pair("scan:c"),
+            pair("scan:c"),
+            pair("scan:b"),
+            pair("scan:b"),
+            pair("scan:c"),
+        ],
+    )
+    .await;
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn test_binary_value() -> Result<()> {
+    let mut client = kv_client::<_, super::Backend>(KV_STORE_LOC.clone()).await?;
+
+    let value = vec![0xff, 0x00, 0xfe, b'a'];
+    let set = client
+        .set(stream::iter([SetRequest {
+            key: "binary:a".to_owned(),
+            value: value.clone(),
+            expiration: None,
+        }]))
+        .await?
+        .into_inner()
+        .try_collect::<Vec<_>>()
+        .await?;
+
+    let stream = client
+        .get(stream::iter([GetRequest {
+            key: "binary:a".to_owned(),
+            allow_missing: false,
+            version: None,
+        }]))
+        .await?
+        .into_inner();
+    assert_stream_eq(
+        stream,
+        [GetResponse {
+            value: Some(value.clone()),
+            version: set[0].version,
+        }],
+    )
+    .await;
+
+    let stream = client
+        .scan(stream::iter([ScanRequest {
+            prefix: Some("binary:".to_owned()),
+            start: None,
+            end: None,
+            limit: None,
+            reverse: false,
+        }]))
+        .await?
+        .into_inner();
+    drop(client);
+    assert_stream_eq(
+        stream,
+        [ScanResponse {
+            key: "binary:a".to_owned(),
+            value,
+        }],
+    )
+    .await;
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn test_expiration() -> Result<()> {
+    let mut client = kv_client::<_, super::Backend>(KV_STORE_LOC.clone()).await?;
+
+    let set = client
+        .set(stream::iter([
+            SetRequest {
+                key: "ttl:expired".to_owned(),
+                value: b"value_expired".to_vec(),
+                expiration: Some(Expiration::ExpiresAt(prost_types::Timestamp {
+                    seconds: 1,
+                    nanos: 0,
+                })),
+            },
+            SetRequest {
+                key: "ttl:not_expired".to_owned(),
+                value: b"value_not_expired".to_vec(),
+                expiration: Some(Expiration::Ttl(prost_types::Duration {
+                    seconds: 60 * 60,
+                    nanos: 0,
+                })),
+            },
+        ]))
+        .await?
+        .into_inner()
+        .try_collect::<Vec<_>>()
+        .await?;
+
+    let stream = client
+        .get(stream::iter([GetRequest {
+            key: "ttl:not_expired".to_owned(),
+            allow_missing: false,
+            version: None,
+        }]))
+        .await?
+        .into_inner();
+    assert_stream_eq(
+        stream,
+        [GetResponse {
+            value: Some(b"value_not_expired".to_vec()),
+            version: set[1].version,
+        }],
+    )
+    .await;
+
+    let mut stream = client
+        .get(stream::iter([GetRequest {
+            key: "ttl:expired".to_owned(),
+            allow_missing: false,
+            version: None,
+        }]))
+        .await?
+        .into_inner();
+    assert!(matches!(stream.next().await, Some(Err(_))));
+
+    let stream = client
+        .scan(stream::iter([ScanRequest {
+            prefix: Some("ttl:".to_owned()),
+            start: None,
+            end: None,
+            limit: None,
+            reverse: false,
+        }]))
+        .await?
+        .into_inner();
+    assert_stream_eq(
+        stream,
+        [ScanResponse {
+            key: "ttl:not_expired".to_owned(),
+            value: b"value_not_expired".to_vec(),
+        }],
+    )
+    .await;
+
+    let res = client
+        .set(stream::iter([SetRequest {
+            key: "negative_ttl".to_owned(),
+            value: b"value".to_vec(),
+            expiration: Some(Expiration::Ttl(prost_types::Duration {
+                seconds: -1,
+                nanos: 0,
+            })),
+        }]))
+        .await?
+        .into_inner()
+        .next()
+        .await;
+    drop(client);
+    assert!(matches!(res, Some(Err(_))));
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn test_compare_and_swap() -> Result<()> {
+    let mut client = kv_client::<_, super::Backend>(KV_STORE_LOC.clone()).await?;
+
+    let _response = client
+        .delete(stream::iter([DeleteRequest {
+            key: "cas_absent".to_owned(),
+        }]))
+        .await?
+        .into_inner()
+        .collect::<Vec<_>>()
+        .await;
+    let _response = client
+        .set(stream::iter([SetRequest {
+            key: "cas".to_owned(),
+            value: b"value_a".to_vec(),
+            expiration: None,
+        }]))
+        .await?
+        .into_inner()
+        .collect::<Vec<_>>()
+        .await;
+
+    let stream = client
+        .compare_and_swap(stream::iter([
+            CompareAndSwapRequest {
+                key: "cas".to_owned(),
+                expected_value: Some(b"value_a".to_vec()),
+                new_value: b"value_b".to_vec(),
+                expiration: None,
+            },
+            CompareAndSwapRequest {
+                key: "cas_absent".to_owned(),
+                expected_value: None,
+                new_value: b"value_c".to_vec(),
+                expiration: None,
+            },
+        ]))
+        .await?
+        .into_inner();
+    assert_stream_eq(
+        stream,
+        [
+            CompareAndSwapResponse {
+                key: "cas".to_owned(),
+            },
+            CompareAndSwapResponse {
+                key: "cas_absent".to_owned(),
+            },
+        ],
+    )
+    .await;
+
+    for (expected_value, current_value) in [
+        (Some("value_a"), "value_b"),
+        (None, "value_b"),
+        (Some("value_a"), "value_b"),
+    ] {
+        let status = client
+            .compare_and_swap(stream::iter([CompareAndSwapRequest {
+                key: "cas".to_owned(),
+                expected_value: expected_value.map(Into::into),
+                new_value: b"value_d".to_vec(),
+                expiration: None,
+            }]))
+            .await?
+            .into_inner()
+            .next()
+            .await
+            .expect("a response should be returned")
+            .expect_err("the swap should fail");
+        assert_eq!(status.code(), Code::FailedPrecondition);
+        assert_eq!(
+            CompareAndSwapFailure::decode(status.details())?,
+            CompareAndSwapFailure {
+                current_value: Some(current_value.into()),
+            }
+        );
+    }
+
+    let stream = client
+        .get(stream::iter([
+            GetRequest {
+                key: "cas".to_owned(),
+                allow_missing: false,
+                version: None,
+            },
+            GetRequest {
+                key: "cas_absent".to_owned(),
+                allow_missing: false,
+                version: None,
+            },
+        ]))
+        .await?
+        .into_inner();
+    let versions = [
+        latest_version(&mut client, "cas").await?,
+        latest_version(&mut client, "cas_absent").await?,
+    ];
+    drop(client);
+    assert_stream_eq(
+        stream,
+        [
+            GetResponse {
+                value: Some(b"value_b".to_vec()),
+                version: versions[0],
+            },
+            GetResponse {
+                value: Some(b"value_c".to_vec()),
+                version: versions[1],
+            },
+        ],
+    )
+    .await;
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn test_transaction() -> Result<()> {
+    let mut client = kv_client::<_, super::Backend>(KV_STORE_LOC.clone()).await?;
+
+    let set = |key: &str, value: &str| TransactionRequest {
+        operation: Some(Operation::Set(SetRequest {
+            key: key.to_owned(),
+            value: value.into(),
+            expiration: None,
+        })),
+    };
+    let get = |key: &str| TransactionRequest {
+        operation: Some(Operation::Get(GetRequest {
+            key: key.to_owned(),
+            allow_missing: false,
+            version: None,
+        })),
+    };
+    let set_response = |key: &str, version| TransactionResponse {
+        outcome: Some(Outcome::Set(SetResponse {
+            key: key.to_owned(),
+            version,
+        })),
+    };
+    let get_response = |value: &str, version| TransactionResponse {
+        outcome: Some(Outcome::Get(GetResponse {
+            value: Some(value.into()),
+            version,
+        })),
+    };
+    let commit = || TransactionRequest {
+        operation: Some(Operation::Commit(Commit::default())),
+    };
+    // Versions depend on every earlier write to the store, so they are taken from the response to
+    // the commit.
+    let commit_versions = |responses: &[TransactionResponse]| {
+        responses
+            .iter()
+            .find_map(|response| match &response.outcome {
+                Some(Outcome::Commit(Commit { versions })) => Some(versions.clone()),
+                _ => None,
+            })
+            .unwrap_or_default()
+    };
+    let commit_response = |versions| TransactionResponse {
+        outcome: Some(Outcome::Commit(Commit { versions })),
+    };
+
+    let responses = client
+        .transaction(stream::iter([
+            set("txn:from", "10"),
+            set("txn:to", "0"),
+            commit(),
+        ]))
+        .await?
+        .into_inner()
+        .try_collect::<Vec<_>>()
+        .await?;
+    let versions = commit_versions(&responses);
+    assert_eq!(
+        responses,
+        [
+            set_response("txn:from", 0),
+            set_response("txn:to", 0),
+            commit_response(versions.clone()),
+        ],
+    );
+    assert!(versions.len() == 2 && versions[0] < versions[1]);
+
+    // Writes are visible within the transaction before it is committed.
+    let responses = client
+        .transaction(stream::iter([
+            set("txn:from", "5"),
+            set("txn:to", "5"),
+            get("txn:to"),
+            commit(),
+        ]))
+        .await?
+        .into_inner()
+        .try_collect::<Vec<_>>()
+        .await?;
+    let committed_versions = commit_versions(&responses);
+    assert_eq!(
+        responses,
+        [
+            set_response("txn:from", 0),
+            set_response("txn:to", 0),
+            get_response("5", 0),
+            commit_response(committed_versions.clone()),
+        ],
+    );
+
+    // Neither an explicit rollback nor ending the stream early applies any writes.
+    let responses = client
+        .transaction(stream::iter([
+            set("txn:from", "0"),
+            TransactionRequest {
+                operation: Some(Operation::Rollback(Rollback {})),
+            },
+        ]))
+        .await?
+        .into_inner()
+        .try_collect::<Vec<_>>()
+        .await?;
+    assert_eq!(
+        responses,
+        [
+            set_response("txn:from", 0),
+            TransactionResponse {
+                outcome: Some(Outcome::Rollback(Rollback {})),
+            },
+        ],
+    );
+    let responses = client
+        .transaction(stream::iter([set("txn:to", "10")]))
+        .await?
+        .into_inner()
+        .try_collect::<Vec<_>>()
+        .await?;
+    assert_eq!(responses, [set_response("txn:to", 0)]);
+
+    // No operations are accepted once the transaction has ended.
+    let responses = client
+        .transaction(stream::iter([commit(), get("txn:from")]))
+        .await?
+        .into_inner()
+        .collect::<Vec<_>>()
+        .await;
+    assert!(matches!(responses.as_slice(), [Ok(_), Err(_)]));
+
+    // A commit fails if a key read by the transaction has since been written by another client.
+    let _responses = client
+        .delete(stream::iter([DeleteRequest {
+            key: "txn:read".to_owned(),
+        }]))
+        .await?
+        .into_inner()
+        .try_collect::<Vec<_>>()
+        .await?;
+    let (requests, receiver) = futures::channel::mpsc::unbounded();
+    let mut responses = client.clone().transaction(receiver).await?.into_inner();
+    requests.unbounded_send(TransactionRequest {
+        operation: Some(Operation::Get(GetRequest {
+            key: "txn:read".to_owned(),
+            allow_missing: true,
+            version: None,
+        })),
+    })?;
+    assert_eq!(
+        responses.message().await?,
+        Some(TransactionResponse {
+            outcome: Some(Outcome::Get(GetResponse {
+                value: None,
+                version: 0,
+            })),
+        }),
+    );
+    let _responses = client
+        .set(stream::iter([SetRequest {
+            key: "txn:read".to_owned(),
+            value: "1".into(),
+            expiration: None,
+        }]))
+        .await?
+        .into_inner()
+        .try_collect::<Vec<_>>()
+        .await?;
+    requests.unbounded_send(set("txn:to", "0"))?;
+    requests.unbounded_send(commit())?;
+    assert_eq!(responses.message().await?, Some(set_response("txn:to", 0)));
+    assert!(matches!(
+        responses.message().await,
+        Err(status) if status.code() == Code::Aborted
+    ));
+
+    let stream = client
+        .get(stream::iter([
+            GetRequest {
+                key: "txn:from".to_owned(),
+                allow_missing: false,
+                version: None,
+            },
+            GetRequest {
+                key: "txn:to".to_owned(),
+                allow_missing: false,
+                version: None,
+            },
+        ]))
+        .await?
+        .into_inner();
+    drop(client);
+    assert_stream_eq(
+        stream,
+        [
+            GetResponse {
+                value: Some(b"5".to_vec()),
+                version: committed_versions[0],
+            },
+            GetResponse {
+                value: Some(b"5".to_vec()),
+                version: committed_versions[1],
+            },
+        ],
+    )
+    .await;
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn test_watch() -> Result<()> {
+    let mut client = kv_client::<_, super::Backend>(KV_STORE_LOC.clone()).await?;
+
+    let _response = client
+        .delete(stream::iter([DeleteRequest {
+            key: "watch:a".to_owned(),
+        }]))
+        .await?
+        .into_inner()
+        .collect::<Vec<_>>()
+        .await;
+
+    let mut changes = client
+        .watch(WatchRequest {
+            target: Some(Target::Prefix("watch:".to_owned())),
+        })
+        .await?
+        .into_inner();
+
+    let _response = client
+        .set(stream::iter(
+            [
+                ("watch:a", "value_1"),
+                ("unwatched", "value"),
+                ("watch:a", "value_2"),
+            ]
+            .map(|(key, value)| SetRequest {
+                key: key.to_owned(),
+                value: value.into(),
+                expiration: None,
+            }),
+        ))
+        .await?
+        .into_inner()
+        .collect::<Vec<_>>()
+        .await;
+    let _response = client
+        .delete(stream::iter([
+            DeleteRequest {
+                key: "watch:a".to_owned(),
+            },
+            DeleteRequest {
+                key: "watch:never_set".to_owned(),
+            },
+        ]))
+        .await?
+        .into_inner()
+        .collect::<Vec<_>>()
+        .await;
+    drop(client);
+
+    let expected = [
+        WatchResponse {
+            kind: ChangeKind::Put.into(),
+            key: "watch:a".to_owned(),
+            old_value: None,
+            new_value: Some(b"value_1".to_vec()),
+        },
+        WatchResponse {
+            kind: ChangeKind::Put.into(),
+            key: "watch:a".to_owned(),
+            old_value: Some(b"value_1".to_vec()),
+            new_value: Some(b"value_2".to_vec()),
+        },
+        WatchResponse {
+            kind: ChangeKind::Delete.into(),
+            key: "watch:a".to_owned(),
+            old_value: Some(b"value_2".to_vec()),
+            new_value: None,
+        },
+    ];
+    for expected in expected {
+        assert_eq!(changes.next().await.transpose()?, Some(expected));
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn test_increment() -> Result<()> {
+    let mut client = kv_client::<_, super::Backend>(KV_STORE_LOC.clone()).await?;
+
+    let _response = client
+        .delete(stream::iter([DeleteRequest {
+            key: "counter:a".to_owned(),
+        }]))
+        .await?
+        .into_inner()
+        .collect::<Vec<_>>()
+        .await;
+
+    let increment = |key: &str, delta| IncrementRequest {
+        key: key.to_owned(),
+        delta,
+        expiration: None,
+    };
+    let stream = client
+        .increment(stream::iter([
+            increment("counter:a", 5),
+            increment("counter:a", -7),
+        ]))
+        .await?
+        .into_inner();
+    assert_stream_eq(
+        stream,
+        [
+            IncrementResponse {
+                key: "counter:a".to_owned(),
+                value: 5,
+            },
+            IncrementResponse {
+                key: "counter:a".to_owned(),
+                value: -2,
+            },
+        ],
+    )
+    .await;
+
+    let stream = client
+        .get(stream::iter([GetRequest {
+            key: "counter:a".to_owned(),
+            allow_missing: false,
+            version: None,
+        }]))
+        .await?
+        .into_inner();
+    let version = latest_version(&mut client, "counter:a").await?;
+    assert_stream_eq(
+        stream,
+        [GetResponse {
+            value: Some(b"-2".to_vec()),
+            version,
+        }],
+    )
+    .await;
+
+    let _response = client
+        .set(stream::iter([
+            SetRequest {
+                key: "counter:text".to_owned(),
+                value: b"value_text".to_vec(),
+                expiration: None,
+            },
+            SetRequest {
+                key: "counter:max".to_owned(),
+                value: i64::MAX.to_string().into_bytes(),
+                expiration: None,
+            },
+        ]))
+        .await?
+        .into_inner()
+        .collect::<Vec<_>>()
+        .await;
+
+    for (key, code) in [
+        ("counter:text", Code::FailedPrecondition),
+        ("counter:max", Code::OutOfRange),
+    ] {
+        let status = client
+            .increment(stream::iter([increment(key, 1)]))
+            .await?
+            .into_inner()
+            .next()
+            .await
+            .expect("stream should not be empty")
+            .expect_err("increment should fail");
+        assert_eq!(status.code(), code);
+    }
+    drop(client);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[serial]
+async fn test_increment_concurrent() -> Result<()> {
+    let mut client = kv_client::<_, super::Backend>(KV_STORE_LOC.clone()).await?;
+
+    let _response = client
+        .delete(stream::iter([DeleteRequest {
+            key: "counter:concurrent".to_owned(),
+        }]))
+        .await?
+        .into_inner()
+        .collect::<Vec<_>>()
+        .await;
+
+    let tasks = (0..4).map(|_| {
+        let mut client = client.clone();
+        tokio::spawn(async move {
+            client
+                .increment(stream::iter((0..25).map(|_| IncrementRequest {
+                    key: "counter:concurrent".to_owned(),
+                    delta: 1,
+                    expiration: None,
+                })))
+                .await?
+                .into_inner()
+                .try_collect::<Vec<_>>()
+                .await
+        })
+    });
+    for task in futures::future::join_all(tasks).await {
+        let _responses = task??;
+    }
+
+    let response = client
+        .get(stream::iter([GetRequest {
+            key: "counter:concurrent".to_owned(),
+            allow_missing: false,
+            version: None,
+        }]))
+        .await?
+        .into_inner()
+        .try_collect::<Vec<_>>()
+        .await?;
+    drop(client);
+    let [GetResponse {
+        value: Some(value), ..
+    }] = &response[..]
+    else {
+        anyhow::bail!("unexpected responses {response:?}");
+    };
+    assert_eq!(value, b"100");
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn test_namespaces() -> Result<()> {
+    let mut client = kv_client::<_, super::Backend>(KV_STORE_LOC.clone()).await?;
+
+    let _res = client
+        .drop_namespace(DropNamespaceRequest {
+            name: "ns_test".to_owned(),
+        })
+        .await;
+    let _response = client
+        .create_namespace(CreateNamespaceRequest {
+            name: "ns_test".to_owned(),
+        })
+        .await?;
+    let status = client
+        .create_namespace(CreateNamespaceRequest {
+            name: "ns_test".to_owned(),
+        })
+        .await
+        .expect_err("namespace should already exist");
+    assert_eq!(status.code(), Code::AlreadyExists);
+    let status = client
+        .create_namespace(CreateNamespaceRequest {
+            name: "not valid".to_owned(),
+        })
+        .await
+        .expect_err("name should be invalid");
+    assert_eq!(status.code(), Code::InvalidArgument);
+
+    let names = client
+        .list_namespaces(ListNamespacesRequest {})
+        .await?
+        .into_inner()
+        .names;
+    assert!(names.contains(&"ns_test".to_owned()));
+
+    fn in_namespace<T>(message: T) -> tonic::Request<T> {
+        let mut request = tonic::Request::new(message);
+        let _res = request.metadata_mut().insert(
+            NAMESPACE_METADATA_KEY,
+            "ns_test".parse().expect("valid metadata"),
+        );
+        request
+    }
+
+    let _response = client
+        .delete(stream::iter([DeleteRequest {
+            key: "key_namespaced".to_owned(),
+        }]))
+        .await?
+        .into_inner()
+        .collect::<Vec<_>>()
+        .await;
+    let set = client
+        .set(in_namespace(stream::iter([SetRequest {
+            key: "key_namespaced".to_owned(),
+            value: b"value_namespaced".to_vec(),
+            expiration: None,
+        }])))
+        .await?
+        .into_inner()
+        .try_collect::<Vec<_>>()
+        .await?;
+
+    let stream = client
+        .get(in_namespace(stream::iter([GetRequest {
+            key: "key_namespaced".to_owned(),
+            allow_missing: false,
+            version: None,
+        }])))
+        .await?
+        .into_inner();
+    assert_stream_eq(
+        stream,
+        [GetResponse {
+            value: Some(b"value_namespaced".to_vec()),
+            version: set[0].version,
+        }],
+    )
+    .await;
+
+    // The default namespace is a separate keyspace.
+    let stream = client
+        .get(stream::iter([GetRequest {
+            key: "key_namespaced".to_owned(),
+            allow_missing: true,
+            version: None,
+        }]))
+        .await?
+        .into_inner();
+    assert_stream_eq(
+        stream,
+        [GetResponse {
+            value: None,
+            version: 0,
+        }],
+    )
+    .await;
+
+    let _response = client
+        .drop_namespace(DropNamespaceRequest {
+            name: "ns_test".to_owned(),
+        })
+        .await?;
+    let names = client
+        .list_namespaces(ListNamespacesRequest {})
+        .await?
+        .into_inner()
+        .names;
+    assert!(!names.contains(&"ns_test".to_owned()));
+
+    let status = match client
+        .get(in_namespace(stream::iter([GetRequest {
+            key: "key_namespaced".to_owned(),
+            allow_missing: true,
+            version: None,
+        }])))
+        .await
+    {
+        Ok(response) => response
+            .into_inner()
+            .next()
+            .await
+            .expect("stream should not be empty")
+            .expect_err("namespace should not exist"),
+        Err(status) => status,
+    };
+    assert_eq!(status.code(), Code::NotFound);
+    drop(client);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn test_history() -> Result<()> {
+    let mut client = kv_client::<_, super::Backend>(KV_STORE_LOC.clone()).await?;
+
+    let set = |value: &str| SetRequest {
+        key: "history".to_owned(),
+        value: value.into(),
+        expiration: None,
+    };
+    let versions = client
+        .set(stream::iter([set("value_a"), set("value_b")]))
+        .await?
+        .into_inner()
+        .map_ok(|response| response.version)
+        .try_collect::<Vec<_>>()
+        .await?;
+    let [first, second] = versions[..] else {
+        panic!("expected two responses, got {versions:?}");
+    };
+    assert!(first < second);
+
+    let _response = client
+        .delete(stream::iter([DeleteRequest {
+            key: "history".to_owned(),
+        }]))
+        .await?
+        .into_inner()
+        .try_collect::<Vec<_>>()
+        .await?;
+
+    // Earlier runs may have written to the same key.
+    let writes = client
+        .history(stream::iter([HistoryRequest {
+            key: "history".to_owned(),
+        }]))
+        .await?
+        .into_inner()
+        .try_filter(|write| std::future::ready(write.version >= first))
+        .try_collect::<Vec<_>>()
+        .await?;
+    assert_eq!(writes.len(), 3);
+    assert_eq!(writes[0].version, first);
+    assert_eq!(writes[0].value.as_deref(), Some(&b"value_a"[..]));
+    assert_eq!(writes[1].version, second);
+    assert_eq!(writes[1].value.as_deref(), Some(&b"value_b"[..]));
+    assert!(writes[2].version > second);
+    assert_eq!(writes[2].value, None);
+    assert!(writes.iter().all(|write| write.written_at.is_some()));
+
+    let get = |version: Option<u64>| GetRequest {
+        key: "history".to_owned(),
+        allow_missing: true,
+        version,
+    };
+    let stream = client
+        .get(stream::iter([
+            get(Some(first)),
+            get(Some(second)),
+            get(None),
+        ]))
+        .await?
+        .into_inner();
+    drop(client);
+    assert_stream_eq(
+        stream,
+        [
+            GetResponse {
+                value: Some(b"value_a".to_vec()),
+                version: first,
+            },
+            GetResponse {
+                value: Some(b"value_b".to_vec()),
+                version: second,
+            },
+            GetResponse {
+                value: None,
+                version: 0,
+            },
+        ],
+    )
+    .await;
+
+    Ok(())
+}
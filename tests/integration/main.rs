@@ -1,7 +1,12 @@
+//! Integration tests for all backends.
+
+#[cfg(feature = "sqlite")]
 mod sqlite {
     type Backend = buffdb::backend::Sqlite;
     const BLOB_PATH: &str = "blob_store.sqlite-test.db";
     const KV_PATH: &str = "kv_store.sqlite-test.db";
+    const SUPPORTS_NAMED_PARAMS: bool = true;
+    const REPORTS_NULLABILITY: bool = true;
 
     mod blob {
         include!("blob.rs");
@@ -14,10 +19,13 @@ mod sqlite {
     }
 }
 
+#[cfg(feature = "duckdb")]
 mod duckdb {
     type Backend = buffdb::backend::DuckDb;
     const BLOB_PATH: &str = "blob_store.duckdb-test.db";
     const KV_PATH: &str = "kv_store.duckdb-test.db";
+    const SUPPORTS_NAMED_PARAMS: bool = false;
+    const REPORTS_NULLABILITY: bool = false;
 
     mod blob {
         include!("blob.rs");
@@ -30,6 +38,7 @@ mod duckdb {
     }
 }
 
+#[cfg(feature = "rocksdb")]
 mod rocksdb {
     type Backend = buffdb::backend::RocksDb;
     const BLOB_PATH: &str = "blob_store.rocksdb-test.db";
@@ -41,6 +50,9 @@ mod rocksdb {
     mod kv {
         include!("kv.rs");
     }
+    mod query {
+        include!("rocksdb_query.rs");
+    }
 }
 
 mod helpers;
@@ -1,12 +1,20 @@
-use super::{Backend, BLOB_PATH, KV_PATH};
-use anyhow::Result;
-use buffdb::proto::blob::StoreRequest;
-use buffdb::proto::kv::SetRequest;
+use super::{Backend, BLOB_PATH, KV_PATH, REPORTS_NULLABILITY, SUPPORTS_NAMED_PARAMS};
+use anyhow::{bail, Result};
+use buffdb::prost_types::value::Kind;
+use buffdb::prost_types::Value;
+use buffdb::proto::blob::{
+    Compression, DeleteRequest, GetRequest as BlobGetRequest, StoreRequest, StoreResponse,
+    VerifyRequest,
+};
+use buffdb::proto::kv::set_request::Expiration;
+use buffdb::proto::kv::{GetRequest, ScanRequest, SetRequest};
 use buffdb::proto::query::{QueryResult, RawQuery, RowsChanged, TargetStore};
 use buffdb::transitive::{blob_client, kv_client, query_client};
 use buffdb::Location;
-use futures::{stream, StreamExt as _};
+use futures::{stream, StreamExt as _, TryStreamExt as _};
+use prost::Message as _;
 use serial_test::serial;
+use std::collections::HashMap;
 use std::sync::LazyLock;
 
 static KV_STORE_LOC: LazyLock<Location> = LazyLock::new(|| Location::OnDisk {
@@ -25,7 +33,8 @@ async fn test_kv_query() -> Result<()> {
     let _response = kv_client
         .set(stream::iter([SetRequest {
             key: "key_raw_query".to_owned(),
-            value: "value_raw_query".to_owned(),
+            value: b"value_raw_query".to_vec(),
+            expiration: None,
         }]))
         .await;
 
@@ -33,15 +42,21 @@ async fn test_kv_query() -> Result<()> {
         .query(stream::iter([RawQuery {
             query: "SELECT COUNT(*) FROM kv".to_owned(),
             target: TargetStore::Kv as i32,
+            params: Vec::new(),
+            named_params: HashMap::new(),
         }]))
         .await?
         .into_inner();
     drop((kv_client, query_client));
 
-    let QueryResult { fields } = response
+    let header = response.next().await.expect("a header should be present")?;
+    assert_eq!(header.columns.len(), 1);
+    assert!(header.fields.is_empty());
+    let QueryResult { fields, columns } = response
         .next()
         .await
         .expect("one result should be present")?;
+    assert!(columns.is_empty());
     assert_eq!(fields.len(), 1);
 
     assert!(response.next().await.is_none());
@@ -58,6 +73,8 @@ async fn test_kv_execute() -> Result<()> {
         .execute(stream::iter([RawQuery {
             query: "CREATE TABLE IF NOT EXISTS test_kv_execute_table (value TEXT);".to_owned(),
             target: TargetStore::Kv as i32,
+            params: Vec::new(),
+            named_params: HashMap::new(),
         }]))
         .await?;
     drop(query_client);
@@ -83,6 +100,10 @@ async fn test_blob_query() -> Result<()> {
         .store(stream::iter([StoreRequest {
             bytes: b"abcdef".to_vec(),
             metadata: None,
+            attributes: None,
+            compression: Compression::None.into(),
+            id: None,
+            idempotency_key: None,
         }]))
         .await?
         .into_inner()
@@ -93,15 +114,21 @@ async fn test_blob_query() -> Result<()> {
         .query(stream::iter([RawQuery {
             query: "SELECT COUNT(*) FROM blob".to_owned(),
             target: TargetStore::Blob as i32,
+            params: Vec::new(),
+            named_params: HashMap::new(),
         }]))
         .await?
         .into_inner();
     drop((client, query_client));
 
-    let QueryResult { fields } = response
+    let header = response.next().await.expect("a header should be present")?;
+    assert_eq!(header.columns.len(), 1);
+    assert!(header.fields.is_empty());
+    let QueryResult { fields, columns } = response
         .next()
         .await
         .expect("one result should be present")?;
+    assert!(columns.is_empty());
     assert_eq!(fields.len(), 1);
 
     assert!(response.next().await.is_none());
@@ -109,6 +136,347 @@ async fn test_blob_query() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+#[serial]
+async fn test_blob_unchunked_content() -> Result<()> {
+    let mut client = blob_client::<_, Backend>(BLOB_STORE_LOC.clone()).await?;
+    let mut query_client = query_client::<_, _, Backend>(KV_PATH, BLOB_PATH).await?;
+    let hex = |bytes: &[u8]| bytes.iter().map(|b| format!("{b:02x}")).collect::<String>();
+
+    let data = b"stored before chunking";
+    let stored = client
+        .store(stream::iter([StoreRequest {
+            bytes: data.to_vec(),
+            metadata: None,
+            attributes: None,
+            compression: Compression::None.into(),
+            id: None,
+            idempotency_key: None,
+        }]))
+        .await?
+        .into_inner()
+        .collect::<Vec<_>>()
+        .await;
+    let [Ok(StoreResponse { id, ref digest, .. })] = stored[..] else {
+        bail!("unexpected responses {stored:?}");
+    };
+
+    // Content stored before it was split into chunks is held in full by the content table.
+    let response = query_client
+        .execute(stream::iter(
+            [
+                format!(
+                    "UPDATE blob_content SET data = unhex('{}') WHERE digest = unhex('{}')",
+                    hex(data),
+                    hex(digest),
+                ),
+                format!(
+                    "DELETE FROM blob_content_chunk WHERE digest = unhex('{}')",
+                    hex(digest),
+                ),
+            ]
+            .map(|query| RawQuery {
+                query,
+                target: TargetStore::Blob as i32,
+                params: Vec::new(),
+                named_params: HashMap::new(),
+            }),
+        ))
+        .await?
+        .into_inner()
+        .collect::<Vec<_>>()
+        .await;
+    assert!(matches!(
+        &response[..],
+        [
+            Ok(RowsChanged { rows_changed: 1 }),
+            Ok(RowsChanged { rows_changed: 1 })
+        ]
+    ));
+
+    let range = |offset, length| BlobGetRequest { id, offset, length };
+    let response = client
+        .get(stream::iter([
+            range(Some(7), Some(6)),
+            range(Some(14), None),
+            range(None, None),
+        ]))
+        .await?
+        .into_inner()
+        .map_ok(|response| response.bytes)
+        .collect::<Vec<_>>()
+        .await;
+    let _response = client
+        .delete(stream::iter([DeleteRequest { id }]))
+        .await?
+        .into_inner()
+        .collect::<Vec<_>>()
+        .await;
+    drop((client, query_client));
+    assert_eq!(
+        response.into_iter().collect::<Result<Vec<_>, _>>()?,
+        [b"before".to_vec(), b"chunking".to_vec(), data.to_vec()]
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn test_blob_wrong_size() -> Result<()> {
+    let mut client = blob_client::<_, Backend>(BLOB_STORE_LOC.clone()).await?;
+    let mut query_client = query_client::<_, _, Backend>(KV_PATH, BLOB_PATH).await?;
+    let hex = |bytes: &[u8]| bytes.iter().map(|b| format!("{b:02x}")).collect::<String>();
+
+    let stored = client
+        .store(stream::iter([StoreRequest {
+            bytes: b"compressed ".repeat(10),
+            metadata: None,
+            attributes: None,
+            compression: Compression::Zstd.into(),
+            id: None,
+            idempotency_key: None,
+        }]))
+        .await?
+        .into_inner()
+        .collect::<Vec<_>>()
+        .await;
+    let [Ok(StoreResponse { id, ref digest, .. })] = stored[..] else {
+        bail!("unexpected responses {stored:?}");
+    };
+
+    // The recorded size no longer matches the data once decompressed.
+    let response = query_client
+        .execute(stream::iter([RawQuery {
+            query: format!(
+                "UPDATE blob_content SET size = size + 100 WHERE digest = unhex('{}')",
+                hex(digest),
+            ),
+            target: TargetStore::Blob as i32,
+            params: Vec::new(),
+            named_params: HashMap::new(),
+        }]))
+        .await?
+        .into_inner()
+        .collect::<Vec<_>>()
+        .await;
+    assert!(matches!(
+        &response[..],
+        [Ok(RowsChanged { rows_changed: 1 })]
+    ));
+
+    let mut codes = Vec::new();
+    for (offset, length) in [(Some(100), None), (None, None)] {
+        let response = client
+            .get(stream::iter([BlobGetRequest { id, offset, length }]))
+            .await?
+            .into_inner()
+            .try_collect::<Vec<_>>()
+            .await;
+        codes.push(response.map_err(|status| status.code()).err());
+    }
+    let _response = client
+        .delete(stream::iter([DeleteRequest { id }]))
+        .await?
+        .into_inner()
+        .collect::<Vec<_>>()
+        .await;
+    drop((client, query_client));
+    assert_eq!(codes, [Some(tonic::Code::DataLoss); 2]);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn test_blob_missing_chunk() -> Result<()> {
+    let mut client = blob_client::<_, Backend>(BLOB_STORE_LOC.clone()).await?;
+    let mut query_client = query_client::<_, _, Backend>(KV_PATH, BLOB_PATH).await?;
+    let hex = |bytes: &[u8]| bytes.iter().map(|b| format!("{b:02x}")).collect::<String>();
+
+    // Two BLOBs share the content that is damaged, while a third is left intact.
+    let store = |bytes: &[u8]| StoreRequest {
+        bytes: bytes.to_vec(),
+        metadata: None,
+        attributes: None,
+        compression: Compression::None.into(),
+        id: None,
+        idempotency_key: None,
+    };
+    let stored = client
+        .store(stream::iter([
+            store(b"damaged"),
+            store(b"damaged"),
+            store(b"intact"),
+        ]))
+        .await?
+        .into_inner()
+        .try_collect::<Vec<_>>()
+        .await?;
+    let [StoreResponse {
+        id: damaged_id,
+        ref digest,
+        ..
+    }, StoreResponse { id: shared_id, .. }, StoreResponse { id: intact_id, .. }] = stored[..]
+    else {
+        bail!("unexpected responses {stored:?}");
+    };
+
+    let response = query_client
+        .execute(stream::iter([RawQuery {
+            query: format!(
+                "DELETE FROM blob_content_chunk WHERE digest = unhex('{}')",
+                hex(digest),
+            ),
+            target: TargetStore::Blob as i32,
+            params: Vec::new(),
+            named_params: HashMap::new(),
+        }]))
+        .await?
+        .into_inner()
+        .collect::<Vec<_>>()
+        .await;
+    assert!(matches!(
+        &response[..],
+        [Ok(RowsChanged { rows_changed: 1 })]
+    ));
+
+    let corrupted = client
+        .verify(VerifyRequest {})
+        .await?
+        .into_inner()
+        .map_ok(|response| response.id)
+        .try_collect::<Vec<_>>()
+        .await?;
+    let _response = client
+        .delete(stream::iter(
+            [damaged_id, shared_id, intact_id].map(|id| DeleteRequest { id }),
+        ))
+        .await?
+        .into_inner()
+        .collect::<Vec<_>>()
+        .await;
+    drop((client, query_client));
+    assert!(corrupted.contains(&damaged_id) && corrupted.contains(&shared_id));
+    assert!(!corrupted.contains(&intact_id));
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn test_cross_store_query() -> Result<()> {
+    let mut kv_client = kv_client::<_, Backend>(KV_STORE_LOC.clone()).await?;
+    let mut blob_client = blob_client::<_, Backend>(BLOB_STORE_LOC.clone()).await?;
+    let mut query_client = query_client::<_, _, Backend>(KV_PATH, BLOB_PATH).await?;
+
+    let stored = blob_client
+        .store(stream::iter([StoreRequest {
+            bytes: b"cross-store data".to_vec(),
+            metadata: Some("cross-store metadata".to_owned()),
+            attributes: None,
+            compression: Compression::None.into(),
+            id: None,
+            idempotency_key: None,
+        }]))
+        .await?
+        .into_inner()
+        .collect::<Vec<_>>()
+        .await;
+    let [Ok(StoreResponse { digest, .. })] = &stored[..] else {
+        bail!("unexpected responses {stored:?}");
+    };
+    let _response = kv_client
+        .set(stream::iter([SetRequest {
+            key: "key_cross_store".to_owned(),
+            value: digest.clone(),
+            expiration: None,
+        }]))
+        .await?;
+
+    let response = query_client
+        .query(stream::iter([RawQuery {
+            query: "SELECT b.metadata FROM kv JOIN blob.blob AS b ON kv.value = b.digest
+                WHERE kv.key = ?"
+                .to_owned(),
+            target: TargetStore::Both as i32,
+            params: vec![Value {
+                kind: Some(Kind::StringValue("key_cross_store".to_owned())),
+            }],
+            named_params: HashMap::new(),
+        }]))
+        .await?
+        .into_inner()
+        .collect::<Vec<_>>()
+        .await;
+    drop((kv_client, blob_client, query_client));
+
+    // BLOBs stored with the same data by earlier runs share the digest.
+    let [Ok(_header), rows @ ..] = &response[..] else {
+        bail!("unexpected responses {response:?}");
+    };
+    assert!(!rows.is_empty());
+    for row in rows {
+        let Ok(QueryResult { fields, .. }) = row else {
+            bail!("unexpected response {row:?}");
+        };
+        let [metadata] = &fields[..] else {
+            bail!("unexpected fields {fields:?}");
+        };
+        assert_eq!(String::decode(&*metadata.value)?, "cross-store metadata");
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn test_cross_store_in_transaction() -> Result<()> {
+    let mut query_client = query_client::<_, _, Backend>(KV_PATH, BLOB_PATH).await?;
+
+    let raw_query = |query: &str, target: TargetStore| RawQuery {
+        query: query.to_owned(),
+        target: target as i32,
+        params: Vec::new(),
+        named_params: HashMap::new(),
+    };
+    let create_table = "CREATE TABLE IF NOT EXISTS test_cross_store_in_transaction (key TEXT)";
+    // The BLOB store is attached within the transaction, which bundled SQLite and DuckDB allow.
+    let response = query_client
+        .execute(stream::iter([
+            raw_query(create_table, TargetStore::Kv),
+            raw_query(create_table, TargetStore::Blob),
+            raw_query("BEGIN TRANSACTION", TargetStore::Kv),
+            raw_query(
+                "DELETE FROM test_cross_store_in_transaction
+                    WHERE key IN (SELECT key FROM blob.test_cross_store_in_transaction)",
+                TargetStore::Both,
+            ),
+            raw_query("COMMIT", TargetStore::Kv),
+        ]))
+        .await?
+        .into_inner()
+        .collect::<Vec<_>>()
+        .await;
+    drop(query_client);
+
+    assert!(
+        matches!(
+            response[..],
+            [
+                Ok(_),
+                Ok(_),
+                Ok(_),
+                Ok(RowsChanged { rows_changed: 0 }),
+                Ok(_)
+            ]
+        ),
+        "unexpected responses {response:?}"
+    );
+
+    Ok(())
+}
+
 #[tokio::test]
 #[serial]
 async fn test_blob_execute() -> Result<()> {
@@ -118,6 +486,8 @@ async fn test_blob_execute() -> Result<()> {
         .execute(stream::iter([RawQuery {
             query: "CREATE TABLE IF NOT EXISTS test_blob_execute_table (value TEXT);".to_owned(),
             target: TargetStore::Blob as i32,
+            params: Vec::new(),
+            named_params: HashMap::new(),
         }]))
         .await?;
     drop(query_client);
@@ -131,3 +501,358 @@ async fn test_blob_execute() -> Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+#[serial]
+async fn test_kv_query_params() -> Result<()> {
+    let mut query_client = query_client::<_, _, Backend>(KV_PATH, BLOB_PATH).await?;
+    let string = |value: &str| Value {
+        kind: Some(Kind::StringValue(value.to_owned())),
+    };
+    let number = |value: f64| Value {
+        kind: Some(Kind::NumberValue(value)),
+    };
+    let raw_query = |query: &str, params: Vec<Value>| RawQuery {
+        query: query.to_owned(),
+        target: TargetStore::Kv as i32,
+        params,
+        named_params: HashMap::new(),
+    };
+
+    // The value is bound rather than interpolated, so it cannot alter the query.
+    let value = "'); DROP TABLE kv; --";
+    let response = query_client
+        .execute(stream::iter([
+            raw_query(
+                "CREATE TABLE IF NOT EXISTS test_kv_query_params (value TEXT, n INTEGER NOT NULL)",
+                Vec::new(),
+            ),
+            raw_query("DELETE FROM test_kv_query_params", Vec::new()),
+            raw_query(
+                "INSERT INTO test_kv_query_params (value, n) VALUES (?, ?)",
+                vec![string(value), number(3.)],
+            ),
+        ]))
+        .await?
+        .into_inner()
+        .collect::<Vec<_>>()
+        .await;
+    assert!(matches!(
+        response[..],
+        [Ok(_), Ok(_), Ok(RowsChanged { rows_changed: 1 })]
+    ));
+
+    let response = query_client
+        .query(stream::iter([raw_query(
+            "SELECT n, value FROM test_kv_query_params WHERE value = ?",
+            vec![string(value)],
+        )]))
+        .await?
+        .into_inner()
+        .collect::<Vec<_>>()
+        .await;
+    let [Ok(header), Ok(QueryResult { fields, .. })] = &response[..] else {
+        bail!("unexpected responses {response:?}");
+    };
+    let names = header
+        .columns
+        .iter()
+        .map(|column| column.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["n", "value"]);
+    assert_eq!(header.columns[0].declared_type.as_deref(), Some("INTEGER"));
+    let nullable = header
+        .columns
+        .iter()
+        .map(|column| column.nullable)
+        .collect::<Vec<_>>();
+    if REPORTS_NULLABILITY {
+        assert_eq!(nullable, [Some(false), Some(true)]);
+    } else {
+        assert_eq!(nullable, [None, None]);
+    }
+    let [n, stored_value] = &fields[..] else {
+        bail!("unexpected fields {fields:?}");
+    };
+    assert_eq!(i64::decode(&*n.value)?, 3);
+    assert_eq!(
+        stored_value.type_url,
+        "type.googleapis.com/google.protobuf.StringValue"
+    );
+    assert_eq!(String::decode(&*stored_value.value)?, value);
+
+    // Every parameter must have a value.
+    let response = query_client
+        .query(stream::iter([raw_query(
+            "SELECT n FROM test_kv_query_params WHERE value = ? AND n = ?",
+            vec![string(value)],
+        )]))
+        .await?
+        .into_inner()
+        .collect::<Vec<_>>()
+        .await;
+    drop(query_client);
+    assert!(matches!(
+        &response[..],
+        [Err(status)] if status.code() == tonic::Code::InvalidArgument
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn test_kv_query_named_params() -> Result<()> {
+    let mut query_client = query_client::<_, _, Backend>(KV_PATH, BLOB_PATH).await?;
+    let number = |value: f64| Value {
+        kind: Some(Kind::NumberValue(value)),
+    };
+    let raw_query = |query: &str, params: Vec<Value>, named: &[(&str, f64)]| RawQuery {
+        query: query.to_owned(),
+        target: TargetStore::Kv as i32,
+        params,
+        named_params: named
+            .iter()
+            .map(|&(name, value)| (name.to_owned(), number(value)))
+            .collect(),
+    };
+
+    let mut responses = Vec::new();
+    for query in [
+        // Each value is bound to the parameter with its name, wherever it appears.
+        raw_query(
+            "SELECT :second - :first",
+            Vec::new(),
+            &[(":first", 1.), (":second", 3.)],
+        ),
+        // Positional parameters are never bound to the index of a named parameter.
+        raw_query("SELECT :first - ?", vec![number(3.)], &[(":first", 1.)]),
+        raw_query("SELECT :second - :first", vec![number(3.), number(1.)], &[]),
+    ] {
+        let response = query_client
+            .query(stream::iter([query]))
+            .await?
+            .into_inner()
+            .collect::<Vec<_>>()
+            .await;
+        responses.push(response);
+    }
+    drop(query_client);
+
+    let codes = responses
+        .iter()
+        .map(|response| match &response[..] {
+            [Err(status)] => Some(status.code()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    if SUPPORTS_NAMED_PARAMS {
+        let [Ok(_), Ok(QueryResult { fields, .. })] = &responses[0][..] else {
+            bail!("unexpected responses {:?}", responses[0]);
+        };
+        let [difference] = &fields[..] else {
+            bail!("unexpected fields {fields:?}");
+        };
+        assert_eq!(i64::decode(&*difference.value)?, 2);
+        assert_eq!(codes[1..], [Some(tonic::Code::InvalidArgument); 2]);
+    } else {
+        assert!(codes.iter().all(Option::is_some));
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn test_kv_transaction() -> Result<()> {
+    let mut query_client = query_client::<_, _, Backend>(KV_PATH, BLOB_PATH).await?;
+    let raw_query = |query: &str| RawQuery {
+        query: query.to_owned(),
+        target: TargetStore::Kv as i32,
+        params: Vec::new(),
+        named_params: HashMap::new(),
+    };
+
+    // The stream ends before the transaction is committed.
+    let response = query_client
+        .execute(stream::iter([
+            raw_query("CREATE TABLE IF NOT EXISTS test_kv_transaction (value TEXT)"),
+            raw_query("DELETE FROM test_kv_transaction"),
+            raw_query("BEGIN TRANSACTION"),
+            raw_query("INSERT INTO test_kv_transaction (value) VALUES ('ended')"),
+        ]))
+        .await?
+        .into_inner()
+        .collect::<Vec<_>>()
+        .await;
+    assert!(response.iter().all(Result::is_ok));
+
+    // The stream fails before the transaction is committed.
+    let response = query_client
+        .execute(stream::iter([
+            raw_query("BEGIN TRANSACTION"),
+            raw_query("INSERT INTO test_kv_transaction (value) VALUES ('failed')"),
+            RawQuery {
+                target: -1,
+                ..raw_query("COMMIT")
+            },
+        ]))
+        .await?
+        .into_inner()
+        .collect::<Vec<_>>()
+        .await;
+    assert!(matches!(&response[..], [Ok(_), Ok(_), Err(_)]));
+
+    let response = query_client
+        .execute(stream::iter([
+            raw_query("BEGIN TRANSACTION"),
+            raw_query("INSERT INTO test_kv_transaction (value) VALUES ('committed')"),
+            raw_query("COMMIT"),
+        ]))
+        .await?
+        .into_inner()
+        .collect::<Vec<_>>()
+        .await;
+    assert!(response.iter().all(Result::is_ok));
+
+    let response = query_client
+        .query(stream::iter([raw_query(
+            "SELECT value FROM test_kv_transaction",
+        )]))
+        .await?
+        .into_inner()
+        .collect::<Vec<_>>()
+        .await;
+    drop(query_client);
+    let [Ok(_header), Ok(QueryResult { fields, .. })] = &response[..] else {
+        bail!("unexpected responses {response:?}");
+    };
+    let [value] = &fields[..] else {
+        bail!("unexpected fields {fields:?}");
+    };
+    assert_eq!(String::decode(&*value.value)?, "committed");
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn test_kv_sweep() -> Result<()> {
+    let mut client = kv_client::<_, Backend>(KV_STORE_LOC.clone()).await?;
+    let mut query_client = query_client::<_, _, Backend>(KV_PATH, BLOB_PATH).await?;
+
+    let _response = client
+        .set(stream::iter([SetRequest {
+            key: "key_swept".to_owned(),
+            value: b"value_swept".to_vec(),
+            expiration: Some(Expiration::ExpiresAt(prost_types::Timestamp {
+                seconds: 1,
+                nanos: 0,
+            })),
+        }]))
+        .await?
+        .into_inner()
+        .collect::<Vec<_>>()
+        .await;
+    drop(client);
+
+    // A new store begins removing expired pairs in the background once a connection is made.
+    let mut client = kv_client::<_, Backend>(KV_STORE_LOC.clone()).await?;
+    let _response = client
+        .get(stream::iter([GetRequest {
+            key: "key_swept".to_owned(),
+            allow_missing: true,
+            version: None,
+        }]))
+        .await?
+        .into_inner()
+        .collect::<Vec<_>>()
+        .await;
+
+    let mut count = None;
+    for _ in 0..100 {
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        let response = query_client
+            .query(stream::iter([RawQuery {
+                query: "SELECT COUNT(*) FROM kv WHERE key = 'key_swept'".to_owned(),
+                target: TargetStore::Kv as i32,
+                params: Vec::new(),
+                named_params: HashMap::new(),
+            }]))
+            .await?
+            .into_inner()
+            .collect::<Vec<_>>()
+            .await;
+        let [Ok(_header), Ok(QueryResult { fields, .. })] = &response[..] else {
+            bail!("unexpected responses {response:?}");
+        };
+        let [value] = &fields[..] else {
+            bail!("unexpected fields {fields:?}");
+        };
+        count = Some(i64::decode(&*value.value)?);
+        if count == Some(0) {
+            break;
+        }
+    }
+    drop((client, query_client));
+    assert_eq!(count, Some(0));
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn test_kv_scan_invalid_utf8() -> Result<()> {
+    let mut client = kv_client::<_, Backend>(KV_STORE_LOC.clone()).await?;
+    let mut query_client = query_client::<_, _, Backend>(KV_PATH, BLOB_PATH).await?;
+    let raw_query = |query: &str| RawQuery {
+        query: query.to_owned(),
+        target: TargetStore::Kv as i32,
+        params: Vec::new(),
+        named_params: HashMap::new(),
+    };
+
+    let response = query_client
+        .execute(stream::iter([raw_query(
+            "INSERT INTO kv (key, value) VALUES ('invalid_utf8:' || CAST(unhex('ff') AS TEXT), unhex('00'))",
+        )]))
+        .await?
+        .into_inner()
+        .collect::<Vec<_>>()
+        .await;
+    assert!(matches!(
+        &response[..],
+        [Ok(RowsChanged { rows_changed: 1 })]
+    ));
+
+    let response = client
+        .scan(stream::iter([ScanRequest {
+            prefix: Some("invalid_utf8:".to_owned()),
+            start: None,
+            end: None,
+            limit: None,
+            reverse: false,
+        }]))
+        .await?
+        .into_inner()
+        .collect::<Vec<_>>()
+        .await;
+    let _response = query_client
+        .execute(stream::iter([raw_query(
+            "DELETE FROM kv WHERE key = 'invalid_utf8:' || CAST(unhex('ff') AS TEXT)",
+        )]))
+        .await?
+        .into_inner()
+        .collect::<Vec<_>>()
+        .await;
+    drop((client, query_client));
+
+    // DuckDB escapes invalid UTF-8 when casting to text, so only SQLite stores an invalid key.
+    assert!(match &response[..] {
+        [Err(status)] => status.code() == tonic::Code::DataLoss,
+        [Ok(pair)] => pair.key == "invalid_utf8:\\xFF",
+        _ => false,
+    });
+
+    Ok(())
+}
@@ -0,0 +1,175 @@
+use super::{Backend, BLOB_PATH, KV_PATH};
+use anyhow::{bail, Result};
+use buffdb::prost_types::value::Kind;
+use buffdb::prost_types::Value;
+use buffdb::proto::kv::SetRequest;
+use buffdb::proto::query::{QueryResult, RawQuery, TargetStore};
+use buffdb::transitive::{kv_client, query_client};
+use buffdb::Location;
+use futures::{stream, StreamExt as _};
+use prost::Message as _;
+use serial_test::serial;
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+static KV_STORE_LOC: LazyLock<Location> = LazyLock::new(|| Location::OnDisk {
+    path: KV_PATH.into(),
+});
+
+fn raw_query(query: &str, params: Vec<Value>) -> RawQuery {
+    RawQuery {
+        query: query.to_owned(),
+        target: TargetStore::Kv as i32,
+        params,
+        named_params: HashMap::new(),
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn test_kv_scan() -> Result<()> {
+    let mut kv_client = kv_client::<_, Backend>(KV_STORE_LOC.clone()).await?;
+    let mut query_client = query_client::<_, _, Backend>(KV_PATH, BLOB_PATH).await?;
+
+    let _response = kv_client
+        .set(stream::iter(["a", "b", "c"].map(|suffix| SetRequest {
+            key: format!("key_scan_{suffix}"),
+            value: suffix.as_bytes().to_vec(),
+            expiration: None,
+        })))
+        .await?;
+
+    let prefix = Value {
+        kind: Some(Kind::StringValue("key_scan_".to_owned())),
+    };
+    let response = query_client
+        .query(stream::iter([raw_query(
+            "SCAN default PREFIX ? FROM 'key_scan_b'",
+            vec![prefix],
+        )]))
+        .await?
+        .into_inner()
+        .collect::<Vec<_>>()
+        .await;
+    drop((kv_client, query_client));
+
+    let [Ok(header), rows @ ..] = &response[..] else {
+        bail!("unexpected responses {response:?}");
+    };
+    let names = header
+        .columns
+        .iter()
+        .map(|column| column.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["key", "value"]);
+
+    let mut keys = Vec::new();
+    for row in rows {
+        let Ok(QueryResult { fields, .. }) = row else {
+            bail!("unexpected response {row:?}");
+        };
+        let [key, value] = &fields[..] else {
+            bail!("unexpected fields {fields:?}");
+        };
+        assert_eq!(
+            key.type_url,
+            "type.googleapis.com/google.protobuf.BytesValue"
+        );
+        let key = String::from_utf8(Vec::<u8>::decode(&*key.value)?)?;
+        let value = Vec::<u8>::decode(&*value.value)?;
+        assert!(key.ends_with(std::str::from_utf8(&value)?));
+        keys.push(key);
+    }
+    assert_eq!(keys, ["key_scan_b", "key_scan_c"]);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn test_kv_query_errors() -> Result<()> {
+    let mut query_client = query_client::<_, _, Backend>(KV_PATH, BLOB_PATH).await?;
+
+    let response = query_client
+        .query(stream::iter([raw_query("SELECT * FROM kv", Vec::new())]))
+        .await?
+        .into_inner()
+        .collect::<Vec<_>>()
+        .await;
+    assert!(matches!(
+        &response[..],
+        [Err(status)] if status.code() == tonic::Code::InvalidArgument
+    ));
+
+    let response = query_client
+        .query(stream::iter([raw_query(
+            "COUNT no_such_family",
+            Vec::new(),
+        )]))
+        .await?
+        .into_inner()
+        .collect::<Vec<_>>()
+        .await;
+    assert!(matches!(
+        &response[..],
+        [Err(status)] if status.code() == tonic::Code::NotFound
+    ));
+
+    // Column families used internally by the store cannot be queried.
+    let response = query_client
+        .query(stream::iter([raw_query("SCAN kv_version", Vec::new())]))
+        .await?
+        .into_inner()
+        .collect::<Vec<_>>()
+        .await;
+    assert!(matches!(
+        &response[..],
+        [Err(status)] if status.code() == tonic::Code::InvalidArgument
+    ));
+
+    let response = query_client
+        .query(stream::iter([raw_query("FAMILIES", Vec::new())]))
+        .await?
+        .into_inner()
+        .collect::<Vec<_>>()
+        .await;
+    let families = response
+        .iter()
+        .skip(1)
+        .map(|row| match row {
+            Ok(QueryResult { fields, .. }) => Ok(String::decode(&*fields[0].value)?),
+            Err(status) => bail!("unexpected error {status:?}"),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    assert!(families.iter().any(|family| family == "default"));
+    assert!(!families.iter().any(|family| family == "kv_version"));
+
+    // Scans are bounded.
+    let response = query_client
+        .query(stream::iter([raw_query(
+            "SCAN default LIMIT 1001",
+            Vec::new(),
+        )]))
+        .await?
+        .into_inner()
+        .collect::<Vec<_>>()
+        .await;
+    assert!(matches!(
+        &response[..],
+        [Err(status)] if status.code() == tonic::Code::InvalidArgument
+    ));
+
+    // Queries are read-only.
+    let response = query_client
+        .execute(stream::iter([raw_query("FAMILIES", Vec::new())]))
+        .await?
+        .into_inner()
+        .collect::<Vec<_>>()
+        .await;
+    assert!(matches!(
+        &response[..],
+        [Err(status)] if status.code() == tonic::Code::Unimplemented
+    ));
+
+    Ok(())
+}